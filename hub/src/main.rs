@@ -1,6 +1,8 @@
 #[macro_use]
 extern crate log;
 extern crate chrono;
+#[macro_use]
+extern crate failure;
 extern crate fern;
 #[macro_use]
 extern crate trustnote;
@@ -89,6 +91,42 @@ fn run_hub_server() -> Result<()> {
     Ok(())
 }
 
+// export and import the stable joints as json lines, used to seed a new
+// node from a file instead of the network catchup
+fn run_command(args: &[String]) -> Result<()> {
+    use std::fs::File;
+    use std::io::{BufReader, BufWriter};
+
+    const USAGE: &str = "usage: trustnote_hub [export FILE [FROM_MCI [TO_MCI]] | import FILE]";
+    match args[0].as_str() {
+        "export" if args.len() >= 2 && args.len() <= 4 => {
+            let db = db::DB_POOL.get_connection();
+            let from_mci = match args.get(2) {
+                Some(mci) => mci.parse()?,
+                None => 0,
+            };
+            let to_mci = match args.get(3) {
+                Some(mci) => mci.parse()?,
+                None => storage::read_last_stable_mc_index(&db)?,
+            };
+            let mut writer = BufWriter::new(File::create(&args[1])?);
+            let count = storage::export_joints(&db, from_mci, to_mci, &mut writer)?;
+            println!(
+                "exported {} joints of mci {} to {} into {}",
+                count, from_mci, to_mci, args[1]
+            );
+        }
+        "import" if args.len() == 2 => {
+            let mut db = db::DB_POOL.get_connection();
+            let reader = BufReader::new(File::open(&args[1])?);
+            let count = catchup::import_joints(&mut db, reader)?;
+            println!("imported {} joints from {}", count, args[1]);
+        }
+        _ => bail!("{}", USAGE),
+    }
+    Ok(())
+}
+
 #[allow(dead_code)]
 fn test_read_joint() -> Result<()> {
     fn pause() {
@@ -127,6 +165,17 @@ fn main() -> Result<()> {
     // uncomment it to test read joint from db
     // test_read_joint()?;
 
+    // run the export or import command and exit without starting the hub
+    // server, other arguments don't stop the hub
+    let args = ::std::env::args().skip(1).collect::<Vec<_>>();
+    match args.first().map(|s| s.as_str()) {
+        Some("export") | Some("import") => {
+            return go!(move || run_command(&args)).join().unwrap();
+        }
+        Some(_) => warn!("ignored arguments {:?}", args),
+        None => {}
+    }
+
     go!(|| run_hub_server().unwrap()).join().unwrap();
 
     // wait user input a ctrl_c to exit
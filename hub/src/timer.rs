@@ -4,6 +4,7 @@ use may::coroutine;
 use trustnote::db;
 use trustnote::joint_storage;
 use trustnote::network::hub;
+use trustnote::private_payment;
 
 pub fn start_global_timers() {
     // find and handle ready joints
@@ -37,6 +38,14 @@ pub fn start_global_timers() {
         t!(joint_storage::purge_uncovered_nonserial_joints_under_lock());
     });
 
+    // drop the private payments that their payees never fetched
+    go!(move || loop {
+        coroutine::sleep(Duration::from_secs(3600));
+        let db = db::DB_POOL.get_connection();
+        info!("purge_expired_private_chains");
+        t!(private_payment::purge_expired_private_chains(&db));
+    });
+
     // auto connection if peers count is under threshold
     go!(move || loop {
         coroutine::sleep(Duration::from_secs(30));
@@ -1,9 +1,14 @@
+use std::io::{BufRead, Seek, SeekFrom};
+
 use error::Result;
 use joint::Joint;
+use joint_storage;
 use may::sync::Mutex;
 use rusqlite::Connection;
+use serde_json;
 use spec;
 use storage;
+use validation;
 use witness_proof;
 
 lazy_static! {
@@ -361,8 +366,6 @@ pub fn read_hash_tree(db: &Connection, hash_tree_req: HashTreeReq) -> Result<Vec
 
 // this function take a new db connection
 pub fn process_hash_tree(db: &mut Connection, balls: Vec<BallProps>) -> Result<()> {
-    use object_hash;
-
     if balls.is_empty() {
         return Ok(());
     }
@@ -370,8 +373,190 @@ pub fn process_hash_tree(db: &mut Connection, balls: Vec<BallProps>) -> Result<(
     let _g = HASHTREE_MUTEX.lock().unwrap();
     let tx = db.transaction()?;
 
-    let mut max_mci = 0;
     let last_ball = balls.last().as_ref().unwrap().ball.clone().unwrap();
+    add_balls_to_hash_tree(&tx, &balls)?;
+
+    {
+        let mut stmt = tx.prepare_cached(
+            "SELECT ball, main_chain_index \
+             FROM catchup_chain_balls LEFT JOIN balls USING(ball) LEFT JOIN units USING(unit) \
+             ORDER BY member_index LIMIT 2",
+        )?;
+
+        let rows_data: Vec<String> = stmt
+            .query_map(&[], |row| row.get(0))?
+            .collect::<::std::result::Result<Vec<_>, _>>()?;
+
+        if rows_data.len() != 2 {
+            bail!("expecting to have 2 elements in the chain");
+        }
+        if rows_data[1] != last_ball {
+            bail!("tree root doesn't match second chain element");
+        }
+
+        let mut stmt = tx.prepare_cached("DELETE FROM catchup_chain_balls WHERE ball=?")?;
+        stmt.execute(&[&rows_data[0]])?;
+        purge_handled_balls_from_hash_tree(&tx)?;
+    }
+
+    tx.commit()?;
+
+    Ok(())
+}
+
+/// replay the joints exported by `storage::export_joints`, the balls are
+/// added to the hash tree as in catchup and then the joints are validated
+/// and saved in the file order, returns the count of the new joints
+pub fn import_joints<R: BufRead + Seek>(db: &mut Connection, mut reader: R) -> Result<usize> {
+    let balls = add_exported_balls_to_hash_tree(db, &mut reader)?;
+    reader.seek(SeekFrom::Start(0))?;
+    match save_exported_joints(db, reader) {
+        Ok(count) => {
+            purge_handled_balls_from_hash_tree(db)?;
+            Ok(count)
+        }
+        Err(e) => {
+            // the next catchup must not trust the balls of the unsaved joints
+            remove_balls_from_hash_tree(db, &balls)?;
+            Err(e)
+        }
+    }
+}
+
+fn parse_exported_joint(line: &str) -> Result<Option<storage::ExportedJoint>> {
+    if line.trim().is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_str(line)?))
+}
+
+// the whole hash tree of the archive is checked before any ball is added, the
+// last ball must be stable or in the catchup chain verified by the witness
+// proof and all the other balls must be its ancestors
+fn add_exported_balls_to_hash_tree<R: BufRead>(
+    db: &mut Connection,
+    reader: R,
+) -> Result<Vec<String>> {
+    let mut balls = Vec::new();
+    for line in reader.lines() {
+        let exported = match parse_exported_joint(&line?)? {
+            Some(exported) => exported,
+            None => continue,
+        };
+        let unit = exported.joint.get_unit_hash().clone();
+        ensure!(exported.joint.ball.is_some(), "no ball for unit {}", unit);
+        balls.push(BallProps {
+            unit,
+            ball: exported.joint.ball,
+            content_hash: None,
+            is_nonserial: exported.is_nonserial,
+            parent_balls: exported.parent_balls,
+            skiplist_balls: exported.skiplist_balls,
+        });
+    }
+    if balls.is_empty() {
+        return Ok(Vec::new());
+    }
+    check_ancestors_of_last_ball(&balls)?;
+
+    let _g = HASHTREE_MUTEX.lock().unwrap();
+    let tx = db.transaction()?;
+    {
+        let last_ball = balls.last().unwrap().ball.as_ref().unwrap();
+        let mut stmt = tx.prepare_cached(
+            "SELECT 1 FROM balls JOIN units USING(unit) WHERE ball=? AND is_stable=1 \
+             UNION SELECT 1 FROM catchup_chain_balls WHERE ball=?",
+        )?;
+        ensure!(
+            stmt.exists(&[last_ball, last_ball])?,
+            "last ball {} of the archive is neither stable nor in the catchup chain",
+            last_ball
+        );
+    }
+    add_balls_to_hash_tree(&tx, &balls)?;
+    tx.commit()?;
+
+    Ok(balls.into_iter().filter_map(|props| props.ball).collect())
+}
+
+fn check_ancestors_of_last_ball(balls: &[BallProps]) -> Result<()> {
+    use std::collections::{HashMap, HashSet};
+
+    let parents_by_ball = balls
+        .iter()
+        .map(|props| (props.ball.as_ref().unwrap(), &props.parent_balls))
+        .collect::<HashMap<_, _>>();
+    let mut ancestors = HashSet::new();
+    let mut stack = vec![balls.last().unwrap().ball.as_ref().unwrap()];
+    while let Some(ball) = stack.pop() {
+        if !ancestors.insert(ball) {
+            continue;
+        }
+        if let Some(parent_balls) = parents_by_ball.get(ball) {
+            stack.extend(parent_balls.iter());
+        }
+    }
+
+    for props in balls {
+        ensure!(
+            ancestors.contains(props.ball.as_ref().unwrap()),
+            "unit {} of the archive is not an ancestor of the last ball",
+            props.unit
+        );
+    }
+    Ok(())
+}
+
+fn save_exported_joints<R: BufRead>(db: &mut Connection, reader: R) -> Result<usize> {
+    let mut count = 0;
+    for line in reader.lines() {
+        let joint = match parse_exported_joint(&line?)? {
+            Some(exported) => exported.joint,
+            None => continue,
+        };
+        match joint_storage::check_new_joint(db, &joint)? {
+            joint_storage::CheckNewResult::New => {}
+            _ => continue,
+        }
+        match validation::validate(db, &joint) {
+            Ok(validation::ValidationOk::Signed(validate_state, lock)) => {
+                joint.save(validate_state, false)?;
+                drop(lock);
+            }
+            Ok(validation::ValidationOk::Unsigned(_)) => {
+                bail!("unsigned joint {} in the archive", joint.get_unit_hash())
+            }
+            Err(e) => bail!(
+                "joint {} in the archive is invalid, {}: {}",
+                joint.get_unit_hash(),
+                e.code(),
+                e.detail()
+            ),
+        }
+        count += 1;
+    }
+    Ok(count)
+}
+
+fn remove_balls_from_hash_tree(db: &mut Connection, balls: &[String]) -> Result<()> {
+    let _g = HASHTREE_MUTEX.lock().unwrap();
+    let tx = db.transaction()?;
+    {
+        let mut stmt = tx.prepare_cached("DELETE FROM hash_tree_balls WHERE ball=?")?;
+        for ball in balls {
+            stmt.execute(&[ball])?;
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+// check the balls and add them to the hash tree, the parents and skiplist
+// units must be either in the hash tree or stable
+fn add_balls_to_hash_tree(tx: &Connection, balls: &[BallProps]) -> Result<()> {
+    use object_hash;
+
+    let mut max_mci = 0;
     for ball_prop in balls {
         ensure!(ball_prop.ball.is_some(), "no ball");
         if !::spec::is_genesis_unit(&ball_prop.unit) {
@@ -497,31 +682,6 @@ pub fn process_hash_tree(db: &mut Connection, balls: Vec<BallProps>) -> Result<(
 
         check_skiplist_ball_exist()?;
     }
-    {
-        let mut stmt = tx.prepare_cached(
-            "SELECT ball, main_chain_index \
-             FROM catchup_chain_balls LEFT JOIN balls USING(ball) LEFT JOIN units USING(unit) \
-             ORDER BY member_index LIMIT 2",
-        )?;
-
-        let rows_data: Vec<String> = stmt
-            .query_map(&[], |row| row.get(0))?
-            .collect::<::std::result::Result<Vec<_>, _>>()?;
-
-        if rows_data.len() != 2 {
-            bail!("expecting to have 2 elements in the chain");
-        }
-        if rows_data[1] != last_ball {
-            bail!("tree root doesn't match second chain element");
-        }
-
-        let mut stmt = tx.prepare_cached("DELETE FROM catchup_chain_balls WHERE ball=?")?;
-        stmt.execute(&[&rows_data[0]])?;
-        purge_handled_balls_from_hash_tree(&tx)?;
-    }
-
-    tx.commit()?;
-
     Ok(())
 }
 
@@ -538,3 +698,104 @@ pub fn purge_handled_balls_from_hash_tree(db: &Connection) -> Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+fn open_initial_db_copy(name: &str) -> Connection {
+    let path = ::std::env::temp_dir().join(name);
+    let _ = ::std::fs::remove_file(&path);
+    ::std::fs::copy(
+        concat!(env!("CARGO_MANIFEST_DIR"), "/db/initial.trustnote.sqlite"),
+        &path,
+    )
+    .unwrap();
+    Connection::open(&path).unwrap()
+}
+
+// insert a stable unit without content, returns the unit and its ball
+#[cfg(test)]
+fn insert_stable_unit(
+    db: &Connection,
+    name: &str,
+    mci: u32,
+    parent: Option<&(String, String)>,
+) -> (String, String) {
+    use object_hash;
+
+    let unit = object_hash::get_base64_hash(&name).unwrap();
+    let parent_balls = parent.iter().map(|p| p.1.clone()).collect::<Vec<_>>();
+    let ball = object_hash::get_ball_hash(&unit, &parent_balls, &[], false);
+    db.execute(
+        "INSERT INTO units (unit, headers_commission, payload_commission, \
+         is_on_main_chain, main_chain_index, level, is_stable) VALUES (?, 0, 0, 1, ?, ?, 1)",
+        &[&unit, &mci, &mci],
+    )
+    .unwrap();
+    db.execute(
+        "INSERT INTO balls (ball, unit) VALUES (?, ?)",
+        &[&ball, &unit],
+    )
+    .unwrap();
+    if let Some(parent) = parent {
+        db.execute(
+            "INSERT INTO parenthoods (child_unit, parent_unit) VALUES (?, ?)",
+            &[&unit, &parent.0],
+        )
+        .unwrap();
+    }
+    (unit, ball)
+}
+
+#[cfg(test)]
+fn count_hash_tree_balls(db: &Connection) -> u32 {
+    db.query_row("SELECT COUNT(*) FROM hash_tree_balls", &[], |row| {
+        row.get(0)
+    })
+    .unwrap()
+}
+
+#[test]
+fn test_export_import_joints() {
+    use std::io::Cursor;
+
+    let src_db = open_initial_db_copy("trustnote_test_export.sqlite");
+    let genesis = insert_stable_unit(&src_db, "export genesis", 0, None);
+    let first = insert_stable_unit(&src_db, "export first", 1, Some(&genesis));
+    let second = insert_stable_unit(&src_db, "export second", 2, Some(&first));
+    let mut archive = Vec::new();
+    assert_eq!(
+        storage::export_joints(&src_db, 1, 2, &mut archive).unwrap(),
+        2
+    );
+
+    let mut db = open_initial_db_copy("trustnote_test_import.sqlite");
+    insert_stable_unit(&db, "export genesis", 0, None);
+
+    // the last ball is not anchored
+    assert!(import_joints(&mut db, Cursor::new(&archive)).is_err());
+    assert_eq!(count_hash_tree_balls(&db), 0);
+
+    // the last ball is verified by the witness proof of the catchup chain
+    db.execute(
+        "INSERT INTO catchup_chain_balls (ball) VALUES (?)",
+        &[&second.1],
+    )
+    .unwrap();
+    let balls = add_exported_balls_to_hash_tree(&mut db, Cursor::new(&archive)).unwrap();
+    assert_eq!(balls, vec![first.1.clone(), second.1.clone()]);
+    assert_eq!(count_hash_tree_balls(&db), 2);
+    remove_balls_from_hash_tree(&mut db, &balls).unwrap();
+
+    // the joints without content fail the validation and leave no balls
+    assert!(import_joints(&mut db, Cursor::new(&archive)).is_err());
+    assert_eq!(count_hash_tree_balls(&db), 0);
+
+    // a side unit of the archive is not an ancestor of the last ball
+    insert_stable_unit(&src_db, "export side", 1, Some(&genesis));
+    let mut archive = Vec::new();
+    assert_eq!(
+        storage::export_joints(&src_db, 1, 2, &mut archive).unwrap(),
+        3
+    );
+    assert!(add_exported_balls_to_hash_tree(&mut db, Cursor::new(&archive)).is_err());
+    assert_eq!(count_hash_tree_balls(&db), 0);
+}
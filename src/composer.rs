@@ -7,11 +7,13 @@ use light::LastStableBallAndParentUnitsAndWitnessListUnit;
 use mc_outputs;
 use object_hash;
 use paid_witnessing;
+use private_payment;
 use rusqlite::Connection;
 use serde_json::{self, Value};
 use signature::Signer;
 use spec;
 use spec::*;
+use storage;
 
 #[derive(Debug)]
 struct InputWithProof {
@@ -28,11 +30,30 @@ struct InputsAndAmount {
 #[derive(Debug)]
 struct Asset {
     asset: Option<String>,
-    issued_by_definer_only: Option<u32>,
+    issued_by_definer_only: bool,
     definer_address: String,
-    cap: bool,
-    auto_destroy: u32,
+    cap: Option<i64>,
+    auto_destroy: bool,
     is_private: bool,
+    fixed_denominations: bool,
+}
+
+impl Asset {
+    fn read(db: &Connection, asset: &String) -> Result<Asset> {
+        let info = match storage::read_asset_info(db, asset)? {
+            Some(info) => info,
+            None => bail!("asset {} not found", asset),
+        };
+        Ok(Asset {
+            asset: Some(info.asset),
+            issued_by_definer_only: info.issued_by_definer_only,
+            definer_address: info.definer_address,
+            cap: info.cap,
+            auto_destroy: info.auto_destroy,
+            is_private: info.is_private,
+            fixed_denominations: info.fixed_denominations,
+        })
+    }
 }
 
 #[derive(Debug)]
@@ -53,7 +74,7 @@ fn issue_asset(
     if asset.is_none() || asset.as_ref().unwrap().asset.is_none() {
         return finish(send_all, input_info.inputs_and_amount);
     } else {
-        if send_all && !asset.as_ref().unwrap().cap {
+        if send_all && asset.as_ref().unwrap().cap.is_none() {
             bail!(
                 "error_code: NOT_ENOUGH_FUNDS 
                  error: not enough spendable funds from {:?} for {}",
@@ -65,32 +86,31 @@ fn issue_asset(
 
     let asset = asset.as_ref().unwrap();
 
-    if asset.issued_by_definer_only.is_some()
-        && !input_info.paying_addresses.contains(&asset.definer_address)
+    if asset.issued_by_definer_only && !input_info.paying_addresses.contains(&asset.definer_address)
     {
         return finish(send_all, input_info.inputs_and_amount);
     }
 
-    let issuer_address = if asset.issued_by_definer_only.is_some() {
+    let issuer_address = if asset.issued_by_definer_only {
         asset.definer_address.clone()
     } else {
         input_info.paying_addresses[0].clone()
     };
 
-    let add_issue_input = |serial_number: u32, closer_input_info: &mut InputInfo| -> Result<bool> {
-        #[derive(Serialize)]
-        struct TmpSpendProof<'a> {
-            asset: &'a Option<String>,
-            amount: u64,
-            address: &'a String,
-            c: u32,
-            serial_number: u32,
+    // issue the whole cap at once, or just what is still missing
+    let issue_amount = match asset.cap {
+        Some(cap) => cap as u64,
+        None if input_info.required_amount > input_info.inputs_and_amount.amount => {
+            input_info.required_amount - input_info.inputs_and_amount.amount
         }
+        None => 1,
+    };
 
-        closer_input_info.inputs_and_amount.amount += 1;
+    let add_issue_input = |serial_number: u32, closer_input_info: &mut InputInfo| -> Result<bool> {
+        closer_input_info.inputs_and_amount.amount += issue_amount;
 
         let mut input = spec::Input {
-            amount: Some(1),
+            amount: Some(issue_amount as i64),
             kind: Some(String::from("issue")),
             serial_number: Some(serial_number),
             ..Default::default()
@@ -106,13 +126,13 @@ fn issue_asset(
         };
 
         if asset.is_private {
-            let spend_proof = object_hash::get_base64_hash(&TmpSpendProof {
-                asset: &asset.asset,
-                amount: 1,
-                c: 1,
-                address: &issuer_address,
-                serial_number: serial_number,
-            })?;
+            let spend_proof = private_payment::get_issue_spend_proof(
+                asset.asset.as_ref().unwrap(),
+                &issuer_address,
+                issue_amount as i64,
+                serial_number,
+                None,
+            )?;
             let mut spend_proof = spec::SpendProof {
                 spend_proof,
                 address: None,
@@ -137,7 +157,7 @@ fn issue_asset(
         })
     };
 
-    if asset.cap {
+    if asset.cap.is_some() {
         let mut stmt = db.prepare_cached("SELECT 1 FROM inputs WHERE type='issue' AND asset=?")?;
 
         let input_rows = stmt
@@ -160,11 +180,11 @@ fn issue_asset(
             .query_map(&[asset.asset.as_ref().unwrap(), &issuer_address], |row| {
                 row.get(0)
             })?.collect::<::std::result::Result<Vec<Option<u32>>, _>>()?;
-        let max_serial_number = if max_serial_numbers.is_empty() {
-            0
-        } else {
-            max_serial_numbers[0].unwrap()
-        };
+        let max_serial_number = max_serial_numbers
+            .into_iter()
+            .next()
+            .and_then(|n| n)
+            .unwrap_or(0);
         if add_issue_input(max_serial_number + 1, &mut input_info)? {
             return Ok(input_info.inputs_and_amount);
         }
@@ -178,17 +198,6 @@ fn add_input(
     asset: &Option<Asset>,
     multi_authored: bool,
 ) -> Result<InputsAndAmount> {
-    #[derive(Serialize)]
-    struct TmpSpendProof<'a> {
-        asset: &'a Option<String>,
-        amount: &'a Option<i64>,
-        address: &'a Option<String>,
-        unit: &'a Option<String>,
-        message_index: &'a Option<u32>,
-        output_index: &'a Option<u32>,
-        blinding: &'a Option<String>,
-    }
-
     if let Some(amount) = input.amount {
         assert!(amount >= 0, "negative input");
         inputs_and_amount.amount += amount as u64;
@@ -200,15 +209,13 @@ fn add_input(
     };
 
     if asset.is_some() && asset.as_ref().unwrap().is_private {
-        let spend_proof = object_hash::get_base64_hash(&TmpSpendProof {
-            asset: &asset.as_ref().unwrap().asset,
-            amount: &input.amount,
-            address: &input.address,
-            unit: &input.unit,
-            message_index: &input.message_index,
-            output_index: &input.output_index,
-            blinding: &input.blinding,
-        })?;
+        let spend_proof = private_payment::get_transfer_spend_proof(
+            asset.as_ref().unwrap().asset.as_ref().unwrap(),
+            &input,
+            input.address.as_ref().expect("no input address"),
+            input.amount.unwrap_or(0),
+            &input.blinding,
+        )?;
         let mut spend_proof = spec::SpendProof {
             spend_proof,
             address: None,
@@ -220,7 +227,8 @@ fn add_input(
         input_with_proof.spend_proof = Some(spend_proof);
     }
 
-    if !multi_authored || !input.kind.is_none() {
+    // only the typed inputs of a multi-authored unit need to tell their owner
+    if !multi_authored || input.kind.is_none() {
         input.address = None;
     }
 
@@ -347,7 +355,7 @@ fn pick_multiple_coins_and_continue(
     let tmp_sql = if asset.is_none() || asset.as_ref().unwrap().asset.is_none() {
         " IS NULL".to_string()
     } else {
-        "=".to_string() + asset.as_ref().unwrap().asset.as_ref().unwrap()
+        format!("='{}'", asset.as_ref().unwrap().asset.as_ref().unwrap())
     };
 
     let addresses = spendable_addresses
@@ -421,7 +429,7 @@ fn pick_one_coin_just_bigger_and_continue(
     let asset_tmp = if asset.is_none() {
         " IS NULL".to_string()
     } else {
-        "=".to_string() + asset.as_ref().unwrap().asset.as_ref().unwrap()
+        format!("='{}'", asset.as_ref().unwrap().asset.as_ref().unwrap())
     };
 
     let more = if is_base { ">" } else { ">=" };
@@ -482,12 +490,14 @@ fn pick_divisible_coins_for_amount(
 
     debug!("spendable_addresses = {:?}", spendable_addresses);
 
-    //now asset is None
+    // coins sent to the definer of an auto-destroyed asset are gone
     if let Some(tmp) = &asset {
-        spendable_addresses = spendable_addresses
-            .into_iter()
-            .filter(|v| v != &tmp.definer_address)
-            .collect::<Vec<_>>()
+        if tmp.auto_destroy {
+            spendable_addresses = spendable_addresses
+                .into_iter()
+                .filter(|v| v != &tmp.definer_address)
+                .collect::<Vec<_>>()
+        }
     }
 
     let input_info = InputInfo {
@@ -545,6 +555,419 @@ fn pick_divisible_coins_for_amount(
     issue_asset(db, input_info, asset, is_base, send_all)
 }
 
+#[derive(Debug)]
+struct IndivisibleCoin {
+    input: spec::Input,
+    denomination: u32,
+    amount: u64,
+    // needed for the spend proof of a private coin
+    owner_address: String,
+    blinding: Option<String>,
+}
+
+// find the biggest coin that still fits into the remaining amount
+fn pick_indivisible_coin(
+    db: &Connection,
+    asset: &Asset,
+    spendable_addresses: &[String],
+    used_output_ids: &[i64],
+    remaining_amount: u64,
+    last_ball_mci: u32,
+) -> Result<Option<(i64, IndivisibleCoin)>> {
+    if spendable_addresses.is_empty() {
+        return Ok(None);
+    }
+
+    let addresses = spendable_addresses
+        .iter()
+        .map(|v| format!("'{}'", v))
+        .collect::<Vec<_>>()
+        .join(",");
+    let output_ids = if used_output_ids.is_empty() {
+        "-1".to_string()
+    } else {
+        used_output_ids
+            .iter()
+            .map(|v| v.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+
+    let sql = format!(
+        "SELECT output_id, unit, message_index, output_index, amount, denomination, \
+         address, blinding \
+         FROM outputs \
+         CROSS JOIN units USING(unit) \
+         WHERE asset=? AND address IN({}) AND is_spent=0 AND sequence='good' \
+         AND is_stable=1 AND denomination<=? AND output_id NOT IN({}) AND main_chain_index<=? \
+         ORDER BY denomination DESC, (amount>=?) DESC, ABS(amount-?) LIMIT 1",
+        addresses, output_ids
+    );
+    let mut stmt = db.prepare(&sql)?;
+    let remaining_amount = remaining_amount as i64;
+    let coins = stmt
+        .query_map(
+            &[
+                asset.asset.as_ref().unwrap(),
+                &remaining_amount,
+                &last_ball_mci,
+                &remaining_amount,
+                &remaining_amount,
+            ],
+            |row| {
+                let output_id = row.get(0);
+                let coin = IndivisibleCoin {
+                    input: spec::Input {
+                        unit: row.get(1),
+                        message_index: row.get(2),
+                        output_index: row.get(3),
+                        ..Default::default()
+                    },
+                    amount: row.get::<_, i64>(4) as u64,
+                    denomination: row.get(5),
+                    owner_address: row.get(6),
+                    blinding: row.get(7),
+                };
+                (output_id, coin)
+            },
+        )?
+        .collect::<::std::result::Result<Vec<_>, _>>()?;
+
+    Ok(coins.into_iter().next())
+}
+
+// issue coins of the biggest denomination that still fits into the remaining amount,
+// a capped asset issues all the coins of a denomination at once
+fn issue_indivisible_coin(
+    db: &Connection,
+    asset: &Asset,
+    paying_addresses: &[String],
+    remaining_amount: u64,
+    multi_authored: bool,
+) -> Result<Option<IndivisibleCoin>> {
+    if asset.issued_by_definer_only && !paying_addresses.contains(&asset.definer_address) {
+        return Ok(None);
+    }
+    let asset_id = asset.asset.as_ref().unwrap();
+    let issuer_address = if asset.issued_by_definer_only {
+        asset.definer_address.clone()
+    } else {
+        paying_addresses[0].clone()
+    };
+
+    let can_issue_condition = if asset.cap.is_some() {
+        "AND NOT EXISTS (SELECT 1 FROM inputs WHERE type='issue' \
+         AND inputs.asset=asset_denominations.asset \
+         AND inputs.denomination=asset_denominations.denomination)"
+    } else {
+        ""
+    };
+    let sql = format!(
+        "SELECT denomination, count_coins FROM asset_denominations \
+         WHERE asset=? AND denomination<=? {} ORDER BY denomination DESC LIMIT 1",
+        can_issue_condition
+    );
+    let mut stmt = db.prepare(&sql)?;
+    let rows = stmt
+        .query_map(&[asset_id, &(remaining_amount as i64)], |row| {
+            (row.get::<_, u32>(0), row.get::<_, Option<i64>>(1))
+        })?
+        .collect::<::std::result::Result<Vec<_>, _>>()?;
+    let (denomination, count_coins) = match rows.into_iter().next() {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    if count_coins.is_some() != asset.cap.is_some() {
+        bail!("invalid asset cap and count_coins");
+    }
+
+    let serial_number = if asset.cap.is_some() {
+        1
+    } else {
+        let mut stmt = db.prepare_cached(
+            "SELECT MAX(serial_number) FROM inputs \
+             WHERE type='issue' AND asset=? AND denomination=? AND address=?",
+        )?;
+        let max_serial_number = stmt
+            .query_row(&[asset_id, &denomination, &issuer_address], |row| {
+                row.get::<_, Option<u32>>(0)
+            })?;
+        max_serial_number.unwrap_or(0) + 1
+    };
+
+    let count_coins =
+        count_coins.unwrap_or_else(|| (remaining_amount / u64::from(denomination)) as i64);
+    let amount = count_coins as u64 * u64::from(denomination);
+    let mut input = spec::Input {
+        kind: Some(String::from("issue")),
+        amount: Some(amount as i64),
+        serial_number: Some(serial_number),
+        ..Default::default()
+    };
+    if multi_authored {
+        input.address = Some(issuer_address.clone());
+    }
+
+    Ok(Some(IndivisibleCoin {
+        input,
+        denomination,
+        amount,
+        owner_address: issuer_address,
+        blinding: None,
+    }))
+}
+
+// return the coins to spend along with the part of each coin that is used for the amount,
+// every coin is spent in its own payment message
+fn pick_indivisible_coins_for_amount(
+    db: &Connection,
+    asset: &Asset,
+    paying_addresses: &[String],
+    last_ball_mci: u32,
+    amount: u64,
+    multi_authored: bool,
+) -> Result<Vec<(IndivisibleCoin, u64)>> {
+    let mut spendable_addresses = paying_addresses.to_vec();
+    if asset.auto_destroy {
+        spendable_addresses.retain(|v| v != &asset.definer_address);
+    }
+
+    let mut coins = Vec::new();
+    let mut used_output_ids = Vec::new();
+    let mut accumulated_amount = 0;
+    while accumulated_amount < amount {
+        // reserve one message for the base payment
+        if coins.len() >= config::MAX_MESSAGES_PER_UNIT - 1 {
+            bail!("too many messages, try sending a smaller amount");
+        }
+
+        let remaining_amount = amount - accumulated_amount;
+        let picked = pick_indivisible_coin(
+            db,
+            asset,
+            &spendable_addresses,
+            &used_output_ids,
+            remaining_amount,
+            last_ball_mci,
+        )?;
+        let coin = match picked {
+            Some((output_id, coin)) => {
+                used_output_ids.push(output_id);
+                coin
+            }
+            None => match issue_indivisible_coin(
+                db,
+                asset,
+                paying_addresses,
+                remaining_amount,
+                multi_authored,
+            )? {
+                Some(coin) => coin,
+                None => bail!(
+                    "NOT_ENOUGH_FUNDS, not enough indivisible coins from {:?} for {}",
+                    paying_addresses,
+                    amount
+                ),
+            },
+        };
+
+        // take as many coins of the denomination as the amount allows
+        let denomination = u64::from(coin.denomination);
+        let amount_to_use = if coin.amount > remaining_amount {
+            remaining_amount / denomination * denomination
+        } else {
+            coin.amount
+        };
+        accumulated_amount += amount_to_use;
+        coins.push((coin, amount_to_use));
+    }
+
+    Ok(coins)
+}
+
+// payment in an asset, the output with amount 0 receives the change,
+// indivisible assets can only be paid to one address
+pub struct AssetPaymentInfo {
+    pub asset: String,
+    pub outputs: Vec<Output>,
+}
+
+// the payload of a private asset is returned along with its message
+fn compose_asset_payment_messages(
+    db: &Connection,
+    asset_payment: AssetPaymentInfo,
+    paying_addresses: &[String],
+    last_ball_mci: u32,
+    multi_authored: bool,
+) -> Result<Vec<(Message, Option<Payment>)>> {
+    let AssetPaymentInfo { asset, outputs } = asset_payment;
+    let (mut change_outputs, mut outputs): (Vec<_>, Vec<_>) =
+        outputs.into_iter().partition(|output| output.amount == 0);
+    if change_outputs.len() != 1 {
+        bail!("asset payment needs exactly one change output");
+    }
+    if outputs.is_empty() {
+        bail!("no asset outputs");
+    }
+    let amount = outputs.iter().fold(0, |acc, output| acc + output.amount) as u64;
+
+    let asset_info = Asset::read(db, &asset)?;
+    let is_private = asset_info.is_private;
+
+    let payments = if asset_info.fixed_denominations {
+        if outputs.len() != 1 {
+            bail!("indivisible asset payment supports only one recipient");
+        }
+        let coins = pick_indivisible_coins_for_amount(
+            db,
+            &asset_info,
+            paying_addresses,
+            last_ball_mci,
+            amount,
+            multi_authored,
+        )?;
+        debug!("indivisible coins = {:?}", coins);
+
+        let mut payments = Vec::new();
+        for (coin, amount_to_use) in coins {
+            let mut coin_outputs = vec![Output {
+                address: outputs[0].address.clone(),
+                amount: amount_to_use as i64,
+                blinding: None,
+            }];
+            if coin.amount > amount_to_use {
+                coin_outputs.push(Output {
+                    address: change_outputs[0].address.clone(),
+                    amount: (coin.amount - amount_to_use) as i64,
+                    blinding: None,
+                });
+                coin_outputs.sort_by(|a, b| {
+                    if a.address == b.address {
+                        a.amount.cmp(&b.amount)
+                    } else {
+                        a.address.cmp(&b.address)
+                    }
+                });
+            }
+
+            let mut spend_proofs = Vec::new();
+            if is_private {
+                let spend_proof = if coin.input.kind.is_some() {
+                    private_payment::get_issue_spend_proof(
+                        &asset,
+                        &coin.owner_address,
+                        coin.amount as i64,
+                        coin.input.serial_number.unwrap(),
+                        Some(coin.denomination),
+                    )?
+                } else {
+                    private_payment::get_transfer_spend_proof(
+                        &asset,
+                        &coin.input,
+                        &coin.owner_address,
+                        coin.amount as i64,
+                        &coin.blinding,
+                    )?
+                };
+                spend_proofs.push(spec::SpendProof {
+                    spend_proof,
+                    address: some_if!(multi_authored, coin.owner_address.clone()),
+                });
+            }
+
+            let payment = Payment {
+                address: None,
+                asset: Some(asset.clone()),
+                definition_chash: None,
+                denomination: Some(coin.denomination),
+                inputs: vec![coin.input],
+                outputs: coin_outputs,
+            };
+            payments.push((payment, spend_proofs));
+        }
+        payments
+    } else {
+        let inputs_and_amount = pick_divisible_coins_for_amount(
+            db,
+            Some(asset_info),
+            paying_addresses.to_vec(),
+            last_ball_mci,
+            amount,
+            multi_authored,
+            false,
+        )?;
+        debug!("asset inputs_and_amount = {:?}", inputs_and_amount);
+
+        let change = inputs_and_amount.amount - amount;
+        if change > 0 {
+            let mut change_output = change_outputs.remove(0);
+            change_output.amount = change as i64;
+            outputs.push(change_output);
+        }
+        outputs.sort_by(|a, b| {
+            if a.address == b.address {
+                a.amount.cmp(&b.amount)
+            } else {
+                a.address.cmp(&b.address)
+            }
+        });
+
+        let mut inputs = Vec::new();
+        let mut spend_proofs = Vec::new();
+        for input_with_proof in inputs_and_amount.input_with_proofs {
+            inputs.extend(input_with_proof.input);
+            spend_proofs.extend(input_with_proof.spend_proof);
+        }
+
+        let payment = Payment {
+            address: None,
+            asset: Some(asset),
+            definition_chash: None,
+            denomination: None,
+            inputs,
+            outputs,
+        };
+        vec![(payment, spend_proofs)]
+    };
+
+    let mut messages = Vec::new();
+    for (mut payment, spend_proofs) in payments {
+        if !is_private {
+            messages.push((
+                Message {
+                    app: "payment".to_string(),
+                    payload_location: "inline".to_string(),
+                    payload_hash: object_hash::get_base64_hash(&payment)?,
+                    payload: Some(Payload::Payment(payment)),
+                    payload_uri: None,
+                    payload_uri_hash: None,
+                    spend_proofs,
+                },
+                None,
+            ));
+            continue;
+        }
+
+        // blind the outputs, otherwise the amounts could be guessed from the payload hash
+        for output in &mut payment.outputs {
+            output.blinding = Some(object_hash::gen_random_string(12));
+        }
+        messages.push((
+            Message {
+                app: "payment".to_string(),
+                payload_location: "none".to_string(),
+                payload_hash: object_hash::get_base64_hash(&payment)?,
+                payload: None,
+                payload_uri: None,
+                payload_uri_hash: None,
+                spend_proofs,
+            },
+            Some(payment),
+        ));
+    }
+    Ok(messages)
+}
+
 pub struct ComposeInfo {
     pub signing_addresses: Vec<String>,
     pub paying_addresses: Vec<String>,
@@ -556,9 +979,23 @@ pub struct ComposeInfo {
     pub inputs: Vec<Input>,
     pub input_amount: u64,
     pub send_all: bool,
+    pub asset_payment: Option<AssetPaymentInfo>,
 }
 
 pub fn compose_joint<T: Signer>(db: &Connection, params: ComposeInfo, signer: &T) -> Result<Joint> {
+    let (joint, private_payloads) = compose_joint_with_private_payloads(db, params, signer)?;
+    if !private_payloads.is_empty() {
+        bail!("private payloads would be lost, use compose_joint_with_private_payloads");
+    }
+    Ok(joint)
+}
+
+/// compose the joint along with the private payloads that must be sent to the payee
+pub fn compose_joint_with_private_payloads<T: Signer>(
+    db: &Connection,
+    params: ComposeInfo,
+    signer: &T,
+) -> Result<(Joint, Vec<PrivateElement>)> {
     let ComposeInfo {
         mut signing_addresses,
         mut paying_addresses,
@@ -570,6 +1007,7 @@ pub fn compose_joint<T: Signer>(db: &Connection, params: ComposeInfo, signer: &T
         inputs,
         input_amount,
         send_all,
+        asset_payment,
     } = params;
 
     let change_outputs = outputs
@@ -663,13 +1101,17 @@ pub fn compose_joint<T: Signer>(db: &Connection, params: ComposeInfo, signer: &T
             definition: Value::Null,
         };
 
+        // the definition must be revealed when it is used for the first time,
+        // that is in the first unit of the address or after its definition changed
+        let definition_chash =
+            storage::read_definition_chash_by_address(db, from_address, last_stable_mc_ball_mci)?;
         let mut stmt = db.prepare_cached(
             "SELECT 1 FROM unit_authors CROSS JOIN units USING(unit) \
-             WHERE address=? AND is_stable=1 AND sequence='good' AND main_chain_index<=? \
-             LIMIT 1",
+             WHERE address=? AND definition_chash=? AND is_stable=1 AND sequence='good' \
+             AND main_chain_index<=? LIMIT 1",
         )?;
-        if !stmt.exists(&[from_address, &last_stable_mc_ball_mci])? {
-            author.definition = read_definition(&db, &from_address)?;
+        if !stmt.exists(&[from_address, &definition_chash, &last_stable_mc_ball_mci])? {
+            author.definition = read_definition(&db, &from_address, &definition_chash)?;
         }
 
         unit.authors.push(author);
@@ -682,6 +1124,24 @@ pub fn compose_joint<T: Signer>(db: &Connection, params: ComposeInfo, signer: &T
         unit.witnesses = witnesses;
     }
 
+    // asset coins, the commissions are still paid by the base payment
+    let mut private_payloads = Vec::new();
+    if let Some(asset_payment) = asset_payment {
+        let asset_messages = compose_asset_payment_messages(
+            db,
+            asset_payment,
+            &from_addresses,
+            last_stable_mc_ball_mci,
+            is_multi_authored,
+        )?;
+        for (message, private_payload) in asset_messages {
+            if let Some(payload) = private_payload {
+                private_payloads.push((unit.messages.len() as u32, payload));
+            }
+            unit.messages.push(message);
+        }
+    }
+
     // input coins
     let total_input;
     unit.headers_commission = Some(unit.get_header_size() + config::SIG_LENGTH as u32);
@@ -772,15 +1232,26 @@ pub fn compose_joint<T: Signer>(db: &Connection, params: ComposeInfo, signer: &T
     }
 
     unit.timestamp = Some(::time::now() / 1000);
-    unit.unit = Some(unit.get_unit_hash());
+    let unit_hash = unit.get_unit_hash();
+    unit.unit = Some(unit_hash.clone());
+
+    let private_elements = private_payloads
+        .into_iter()
+        .map(|(message_index, payload)| PrivateElement {
+            unit: unit_hash.clone(),
+            message_index,
+            payload,
+        })
+        .collect();
 
     debug!("-----unit---------{}", serde_json::to_string_pretty(&unit)?);
-    Ok(Joint {
+    let joint = Joint {
         ball: None,
         skiplist_units: Vec::new(),
         unsigned: None,
         unit,
-    })
+    };
+    Ok((joint, private_elements))
 }
 
 fn check_for_unstable_predecessors(
@@ -810,19 +1281,94 @@ fn check_for_unstable_predecessors(
     Ok(())
 }
 
-fn read_definition(db: &Connection, address: &String) -> Result<Value> {
+/// the definition of our address with the given chash, it is either the one the
+/// address is created with or one the wallet has changed the address to
+pub fn read_definition(
+    db: &Connection,
+    address: &String,
+    definition_chash: &String,
+) -> Result<Value> {
     let mut stmt = db.prepare_cached(
         "SELECT definition FROM my_addresses WHERE address=? \
          UNION \
-         SELECT definition FROM shared_addresses WHERE shared_address=?",
+         SELECT definition FROM shared_addresses WHERE shared_address=? \
+         UNION \
+         SELECT definition FROM definitions WHERE definition_chash=?",
     )?;
     let rows = stmt
-        .query_map(&[address, address], |row| row.get(0))?
+        .query_map(&[address, address, definition_chash], |row| row.get(0))?
         .collect::<::std::result::Result<Vec<String>, _>>()?;
-    if rows.len() != 1 {
-        bail!("definition not found");
+    for row in rows {
+        let definition: Value = serde_json::from_str(&row)?;
+        if &object_hash::get_chash(&definition)? == definition_chash {
+            return Ok(definition);
+        }
+    }
+    bail!("definition {} of {} not found", definition_chash, address);
+}
+
+/// the address is only needed when the unit has more than one author
+pub fn create_address_definition_change_message(
+    address: Option<String>,
+    definition_chash: String,
+) -> Result<spec::Message> {
+    let payload = serde_json::to_value(AddressDefinitionChange {
+        address,
+        definition_chash,
+    })?;
+    Ok(spec::Message {
+        app: String::from("address_definition_change"),
+        payload_location: String::from("inline"),
+        payload_hash: object_hash::get_base64_hash(&payload)?,
+        payload: Some(spec::Payload::Other(payload)),
+        ..Default::default()
+    })
+}
+
+/// feed values must be strings or integers, the same as validation requires
+pub fn create_data_feed_message(feeds: &serde_json::Map<String, Value>) -> Result<spec::Message> {
+    ensure!(!feeds.is_empty(), "no data feeds to post");
+    for (name, value) in feeds {
+        ensure!(
+            name.len() <= config::MAX_DATA_FEED_NAME_LENGTH,
+            "feed name {} too long",
+            name
+        );
+        match *value {
+            Value::String(ref s) => ensure!(
+                s.len() <= config::MAX_DATA_FEED_VALUE_LENGTH,
+                "value of feed {} too long",
+                name
+            ),
+            Value::Number(ref n) => ensure!(
+                !n.is_f64(),
+                "fractional numbers not allowed in data feed {}",
+                name
+            ),
+            _ => bail!("data feed {} must be string or number", name),
+        }
     }
-    Ok(serde_json::from_str(&rows.into_iter().nth(0).unwrap())?)
+
+    let payload = Value::Object(feeds.clone());
+    Ok(spec::Message {
+        app: String::from("data_feed"),
+        payload_location: String::from("inline"),
+        payload_hash: object_hash::get_base64_hash(&payload)?,
+        payload: Some(spec::Payload::Other(payload)),
+        ..Default::default()
+    })
+}
+
+/// the payload is only referenced by the uri, it must be retrievable by the payees
+pub fn create_uri_message(app: &str, payload: &Payload, uri: String) -> Result<spec::Message> {
+    Ok(spec::Message {
+        app: app.to_owned(),
+        payload_location: String::from("uri"),
+        payload_hash: object_hash::get_base64_hash(payload)?,
+        payload_uri_hash: Some(object_hash::get_base64_hash(&uri)?),
+        payload_uri: Some(uri),
+        ..Default::default()
+    })
 }
 
 pub fn create_text_message(text: &String) -> Result<spec::Message> {
@@ -834,3 +1380,34 @@ pub fn create_text_message(text: &String) -> Result<spec::Message> {
         ..Default::default()
     })
 }
+
+#[test]
+fn test_create_data_feed_message() {
+    let feeds = |value: Value| value.as_object().unwrap().clone();
+
+    let message =
+        create_data_feed_message(&feeds(json!({"TTT_USD": "0.0321", "height": 100}))).unwrap();
+    assert_eq!(message.app, "data_feed");
+    assert_eq!(
+        message.payload_hash,
+        object_hash::get_base64_hash(&json!({"TTT_USD": "0.0321", "height": 100})).unwrap()
+    );
+
+    assert!(create_data_feed_message(&feeds(json!({}))).is_err());
+
+    // names and string values are limited in length
+    let name = "n".repeat(config::MAX_DATA_FEED_NAME_LENGTH);
+    assert!(create_data_feed_message(&feeds(json!({ name: 1 }))).is_ok());
+    let name = "n".repeat(config::MAX_DATA_FEED_NAME_LENGTH + 1);
+    assert!(create_data_feed_message(&feeds(json!({ name: 1 }))).is_err());
+    let value = "v".repeat(config::MAX_DATA_FEED_VALUE_LENGTH);
+    assert!(create_data_feed_message(&feeds(json!({ "name": value }))).is_ok());
+    let value = "v".repeat(config::MAX_DATA_FEED_VALUE_LENGTH + 1);
+    assert!(create_data_feed_message(&feeds(json!({ "name": value }))).is_err());
+
+    // only strings and integers
+    assert!(create_data_feed_message(&feeds(json!({"price": 0.5}))).is_err());
+    assert!(create_data_feed_message(&feeds(json!({"price": -1}))).is_ok());
+    assert!(create_data_feed_message(&feeds(json!({"price": true}))).is_err());
+    assert!(create_data_feed_message(&feeds(json!({"price": ["1"]}))).is_err());
+}
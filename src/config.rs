@@ -27,7 +27,21 @@ pub const MAX_AUTHENTIFIER_LENGTH: usize = 4096;
 pub const COUNT_MC_BALLS_FOR_PAID_WITNESSING: u32 = 100;
 pub const MAX_DATA_FEED_NAME_LENGTH: usize = 64;
 pub const MAX_DATA_FEED_VALUE_LENGTH: usize = 64;
+pub const MAX_ORACLES_PER_REQUEST: usize = 16;
+pub const MAX_CAP: i64 = 9_000_000_000_000_000;
+pub const MAX_DENOMINATIONS_PER_ASSET_DEFINITION: usize = 64;
+pub const MAX_ATTESTORS_PER_ASSET: usize = 64;
+pub const MAX_CHOICES_PER_POLL: usize = 128;
+pub const MAX_CHOICE_LENGTH: usize = 64;
 pub const MAX_ITEMS_IN_CACHE: usize = 1000;
+pub const MAX_SIGNATURES_IN_CACHE: usize = 10_000;
+pub const DB_BUSY_TIMEOUT: u64 = 30;
+pub const MIN_PRUNING_RETENTION_MCI: u32 = 1000;
+pub const MAX_MCIS_TO_PRUNE_AT_ONCE: u32 = 100;
+pub const MAX_UNHANDLED_PRIVATE_PAYMENTS_PER_PEER: u32 = 100;
+pub const UNHANDLED_PRIVATE_PAYMENT_TIMEOUT: u64 = 3600;
+pub const MAX_PENDING_PRIVATE_PAYMENTS_PER_ADDRESS: u32 = 100;
+pub const PENDING_PRIVATE_PAYMENT_TIMEOUT: u64 = 7 * 24 * 3600;
 pub const MAX_OUTBOUND_CONNECTIONS: usize = 5;
 pub const TRANSFER_INPUT_SIZE: u32 = 60;
 pub const ADDRESS_SIZE: u32 = 32;
@@ -49,6 +63,10 @@ pub fn show_config() {
     println!("\tremote_hub = {:?}", get_remote_hub_url());
     println!("\thub_server_port = {}", get_hub_server_port());
     println!("\tdatabase_path = {:?}", get_database_path(false));
+    println!(
+        "\tpruning_retention_mci = {:?}",
+        get_pruning_retention_mci()
+    );
     println!("\n");
 }
 
@@ -77,6 +95,36 @@ pub fn get_hub_server_port() -> u16 {
     cfg.get::<u16>("hub_server_port").unwrap_or(6615)
 }
 
+/// the directory of the local store for payloads with location "uri"
+pub fn get_content_store_path() -> ::std::path::PathBuf {
+    let cfg = CONFIG.read().unwrap();
+    match cfg.get::<String>("content_store_path") {
+        Ok(path) => ::std::path::PathBuf::from(path),
+        Err(_) => {
+            let mut path = get_database_path(false);
+            path.set_file_name("content_store");
+            path
+        }
+    }
+}
+
+/// the number of latest stable mcis to keep in full, older units keep only
+/// their balls, skiplists and unspent outputs, none disables pruning
+pub fn get_pruning_retention_mci() -> Option<u32> {
+    let cfg = CONFIG.read().unwrap();
+    match cfg.get::<u32>("pruning_retention_mci") {
+        Ok(retention) if retention < MIN_PRUNING_RETENTION_MCI => {
+            warn!(
+                "pruning_retention_mci {} is too small, use {}",
+                retention, MIN_PRUNING_RETENTION_MCI
+            );
+            Some(MIN_PRUNING_RETENTION_MCI)
+        }
+        Ok(retention) => Some(retention),
+        Err(_) => None,
+    }
+}
+
 pub fn get_database_path(is_wallet: bool) -> ::std::path::PathBuf {
     use app_dirs::*;
 
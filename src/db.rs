@@ -2,6 +2,7 @@ use std::fmt;
 use std::ops::{Deref, DerefMut};
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
 use config;
 use error::Result;
@@ -39,9 +40,69 @@ fn create_database_if_necessary() -> Result<PathBuf> {
     Ok(db_path)
 }
 
+// the user_version of the initial databases, an existing database with a lower
+// version is upgraded step by step before the connection pool is created
+const DB_VERSION: u32 = 13;
+
+fn upgrade_database(conn: &mut Connection) -> Result<()> {
+    let version = conn.query_row("PRAGMA user_version", &[], |row| row.get::<_, u32>(0))?;
+    if version >= DB_VERSION {
+        return Ok(());
+    }
+    info!(
+        "upgrade database from version {} to {}",
+        version, DB_VERSION
+    );
+
+    let tx = conn.transaction()?;
+    if version < 12 {
+        // an asset may list several attestors in one message
+        let sql = tx.query_row(
+            "SELECT sql FROM sqlite_master WHERE type='table' AND name='asset_attestors'",
+            &[],
+            |row| row.get::<_, String>(0),
+        )?;
+        let sql = sql.replace(
+            "PRIMARY KEY (unit, message_index)",
+            "PRIMARY KEY (unit, message_index, attestor_address)",
+        );
+        tx.execute_batch("ALTER TABLE asset_attestors RENAME TO asset_attestors_old")?;
+        tx.execute_batch(&sql)?;
+        tx.execute_batch(
+            "INSERT INTO asset_attestors SELECT * FROM asset_attestors_old; \
+             DROP TABLE asset_attestors_old;",
+        )?;
+    }
+    if version < 13 {
+        // the hubs in pruning mode record the stripped units
+        tx.execute_batch(
+            "CREATE TABLE IF NOT EXISTS pruned_units ( \
+             unit CHAR(44) NOT NULL PRIMARY KEY, \
+             main_chain_index INT NOT NULL, \
+             creation_date TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP, \
+             FOREIGN KEY (unit) REFERENCES units(unit)); \
+             CREATE INDEX IF NOT EXISTS prunedUnitsByMci ON pruned_units(main_chain_index);",
+        )?;
+    }
+    tx.execute_batch(&format!("PRAGMA user_version={}", DB_VERSION))?;
+    tx.commit()?;
+    Ok(())
+}
+
+// the hub validates joints concurrently while saving others, in wal mode the
+// readers don't block the writer, and a busy connection waits instead of failing
+fn init_connection(conn: &Connection, is_wallet: bool) -> Result<()> {
+    if !is_wallet {
+        conn.query_row("PRAGMA journal_mode=WAL", &[], |_| ())?;
+    }
+    conn.busy_timeout(Duration::from_secs(config::DB_BUSY_TIMEOUT))?;
+    Ok(())
+}
+
 pub struct DatabasePool {
     db_rx: Receiver<Connection>,
     db_tx: Sender<Connection>,
+    size: usize,
 }
 
 impl Default for DatabasePool {
@@ -54,11 +115,16 @@ impl DatabasePool {
     pub fn new() -> Self {
         // database path
         let db_path = create_database_if_necessary().expect("create database error");
+        let mut conn = Connection::open(&db_path).expect("open database error");
+        upgrade_database(&mut conn).expect("upgrade database error");
+        drop(conn);
+        let is_wallet = IS_WALLET.load(Ordering::Relaxed);
         // create the connection pool
         let (db_tx, db_rx) = mpmc::channel();
+        let size = num_cpus::get() * 4;
 
         may::coroutine::scope(|s| {
-            for _ in 0..(num_cpus::get() * 4) {
+            for _ in 0..size {
                 go!(s, || {
                     let conn = match Connection::open_with_flags(
                         &db_path,
@@ -72,6 +138,10 @@ impl DatabasePool {
                             ::std::process::abort();
                         }
                     };
+                    if let Err(e) = init_connection(&conn, is_wallet) {
+                        error!("failed to init db connection, err={}", e);
+                        ::std::process::abort();
+                    }
 
                     db_tx.send(conn).unwrap();
                 });
@@ -79,7 +149,12 @@ impl DatabasePool {
         });
 
         info!("open database connections done");
-        DatabasePool { db_rx, db_tx }
+        DatabasePool { db_rx, db_tx, size }
+    }
+
+    /// the number of connections in the pool
+    pub fn size(&self) -> usize {
+        self.size
     }
 
     pub fn get_connection(&self) -> Database {
@@ -212,3 +287,65 @@ fn test_db() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_upgrade_asset_attestors() -> Result<()> {
+    let mut conn = Connection::open_in_memory()?;
+    conn.execute_batch(
+        "CREATE TABLE asset_attestors ( \
+         unit CHAR(44) NOT NULL, \
+         message_index TINYINT NOT NULL, \
+         asset CHAR(44) NOT NULL, \
+         attestor_address CHAR(32) NOT NULL, \
+         PRIMARY KEY (unit, message_index), \
+         UNIQUE (asset, attestor_address, unit)); \
+         INSERT INTO asset_attestors VALUES ('unit', 0, 'unit', 'ADDRESS1'); \
+         PRAGMA user_version=11;",
+    )?;
+
+    upgrade_database(&mut conn)?;
+    conn.execute(
+        "INSERT INTO asset_attestors VALUES ('unit', 0, 'unit', 'ADDRESS2')",
+        &[],
+    )?;
+    let count = conn.query_row("SELECT COUNT(*) FROM asset_attestors", &[], |row| {
+        row.get::<_, u32>(0)
+    })?;
+    assert_eq!(count, 2);
+    let version = conn.query_row("PRAGMA user_version", &[], |row| row.get::<_, u32>(0))?;
+    assert_eq!(version, DB_VERSION);
+
+    // upgrading twice is a no-op
+    upgrade_database(&mut conn)?;
+    Ok(())
+}
+
+#[test]
+fn test_upgrade_pruned_units() -> Result<()> {
+    // the table was only in the initial database of version 12
+    for init_sql in &[
+        "",
+        "CREATE TABLE pruned_units (unit CHAR(44) NOT NULL PRIMARY KEY, \
+         main_chain_index INT NOT NULL, \
+         creation_date TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP);",
+    ] {
+        let mut conn = Connection::open_in_memory()?;
+        conn.execute_batch(init_sql)?;
+        conn.execute_batch("PRAGMA user_version=12;")?;
+
+        upgrade_database(&mut conn)?;
+        conn.execute(
+            "INSERT INTO pruned_units (unit, main_chain_index) VALUES ('unit', 1)",
+            &[],
+        )?;
+        let count = conn.query_row(
+            "SELECT COUNT(*) FROM pruned_units WHERE main_chain_index<=1",
+            &[],
+            |row| row.get::<_, u32>(0),
+        )?;
+        assert_eq!(count, 1);
+        let version = conn.query_row("PRAGMA user_version", &[], |row| row.get::<_, u32>(0))?;
+        assert_eq!(version, DB_VERSION);
+    }
+    Ok(())
+}
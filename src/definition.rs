@@ -3,11 +3,13 @@ use std::collections::HashMap;
 use config;
 use error::Result;
 use failure::ResultExt;
+use object_hash;
 use rusqlite::Connection;
-use serde::Deserialize;
-use serde_json::Value;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{self, Map, Value};
 use signature;
 use spec::*;
+use storage;
 use validation::ValidationState;
 
 struct Definition<'a> {
@@ -18,7 +20,6 @@ struct Definition<'a> {
 impl<'a> Definition<'a> {
     fn from_value(value: &'a Value) -> Result<Self> {
         if !value.is_array() {
-            println!("definition={:?}", value);
             bail!("definition must be array");
         }
 
@@ -42,15 +43,433 @@ struct SigValue<'a> {
     pubkey: &'a str,
 }
 
-pub fn validate_definition(definition: &Value, is_asset: bool) -> Result<()> {
-    fn evaluate(
-        definition: &Value,
-        is_in_negation: bool,
-        is_asset: bool,
-        complexity: &mut usize,
-    ) -> Result<bool> {
-        *complexity += 1;
-        if *complexity > config::MAX_COMPLEXITY {
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RofSet {
+    required: usize,
+    set: Vec<Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WeightedValue {
+    value: Value,
+    weight: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WeightedAnd {
+    required: u64,
+    set: Vec<WeightedValue>,
+}
+
+fn get_and_or_args(args: &Value) -> Result<&Vec<Value>> {
+    let args = args
+        .as_array()
+        .ok_or_else(|| format_err!("and/or args must be array"))?;
+    ensure!(args.len() >= 2, "and/or must have at least 2 options");
+    Ok(args)
+}
+
+fn get_r_of_set_args(args: &Value) -> Result<RofSet> {
+    let args = RofSet::deserialize(args).context("can't convert to r of set")?;
+    ensure!(args.required > 0, "required must be positive");
+    ensure!(args.set.len() >= 2, "set must have at least 2 options");
+    ensure!(
+        args.required <= args.set.len(),
+        "required must be <= count of options"
+    );
+    Ok(args)
+}
+
+fn get_weighted_and_args(args: &Value) -> Result<WeightedAnd> {
+    let args = WeightedAnd::deserialize(args).context("can't convert to weighted and")?;
+    ensure!(args.required > 0, "required must be positive");
+    ensure!(args.set.len() >= 2, "set must have at least 2 options");
+    ensure!(
+        args.set.iter().all(|v| v.weight > 0),
+        "weight must be positive"
+    );
+    let total_weight: u64 = args.set.iter().map(|v| v.weight).sum();
+    ensure!(
+        args.required <= total_weight,
+        "required must be <= sum of weights"
+    );
+    Ok(args)
+}
+
+// replace the "$name" strings in the template with the corresponding params
+pub fn replace_in_template(template: &Value, params: &Map<String, Value>) -> Result<Value> {
+    match template {
+        Value::String(s) if s.starts_with('$') => {
+            let name = &s[1..];
+            params.get(name).cloned().ok_or_else(|| {
+                format_err!("variable {} not specified, template {}", name, template)
+            })
+        }
+        Value::Array(arr) => Ok(Value::Array(
+            arr.iter()
+                .map(|v| replace_in_template(v, params))
+                .collect::<Result<Vec<_>>>()?,
+        )),
+        Value::Object(obj) => {
+            let mut map = Map::new();
+            for (k, v) in obj {
+                map.insert(k.clone(), replace_in_template(v, params)?);
+            }
+            Ok(Value::Object(map))
+        }
+        v => Ok(v.clone()),
+    }
+}
+
+fn read_definition_template(db: &Connection, unit: &str, last_ball_mci: u32) -> Result<Value> {
+    let mut stmt = db.prepare_cached(
+        "SELECT payload FROM messages JOIN units USING(unit) \
+         WHERE unit=? AND app='definition_template' AND main_chain_index<=? \
+         AND +sequence='good' AND is_stable=1",
+    )?;
+    let payloads = stmt
+        .query_map(&[&unit, &last_ball_mci], |row| {
+            row.get::<_, Option<String>>(0)
+        })?
+        .collect::<::std::result::Result<Vec<_>, _>>()?;
+    if payloads.len() != 1 {
+        bail!("template not found or too many");
+    }
+    match payloads[0] {
+        Some(ref payload) => Ok(serde_json::from_str(payload)?),
+        None => bail!("template payload is not available"),
+    }
+}
+
+fn get_definition_template_args(args: &Value) -> Result<(&str, &Map<String, Value>)> {
+    let args = match args.as_array() {
+        Some(args) if args.len() == 2 => args,
+        _ => bail!("2-element array expected"),
+    };
+    let unit = args[0]
+        .as_str()
+        .ok_or_else(|| format_err!("unit must be a string"))?;
+    ensure!(unit.len() == config::HASH_LENGTH, "invalid unit");
+    let params = args[1]
+        .as_object()
+        .ok_or_else(|| format_err!("template params must be object"))?;
+    ensure!(!params.is_empty(), "empty params");
+    Ok((unit, params))
+}
+
+fn get_address_arg(args: &Value) -> Result<&str> {
+    match args.as_str() {
+        Some(address) if object_hash::is_chash_valid(address) => Ok(address),
+        _ => bail!("invalid address"),
+    }
+}
+
+struct DataFeedCondition<'a> {
+    addresses: Vec<&'a str>,
+    feed_name: &'a str,
+    relation: &'a str,
+    value: &'a Value,
+    min_mci: u32,
+}
+
+struct MerkleCondition<'a> {
+    addresses: Vec<&'a str>,
+    feed_name: &'a str,
+    element: &'a str,
+    min_mci: u32,
+}
+
+fn get_oracle_addresses(args: &Value) -> Result<Vec<&str>> {
+    let addresses = match args.as_array() {
+        Some(addresses) if !addresses.is_empty() => addresses,
+        _ => bail!("no oracle addresses"),
+    };
+    addresses.iter().map(get_address_arg).collect()
+}
+
+fn get_feed_name(args: &Value) -> Result<&str> {
+    match args.as_str() {
+        Some(feed_name) if feed_name.len() <= config::MAX_DATA_FEED_NAME_LENGTH => Ok(feed_name),
+        _ => bail!("invalid feed name"),
+    }
+}
+
+fn get_min_mci(args: Option<&Value>) -> Result<u32> {
+    match args {
+        None => Ok(0),
+        Some(min_mci) => match min_mci.as_u64() {
+            Some(min_mci) if min_mci <= u64::from(::std::u32::MAX) => Ok(min_mci as u32),
+            _ => bail!("invalid min_mci"),
+        },
+    }
+}
+
+// ["in data feed", [[oracle addresses], feed_name, relation, value, min_mci]]
+fn get_in_data_feed_args(args: &Value) -> Result<DataFeedCondition> {
+    let args = match args.as_array() {
+        Some(args) if args.len() == 4 || args.len() == 5 => args,
+        _ => bail!("in data feed must have 4 or 5 args"),
+    };
+    let relation = match args[2].as_str() {
+        Some(relation) if ["=", ">", "<", ">=", "<=", "!="].contains(&relation) => relation,
+        _ => bail!("invalid relation: {}", args[2]),
+    };
+    let value = &args[3];
+    match value {
+        Value::String(s) => ensure!(
+            s.len() <= config::MAX_DATA_FEED_VALUE_LENGTH,
+            "value too long"
+        ),
+        Value::Number(n) => ensure!(n.is_i64(), "value must be integer"),
+        _ => bail!("invalid value"),
+    }
+
+    Ok(DataFeedCondition {
+        addresses: get_oracle_addresses(&args[0])?,
+        feed_name: get_feed_name(&args[1])?,
+        relation,
+        value,
+        min_mci: get_min_mci(args.get(4))?,
+    })
+}
+
+// ["in merkle", [[oracle addresses], feed_name, element, min_mci]]
+fn get_in_merkle_args(args: &Value) -> Result<MerkleCondition> {
+    let args = match args.as_array() {
+        Some(args) if args.len() == 3 || args.len() == 4 => args,
+        _ => bail!("in merkle must have 3 or 4 args"),
+    };
+    let element = args[2]
+        .as_str()
+        .ok_or_else(|| format_err!("element must be string"))?;
+
+    Ok(MerkleCondition {
+        addresses: get_oracle_addresses(&args[0])?,
+        feed_name: get_feed_name(&args[1])?,
+        element,
+        min_mci: get_min_mci(args.get(3))?,
+    })
+}
+
+/// filter on the inputs or outputs of a unit, used by "has", "has one", "sum" and "seen"
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Filter {
+    pub what: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub asset: Option<String>,
+    #[serde(rename = "type")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub own_funds: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount_at_least: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount_at_most: Option<i64>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SumArgs {
+    filter: Value,
+    equals: Option<i64>,
+    at_least: Option<i64>,
+    at_most: Option<i64>,
+}
+
+fn get_filter_args(args: &Value) -> Result<Filter> {
+    let filter = Filter::deserialize(args).context("can't convert to filter")?;
+    ensure!(
+        filter.what == "input" || filter.what == "output",
+        "invalid what={}",
+        filter.what
+    );
+    if let Some(ref asset) = filter.asset {
+        ensure!(
+            asset == "base" || asset == "this asset" || asset.len() == config::HASH_LENGTH,
+            "invalid asset: {}",
+            asset
+        );
+    }
+    if let Some(ref kind) = filter.kind {
+        ensure!(filter.what == "input", "type allowed only for inputs");
+        ensure!(
+            kind == "transfer" || kind == "issue",
+            "invalid type: {}",
+            kind
+        );
+    }
+    if filter.own_funds.is_some() {
+        ensure!(filter.what == "input", "own_funds allowed only for inputs");
+    }
+    if let Some(ref address) = filter.address {
+        ensure!(
+            address == "this address"
+                || address == "other address"
+                || object_hash::is_chash_valid(address),
+            "invalid address: {}",
+            address
+        );
+    }
+    for amount in &[filter.amount, filter.amount_at_least, filter.amount_at_most] {
+        if let Some(amount) = *amount {
+            ensure!(amount > 0, "amount must be positive");
+        }
+    }
+    if filter.amount.is_some() {
+        ensure!(
+            filter.amount_at_least.is_none() && filter.amount_at_most.is_none(),
+            "can't have amount and amount_at_least/most at the same time"
+        );
+    }
+    if let (Some(at_least), Some(at_most)) = (filter.amount_at_least, filter.amount_at_most) {
+        ensure!(at_least <= at_most, "amount_at_least > amount_at_most");
+    }
+    Ok(filter)
+}
+
+fn get_sum_args(args: &Value) -> Result<(Filter, SumArgs)> {
+    let args = SumArgs::deserialize(args).context("can't convert to sum")?;
+    let filter = get_filter_args(&args.filter)?;
+    ensure!(filter.what == "output", "sum is allowed only for outputs");
+    if let Some(equals) = args.equals {
+        ensure!(equals >= 0, "equals must be non-negative");
+        ensure!(
+            args.at_least.is_none() && args.at_most.is_none(),
+            "can't have equals and at_least/at_most at the same time"
+        );
+    } else {
+        ensure!(
+            args.at_least.is_some() || args.at_most.is_some(),
+            "at least one of equals, at_least, at_most must be specified"
+        );
+    }
+    if let (Some(at_least), Some(at_most)) = (args.at_least, args.at_most) {
+        ensure!(at_least <= at_most, "at_least > at_most");
+    }
+    Ok((filter, args))
+}
+
+// ["mci", [relation, value]] or ["timestamp", [relation, value]]
+fn get_comparison_args(args: &Value) -> Result<(&str, u64)> {
+    let args = match args.as_array() {
+        Some(args) if args.len() == 2 => args,
+        _ => bail!("comparison must be 2-element array"),
+    };
+    let relation = match args[0].as_str() {
+        Some(relation) if ["=", ">", "<", ">=", "<=", "!="].contains(&relation) => relation,
+        _ => bail!("invalid relation: {}", args[0]),
+    };
+    let value = args[1]
+        .as_u64()
+        .ok_or_else(|| format_err!("comparison value must be a non-negative integer"))?;
+    Ok((relation, value))
+}
+
+fn is_amount_matched(filter: &Filter, amount: i64) -> bool {
+    if let Some(expected) = filter.amount {
+        if amount != expected {
+            return false;
+        }
+    }
+    if let Some(at_least) = filter.amount_at_least {
+        if amount < at_least {
+            return false;
+        }
+    }
+    if let Some(at_most) = filter.amount_at_most {
+        if amount > at_most {
+            return false;
+        }
+    }
+    true
+}
+
+fn compare(x: u64, relation: &str, value: u64) -> bool {
+    match relation {
+        "=" => x == value,
+        ">" => x > value,
+        "<" => x < value,
+        ">=" => x >= value,
+        "<=" => x <= value,
+        "!=" => x != value,
+        _ => unreachable!("relation already validated"),
+    }
+}
+
+// check if any of the oracles has posted a stable feed matching the value condition
+fn is_data_feed_posted(
+    db: &Connection,
+    addresses: &[&str],
+    feed_name: &str,
+    value_condition: &str,
+    value: &ToSql,
+    max_mci: u32,
+    min_mci: u32,
+) -> Result<bool> {
+    let addresses_list = addresses
+        .iter()
+        .map(|s| format!("'{}'", s))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let sql = format!(
+        "SELECT 1 FROM data_feeds CROSS JOIN units USING(unit) CROSS JOIN unit_authors USING(unit) \
+         WHERE address IN({}) AND feed_name=? AND {} \
+         AND main_chain_index<=? AND main_chain_index>=? AND sequence='good' AND is_stable=1 \
+         LIMIT 1",
+        addresses_list, value_condition
+    );
+    let mut stmt = db.prepare(&sql)?;
+    Ok(stmt.exists(&[&feed_name, value, &max_mci, &min_mci])?)
+}
+
+// find the definition of the address, either stable as of last ball mci
+// or defined by one of the authors of the current unit
+fn read_inner_definition(
+    db: &Connection,
+    unit: &Unit,
+    address: &str,
+    last_ball_mci: u32,
+) -> Result<Option<Value>> {
+    let definition_chash =
+        match storage::read_definition_by_address(db, &address.to_owned(), Some(last_ball_mci))? {
+            Ok(definition) => return Ok(Some(definition)),
+            Err(definition_chash) => definition_chash,
+        };
+
+    for author in &unit.authors {
+        if author.address == address
+            && !author.definition.is_null()
+            && object_hash::get_chash(&author.definition)? == definition_chash
+        {
+            return Ok(Some(author.definition.clone()));
+        }
+    }
+
+    Ok(None)
+}
+
+struct DefinitionValidator<'a> {
+    db: &'a Connection,
+    unit: &'a Unit,
+    validate_state: &'a ValidationState,
+    is_asset: bool,
+    complexity: usize,
+}
+
+impl<'a> DefinitionValidator<'a> {
+    // return true if the definition has a sig in each branch
+    fn evaluate(&mut self, definition: &Value, is_in_negation: bool) -> Result<bool> {
+        self.complexity += 1;
+        if self.complexity > config::MAX_COMPLEXITY {
             bail!("complexity exceeded");
         }
 
@@ -61,7 +480,7 @@ pub fn validate_definition(definition: &Value, is_asset: bool) -> Result<()> {
                 if is_in_negation {
                     bail!("sig cannot be negated");
                 }
-                if is_asset {
+                if self.is_asset {
                     bail!("asset condition cannot have sig");
                 }
 
@@ -76,14 +495,149 @@ pub fn validate_definition(definition: &Value, is_asset: bool) -> Result<()> {
                     sig_value.pubkey.len() == config::HASH_LENGTH,
                     "wrong pubkey length"
                 );
+                Ok(true)
+            }
+            "or" | "and" => {
+                let args = get_and_or_args(definition.args)?;
+                let mut count_options_with_sig = 0;
+                for arg in args {
+                    if self.evaluate(arg, is_in_negation)? {
+                        count_options_with_sig += 1;
+                    }
+                }
+                if definition.op == "and" {
+                    Ok(count_options_with_sig > 0)
+                } else {
+                    Ok(count_options_with_sig == args.len())
+                }
+            }
+            "r of set" => {
+                let args = get_r_of_set_args(definition.args)?;
+                let mut count_options_with_sig = 0;
+                for arg in &args.set {
+                    if self.evaluate(arg, is_in_negation)? {
+                        count_options_with_sig += 1;
+                    }
+                }
+                // any combination of required options must contain a sig
+                let count_options_without_sig = args.set.len() - count_options_with_sig;
+                Ok(args.required > count_options_without_sig)
+            }
+            "weighted and" => {
+                let args = get_weighted_and_args(definition.args)?;
+                let mut total_weight = 0;
+                let mut weight_of_options_with_sig = 0;
+                for arg in &args.set {
+                    total_weight += arg.weight;
+                    if self.evaluate(&arg.value, is_in_negation)? {
+                        weight_of_options_with_sig += arg.weight;
+                    }
+                }
+                let weight_of_options_without_sig = total_weight - weight_of_options_with_sig;
+                Ok(args.required > weight_of_options_without_sig)
+            }
+            "address" => {
+                if self.validate_state.has_no_references {
+                    bail!("no references allowed in address definition");
+                }
+                if is_in_negation {
+                    bail!("address cannot be negated");
+                }
+                if self.is_asset {
+                    bail!("asset condition cannot have address");
+                }
+                let address = get_address_arg(definition.args)?;
+                let inner_definition = read_inner_definition(
+                    self.db,
+                    self.unit,
+                    address,
+                    self.validate_state.last_ball_mci,
+                )?
+                .ok_or_else(|| format_err!("definition of inner address {} not found", address))?;
+                self.evaluate(&inner_definition, is_in_negation)
+            }
+            "definition template" => {
+                if self.validate_state.has_no_references {
+                    bail!("no references allowed in address definition");
+                }
+                if is_in_negation {
+                    bail!("definition template cannot be negated");
+                }
+                let (unit, params) = get_definition_template_args(definition.args)?;
+                let template =
+                    read_definition_template(self.db, unit, self.validate_state.last_ball_mci)?;
+                let filled_template = replace_in_template(&template, params)?;
+                self.evaluate(&filled_template, is_in_negation)
+            }
+            "cosigned by" => {
+                if is_in_negation {
+                    bail!("cosigned by cannot be negated");
+                }
+                get_address_arg(definition.args)?;
+                Ok(false)
+            }
+            "in data feed" => {
+                if self.validate_state.has_no_references {
+                    bail!("no references allowed in address definition");
+                }
+                get_in_data_feed_args(definition.args)?;
+                Ok(false)
+            }
+            "in merkle" => {
+                if self.validate_state.has_no_references {
+                    bail!("no references allowed in address definition");
+                }
+                get_in_merkle_args(definition.args)?;
+                Ok(false)
+            }
+            "mci" | "timestamp" => {
+                if self.validate_state.has_no_references {
+                    bail!("no references allowed in address definition");
+                }
+                get_comparison_args(definition.args)?;
+                Ok(false)
+            }
+            "has" | "has one" | "seen" => {
+                if self.validate_state.has_no_references {
+                    bail!("no references allowed in address definition");
+                }
+                get_filter_args(definition.args)?;
+                Ok(false)
             }
-            op => unimplemented!("unsupported op: {}", op),
+            "sum" => {
+                if self.validate_state.has_no_references {
+                    bail!("no references allowed in address definition");
+                }
+                get_sum_args(definition.args)?;
+                Ok(false)
+            }
+            "seen address" => {
+                if self.validate_state.has_no_references {
+                    bail!("no references allowed in address definition");
+                }
+                get_address_arg(definition.args)?;
+                Ok(false)
+            }
+            op => bail!("unknown op: {}", op),
         }
-        Ok(true)
     }
+}
 
-    let mut complexity = 0;
-    let has_sig = evaluate(definition, false, is_asset, &mut complexity)?;
+pub fn validate_definition(
+    db: &Connection,
+    definition: &Value,
+    unit: &Unit,
+    validate_state: &ValidationState,
+    is_asset: bool,
+) -> Result<()> {
+    let mut validator = DefinitionValidator {
+        db,
+        unit,
+        validate_state,
+        is_asset,
+        complexity: 0,
+    };
+    let has_sig = validator.evaluate(definition, false)?;
 
     if !has_sig && !is_asset {
         bail!("each branch must have a signature");
@@ -92,65 +646,1311 @@ pub fn validate_definition(definition: &Value, is_asset: bool) -> Result<()> {
     Ok(())
 }
 
-pub fn validate_authentifiers(
-    _db: &Connection,
-    _address: &str,
-    asset: &Value,
-    definition: &Value,
-    _unit: &Unit,
-    validate_state: &mut ValidationState,
-    authentifiers: &HashMap<String, String>,
-) -> Result<()> {
-    let evaluate = |definition: &Value, path: &str, used_path: &mut Vec<String>| -> Result<()> {
+struct AuthentifierVerifier<'a> {
+    db: &'a Connection,
+    address: &'a str,
+    asset: &'a Value,
+    unit: &'a Unit,
+    validate_state: &'a ValidationState,
+    authentifiers: &'a HashMap<String, String>,
+    used_path: Vec<String>,
+    dry_run: Option<DryRun<'a>>,
+}
+
+struct DryRun<'a> {
+    signers: &'a [String],
+    report: DryRunReport,
+}
+
+/// why a branch of the definition is not satisfied
+#[derive(Debug, Clone, Serialize)]
+pub struct BranchFailure {
+    pub path: String,
+    pub reason: String,
+}
+
+/// result of evaluating a definition without signatures
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct DryRunReport {
+    pub is_satisfied: bool,
+    /// all the authentifier paths the definition may ask for
+    pub required_paths: Vec<String>,
+    /// the authentifier paths that the available signers can provide
+    pub satisfied_paths: Vec<String>,
+    pub failures: Vec<BranchFailure>,
+}
+
+impl<'a> AuthentifierVerifier<'a> {
+    fn record_path(&mut self, path: &str, is_satisfied: bool) {
+        if let Some(ref mut dry_run) = self.dry_run {
+            dry_run.report.required_paths.push(path.to_owned());
+            if is_satisfied {
+                dry_run.report.satisfied_paths.push(path.to_owned());
+            }
+        }
+    }
+
+    fn explain_failure(&mut self, path: &str, reason: String) {
+        if let Some(ref mut dry_run) = self.dry_run {
+            dry_run.report.failures.push(BranchFailure {
+                path: path.to_owned(),
+                reason,
+            });
+        }
+    }
+
+    fn is_address_matched(&self, filter_address: &str, address: &str) -> bool {
+        match filter_address {
+            "this address" => address == self.address,
+            "other address" => address != self.address,
+            filter_address => address == filter_address,
+        }
+    }
+
+    fn is_asset_matched(&self, filter_asset: &Option<String>, asset: &Option<String>) -> bool {
+        match filter_asset.as_ref().map(String::as_str) {
+            None => true,
+            Some("base") => asset.is_none(),
+            Some("this asset") => asset.as_ref().map(String::as_str) == self.asset.as_str(),
+            filter_asset => asset.as_ref().map(String::as_str) == filter_asset,
+        }
+    }
+
+    // return the address that owns the funds spent by the input
+    fn read_input_address(&self, input: &Input) -> Result<Option<String>> {
+        match input.kind.as_ref().map(String::as_str) {
+            Some("issue") => Ok(input.address.clone().or_else(|| {
+                some_if!(
+                    self.unit.authors.len() == 1,
+                    self.unit.authors[0].address.clone()
+                )
+            })),
+            _ => {
+                let mut stmt = self.db.prepare_cached(
+                    "SELECT address FROM outputs \
+                     WHERE unit=? AND message_index=? AND output_index=?",
+                )?;
+                let addresses = stmt
+                    .query_map(
+                        &[&input.unit, &input.message_index, &input.output_index],
+                        |row| row.get::<_, String>(0),
+                    )?
+                    .collect::<::std::result::Result<Vec<_>, _>>()?;
+                Ok(addresses.into_iter().next())
+            }
+        }
+    }
+
+    // return amounts of the inputs or outputs of this unit that match the filter
+    fn evaluate_filter(&self, filter: &Filter) -> Result<Vec<i64>> {
+        let mut amounts = Vec::new();
+        for message in &self.unit.messages {
+            let payment = match message.payload {
+                Some(Payload::Payment(ref payment)) if message.app == "payment" => payment,
+                _ => continue,
+            };
+            if !self.is_asset_matched(&filter.asset, &payment.asset) {
+                continue;
+            }
+
+            if filter.what == "input" {
+                for input in &payment.inputs {
+                    let kind = input
+                        .kind
+                        .as_ref()
+                        .map(String::as_str)
+                        .unwrap_or("transfer");
+                    if kind == "headers_commission" || kind == "witnessing" {
+                        continue;
+                    }
+                    if let Some(ref filter_kind) = filter.kind {
+                        if kind != filter_kind {
+                            continue;
+                        }
+                    }
+                    if filter.address.is_some() || filter.own_funds.is_some() {
+                        let input_address = match self.read_input_address(input)? {
+                            Some(address) => address,
+                            None => continue,
+                        };
+                        if let Some(ref filter_address) = filter.address {
+                            if !self.is_address_matched(filter_address, &input_address) {
+                                continue;
+                            }
+                        }
+                        if let Some(own_funds) = filter.own_funds {
+                            if own_funds != (input_address == self.address) {
+                                continue;
+                            }
+                        }
+                    }
+                    let amount = input.amount.unwrap_or(0);
+                    if !is_amount_matched(filter, amount) {
+                        continue;
+                    }
+                    amounts.push(amount);
+                }
+            } else {
+                for output in &payment.outputs {
+                    if let Some(ref filter_address) = filter.address {
+                        if !self.is_address_matched(filter_address, &output.address) {
+                            continue;
+                        }
+                    }
+                    if !is_amount_matched(filter, output.amount) {
+                        continue;
+                    }
+                    amounts.push(output.amount);
+                }
+            }
+        }
+        Ok(amounts)
+    }
+
+    // check if anything matching the filter is already stable as of last ball
+    fn is_seen(&self, filter: &Filter) -> Result<bool> {
+        let mut sql = format!(
+            "SELECT 1 FROM {}s CROSS JOIN units USING(unit) \
+             LEFT JOIN assets ON asset=assets.unit \
+             WHERE main_chain_index<=? AND sequence='good' AND is_stable=1 \
+             AND (asset IS NULL OR is_private=0)",
+            filter.what
+        );
+        let this_asset = self.asset.as_str();
+        let mut params: Vec<&ToSql> = vec![&self.validate_state.last_ball_mci];
+        match filter.asset.as_ref().map(String::as_str) {
+            None => {}
+            Some("base") => sql.push_str(" AND asset IS NULL"),
+            Some("this asset") => {
+                sql.push_str(" AND asset=?");
+                params.push(&this_asset);
+            }
+            Some(_) => {
+                sql.push_str(" AND asset=?");
+                params.push(&filter.asset);
+            }
+        }
+        if let Some(ref kind) = filter.kind {
+            sql.push_str(" AND type=?");
+            params.push(kind);
+        }
+        match filter.address.as_ref().map(String::as_str) {
+            None => {}
+            Some("this address") => {
+                sql.push_str(" AND address=?");
+                params.push(&self.address);
+            }
+            Some("other address") => {
+                sql.push_str(" AND address!=?");
+                params.push(&self.address);
+            }
+            Some(_) => {
+                sql.push_str(" AND address=?");
+                params.push(&filter.address);
+            }
+        }
+        if let Some(ref amount) = filter.amount {
+            sql.push_str(" AND amount=?");
+            params.push(amount);
+        }
+        if let Some(ref amount) = filter.amount_at_least {
+            sql.push_str(" AND amount>=?");
+            params.push(amount);
+        }
+        if let Some(ref amount) = filter.amount_at_most {
+            sql.push_str(" AND amount<=?");
+            params.push(amount);
+        }
+        sql.push_str(" LIMIT 1");
+
+        let mut stmt = self.db.prepare(&sql)?;
+        Ok(stmt.exists(&params)?)
+    }
+
+    fn evaluate(&mut self, definition: &Value, path: &str) -> Result<bool> {
+        let res = self.evaluate_op(definition, path)?;
+        if !res && self.dry_run.is_some() {
+            let definition = Definition::from_value(definition)?;
+            match definition.op {
+                // these ops explain the failure by themselves
+                "sig"
+                | "or"
+                | "and"
+                | "r of set"
+                | "weighted and"
+                | "address"
+                | "definition template" => {}
+                op => self.explain_failure(
+                    path,
+                    format!("condition {} {} is not met", op, definition.args),
+                ),
+            }
+        }
+        Ok(res)
+    }
+
+    fn evaluate_op(&mut self, definition: &Value, path: &str) -> Result<bool> {
         let definition = Definition::from_value(definition)?;
         match definition.op {
+            "sig" if self.dry_run.is_some() => {
+                let sig_value =
+                    SigValue::deserialize(definition.args).context("can't conver to SigValue")?;
+                let is_satisfied = self
+                    .dry_run
+                    .as_ref()
+                    .map(|dry_run| dry_run.signers.iter().any(|s| s == sig_value.pubkey))
+                    .unwrap_or(false);
+                self.record_path(path, is_satisfied);
+                if !is_satisfied {
+                    self.explain_failure(
+                        path,
+                        format!("missing signature of pubkey {}", sig_value.pubkey),
+                    );
+                }
+                Ok(is_satisfied)
+            }
             "sig" => {
-                let sig = authentifiers
-                    .get(path)
-                    .ok_or_else(|| format_err!("authentifier path not found: {}", path))?;
-                used_path.push(path.to_owned());
+                let sig = match self.authentifiers.get(path) {
+                    Some(sig) => sig,
+                    None => return Ok(false),
+                };
+                self.used_path.push(path.to_owned());
 
-                if validate_state.unsigned && sig.starts_with('-') {
-                    return Ok(());
+                if self.validate_state.unsigned && sig.starts_with('-') {
+                    return Ok(true);
                 }
 
                 let sig_value =
                     SigValue::deserialize(definition.args).context("can't conver to SigValue")?;
-                let unit_hash = validate_state
+                let unit_hash = self
+                    .validate_state
                     .unit_hash_to_sign
                     .as_ref()
                     .expect("no unit hash to sign found");
 
                 signature::verify(unit_hash, sig, sig_value.pubkey)
                     .context(format!("bad signature at path: {:?}", path))?;
+                Ok(true)
+            }
+            "or" => {
+                // evaluate all the branches to collect the used paths
+                let mut res = false;
+                for (i, arg) in get_and_or_args(definition.args)?.iter().enumerate() {
+                    res = self.evaluate(arg, &format!("{}.{}", path, i))? || res;
+                }
+                if !res {
+                    self.explain_failure(path, "none of the options is satisfied".to_owned());
+                }
+                Ok(res)
+            }
+            "and" => {
+                let args = get_and_or_args(definition.args)?;
+                let mut count_satisfied = 0;
+                for (i, arg) in args.iter().enumerate() {
+                    if self.evaluate(arg, &format!("{}.{}", path, i))? {
+                        count_satisfied += 1;
+                    }
+                }
+                let res = count_satisfied == args.len();
+                if !res {
+                    self.explain_failure(
+                        path,
+                        format!(
+                            "{} of {} options satisfied, all required",
+                            count_satisfied,
+                            args.len()
+                        ),
+                    );
+                }
+                Ok(res)
+            }
+            "r of set" => {
+                let args = get_r_of_set_args(definition.args)?;
+                let mut count_satisfied = 0;
+                for (i, arg) in args.set.iter().enumerate() {
+                    if self.evaluate(arg, &format!("{}.{}", path, i))? {
+                        count_satisfied += 1;
+                    }
+                }
+                let res = count_satisfied >= args.required;
+                if !res {
+                    self.explain_failure(
+                        path,
+                        format!(
+                            "{} of {} options satisfied, {} required",
+                            count_satisfied,
+                            args.set.len(),
+                            args.required
+                        ),
+                    );
+                }
+                Ok(res)
+            }
+            "weighted and" => {
+                let args = get_weighted_and_args(definition.args)?;
+                let mut weight = 0;
+                for (i, arg) in args.set.iter().enumerate() {
+                    if self.evaluate(&arg.value, &format!("{}.{}", path, i))? {
+                        weight += arg.weight;
+                    }
+                }
+                let res = weight >= args.required;
+                if !res {
+                    self.explain_failure(
+                        path,
+                        format!("weight {} satisfied, {} required", weight, args.required),
+                    );
+                }
+                Ok(res)
+            }
+            "address" => {
+                let address = get_address_arg(definition.args)?;
+                match read_inner_definition(
+                    self.db,
+                    self.unit,
+                    address,
+                    self.validate_state.last_ball_mci,
+                )? {
+                    // the inner definition shares the same path
+                    Some(inner_definition) => self.evaluate(&inner_definition, path),
+                    None => {
+                        self.explain_failure(
+                            path,
+                            format!("definition of address {} not found", address),
+                        );
+                        Ok(false)
+                    }
+                }
+            }
+            "definition template" => {
+                let (unit, params) = get_definition_template_args(definition.args)?;
+                let template =
+                    read_definition_template(self.db, unit, self.validate_state.last_ball_mci)?;
+                let filled_template = replace_in_template(&template, params)?;
+                self.evaluate(&filled_template, path)
+            }
+            "cosigned by" => {
+                let cosigner_address = get_address_arg(definition.args)?;
+                Ok(self
+                    .unit
+                    .authors
+                    .iter()
+                    .any(|author| author.address == cosigner_address))
+            }
+            "in data feed" => {
+                let args = get_in_data_feed_args(definition.args)?;
+                let value_condition = match args.value {
+                    Value::String(_) => format!("value{}?", args.relation),
+                    _ => format!("int_value{}?", args.relation),
+                };
+                let int_value = args.value.as_i64();
+                let value: &ToSql = match args.value {
+                    Value::String(s) => s,
+                    _ => &int_value,
+                };
+                is_data_feed_posted(
+                    self.db,
+                    &args.addresses,
+                    args.feed_name,
+                    &value_condition,
+                    value,
+                    self.validate_state.last_ball_mci,
+                    args.min_mci,
+                )
+            }
+            "in merkle" => {
+                let serialized_proof = match self.authentifiers.get(path) {
+                    Some(proof) => proof,
+                    None => {
+                        self.record_path(path, false);
+                        return Ok(false);
+                    }
+                };
+                self.used_path.push(path.to_owned());
+
+                let args = get_in_merkle_args(definition.args)?;
+                let proof = merkle::deserialize_merkle_proof(serialized_proof)?;
+                ensure!(
+                    merkle::verify_merkle_proof(args.element, &proof),
+                    "bad merkle proof at path {}",
+                    path
+                );
+                // the oracle posts merkle root as the feed value
+                is_data_feed_posted(
+                    self.db,
+                    &args.addresses,
+                    args.feed_name,
+                    "value=?",
+                    &proof.root,
+                    self.validate_state.last_ball_mci,
+                    args.min_mci,
+                )
+            }
+            "mci" => {
+                let (relation, value) = get_comparison_args(definition.args)?;
+                let mci = u64::from(self.validate_state.last_ball_mci);
+                Ok(compare(mci, relation, value))
+            }
+            "timestamp" => {
+                let (relation, value) = get_comparison_args(definition.args)?;
+                Ok(compare(
+                    self.validate_state.last_ball_timestamp,
+                    relation,
+                    value,
+                ))
+            }
+            "has" => {
+                let filter = get_filter_args(definition.args)?;
+                Ok(!self.evaluate_filter(&filter)?.is_empty())
+            }
+            "has one" => {
+                let filter = get_filter_args(definition.args)?;
+                Ok(self.evaluate_filter(&filter)?.len() == 1)
             }
-            op => unimplemented!("unsupported op: {}", op),
+            "sum" => {
+                let (filter, args) = get_sum_args(definition.args)?;
+                let sum: i64 = self.evaluate_filter(&filter)?.iter().sum();
+                if let Some(equals) = args.equals {
+                    return Ok(sum == equals);
+                }
+                if let Some(at_least) = args.at_least {
+                    if sum < at_least {
+                        return Ok(false);
+                    }
+                }
+                if let Some(at_most) = args.at_most {
+                    if sum > at_most {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            "seen address" => {
+                let address = get_address_arg(definition.args)?;
+                let mut stmt = self.db.prepare_cached(
+                    "SELECT 1 FROM unit_authors CROSS JOIN units USING(unit) \
+                     WHERE address=? AND main_chain_index<=? AND sequence='good' AND is_stable=1 \
+                     LIMIT 1",
+                )?;
+                Ok(stmt.exists(&[&address, &self.validate_state.last_ball_mci])?)
+            }
+            "seen" => {
+                let filter = get_filter_args(definition.args)?;
+                self.is_seen(&filter)
+            }
+            op => bail!("unknown op: {}", op),
         }
-        Ok(())
-    };
+    }
+}
 
+pub fn validate_authentifiers(
+    db: &Connection,
+    address: &str,
+    asset: &Value,
+    definition: &Value,
+    unit: &Unit,
+    validate_state: &mut ValidationState,
+    authentifiers: &HashMap<String, String>,
+) -> Result<()> {
+    // asset conditions are evaluated without authentifiers and on behalf of no address
     let is_asset = authentifiers.is_empty();
-    if is_asset && !asset.is_null() {
+    if is_asset && !address.is_empty() || !is_asset && !asset.is_null() {
         bail!("incompatible params");
     }
-    validate_definition(definition, is_asset)?;
-    let mut used_path = Vec::new();
-    evaluate(definition, "r", &mut used_path)?;
-    if !is_asset && used_path.len() != authentifiers.len() {
+    validate_definition(db, definition, unit, validate_state, is_asset)?;
+
+    let mut verifier = AuthentifierVerifier {
+        db,
+        address,
+        asset,
+        unit,
+        validate_state,
+        authentifiers,
+        used_path: Vec::new(),
+        dry_run: None,
+    };
+    let res = verifier.evaluate(definition, "r")?;
+    if !is_asset && verifier.used_path.len() != authentifiers.len() {
         bail!(
             "some authentifiers are not used, used={:?}, passed={:?}",
-            used_path,
+            verifier.used_path,
             authentifiers
         );
     }
+    ensure!(res, "authentifier verification failed");
     Ok(())
 }
 
+/// evaluate the definition against a candidate unit as if it was signed by
+/// the available signers (pubkeys), and report which paths are satisfied
+/// and why the unsatisfied branches fail
+pub fn dry_run_authentifiers(
+    db: &Connection,
+    address: &str,
+    definition: &Value,
+    unit: &Unit,
+    signers: &[String],
+) -> Result<DryRunReport> {
+    let mut validate_state = ValidationState::new();
+    validate_state.last_ball_mci = storage::read_last_stable_mc_index(db)?;
+    validate_state.last_ball_timestamp = ::time::now() / 1000;
+    validate_definition(db, definition, unit, &validate_state, false)?;
+
+    let authentifiers = HashMap::new();
+    let mut verifier = AuthentifierVerifier {
+        db,
+        address,
+        asset: &Value::Null,
+        unit,
+        validate_state: &validate_state,
+        authentifiers: &authentifiers,
+        used_path: Vec::new(),
+        dry_run: Some(DryRun {
+            signers,
+            report: DryRunReport::default(),
+        }),
+    };
+    let is_satisfied = verifier.evaluate(definition, "r")?;
+    let mut report = verifier.dry_run.take().unwrap().report;
+    report.is_satisfied = is_satisfied;
+    Ok(report)
+}
+
+struct OfflineVerifier<'a> {
+    authentifiers: &'a HashMap<String, String>,
+    unit_hash_to_sign: &'a [u8],
+    used_path: Vec<String>,
+    has_unknown_branch: bool,
+}
+
+// none means the branch needs the dag to be evaluated
+fn reach_threshold(satisfied: u64, unknown: u64, required: u64) -> Option<bool> {
+    if satisfied >= required {
+        Some(true)
+    } else if satisfied + unknown < required {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+impl<'a> OfflineVerifier<'a> {
+    fn evaluate(&mut self, definition: &Value, path: &str) -> Result<Option<bool>> {
+        let definition = Definition::from_value(definition)?;
+        match definition.op {
+            "sig" => {
+                let sig = match self.authentifiers.get(path) {
+                    Some(sig) => sig,
+                    None => return Ok(Some(false)),
+                };
+                self.used_path.push(path.to_owned());
+
+                let sig_value =
+                    SigValue::deserialize(definition.args).context("can't convert to SigValue")?;
+                signature::verify(self.unit_hash_to_sign, sig, sig_value.pubkey)
+                    .context(format!("bad signature at path: {:?}", path))?;
+                Ok(Some(true))
+            }
+            "or" => {
+                let args = get_and_or_args(definition.args)?;
+                let mut res = Some(false);
+                for (i, arg) in args.iter().enumerate() {
+                    match self.evaluate(arg, &format!("{}.{}", path, i))? {
+                        Some(true) => res = Some(true),
+                        None if res == Some(false) => res = None,
+                        _ => {}
+                    }
+                }
+                Ok(res)
+            }
+            "and" => {
+                let args = get_and_or_args(definition.args)?;
+                let mut res = Some(true);
+                for (i, arg) in args.iter().enumerate() {
+                    match self.evaluate(arg, &format!("{}.{}", path, i))? {
+                        Some(false) => res = Some(false),
+                        None if res == Some(true) => res = None,
+                        _ => {}
+                    }
+                }
+                Ok(res)
+            }
+            "r of set" => {
+                let args = get_r_of_set_args(definition.args)?;
+                let (mut satisfied, mut unknown) = (0, 0);
+                for (i, arg) in args.set.iter().enumerate() {
+                    match self.evaluate(arg, &format!("{}.{}", path, i))? {
+                        Some(true) => satisfied += 1,
+                        None => unknown += 1,
+                        Some(false) => {}
+                    }
+                }
+                Ok(reach_threshold(satisfied, unknown, args.required as u64))
+            }
+            "weighted and" => {
+                let args = get_weighted_and_args(definition.args)?;
+                let (mut satisfied, mut unknown) = (0, 0);
+                for (i, arg) in args.set.iter().enumerate() {
+                    match self.evaluate(&arg.value, &format!("{}.{}", path, i))? {
+                        Some(true) => satisfied += arg.weight,
+                        None => unknown += arg.weight,
+                        Some(false) => {}
+                    }
+                }
+                Ok(reach_threshold(satisfied, unknown, args.required))
+            }
+            _ => {
+                self.has_unknown_branch = true;
+                Ok(None)
+            }
+        }
+    }
+}
+
+/// verify the signatures of a definition without the db, only the sig,
+/// and/or, r of set and weighted and ops are evaluated, returns false if
+/// the result depends on other ops
+pub fn verify_authentifiers_offline(
+    definition: &Value,
+    authentifiers: &HashMap<String, String>,
+    unit_hash_to_sign: &[u8],
+) -> Result<bool> {
+    let mut verifier = OfflineVerifier {
+        authentifiers,
+        unit_hash_to_sign,
+        used_path: Vec::new(),
+        has_unknown_branch: false,
+    };
+    let res = verifier.evaluate(definition, "r")?;
+    // authentifiers for the unknown branches are not accounted
+    if !verifier.has_unknown_branch && verifier.used_path.len() != authentifiers.len() {
+        bail!(
+            "some authentifiers are not used, used={:?}, passed={:?}",
+            verifier.used_path,
+            authentifiers
+        );
+    }
+    match res {
+        Some(res) => {
+            ensure!(res, "authentifier verification failed");
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+fn collect_sig_pairs<'a>(
+    definition: &'a Value,
+    path: &str,
+    authentifiers: &'a HashMap<String, String>,
+    pairs: &mut Vec<(&'a str, &'a str)>,
+) {
+    let definition = match Definition::from_value(definition) {
+        Ok(definition) => definition,
+        Err(_) => return,
+    };
+    let args = match definition.op {
+        "sig" => {
+            if let (Some(sig), Ok(sig_value)) = (
+                authentifiers.get(path),
+                SigValue::deserialize(definition.args),
+            ) {
+                pairs.push((sig.as_str(), sig_value.pubkey));
+            }
+            return;
+        }
+        "or" | "and" => definition.args.as_array(),
+        "r of set" | "weighted and" => definition.args.get("set").and_then(Value::as_array),
+        _ => None,
+    };
+    for (i, arg) in args.into_iter().flat_map(|args| args.iter()).enumerate() {
+        // the branches of weighted and are wrapped with their weight
+        let arg = match definition.op {
+            "weighted and" => match arg.get("value") {
+                Some(value) => value,
+                None => continue,
+            },
+            _ => arg,
+        };
+        collect_sig_pairs(arg, &format!("{}.{}", path, i), authentifiers, pairs);
+    }
+}
+
+/// the (signature, pubkey) pairs of the sig branches that can be found
+/// without the db, malformed branches are skipped and left to the evaluation
+pub fn get_sig_pairs<'a>(
+    definition: &'a Value,
+    authentifiers: &'a HashMap<String, String>,
+) -> Vec<(&'a str, &'a str)> {
+    let mut pairs = Vec::new();
+    collect_sig_pairs(definition, "r", authentifiers, &mut pairs);
+    pairs
+}
+
 pub fn has_references(definition: &Value) -> Result<bool> {
     let definition = Definition::from_value(definition).context("has_references")?;
 
     match definition.op {
-        "sig" | " hash" | "cosigned by" => Ok(false),
-        op => unimplemented!("unkonw op: {}", op),
+        "sig" | "hash" | "cosigned by" => Ok(false),
+        // evaluated against the last ball, so can't be checked without the dag
+        "mci" | "timestamp" => Ok(true),
+        "has" | "has one" | "sum" | "seen address" | "seen" => Ok(true),
+        "address" | "definition template" | "in data feed" | "in merkle" => Ok(true),
+        "or" | "and" => {
+            for arg in get_and_or_args(definition.args)? {
+                if has_references(arg)? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+        "r of set" => {
+            for arg in &get_r_of_set_args(definition.args)?.set {
+                if has_references(arg)? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+        "weighted and" => {
+            for arg in &get_weighted_and_args(definition.args)?.set {
+                if has_references(&arg.value)? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+        op => bail!("unknown op: {}", op),
+    }
+}
+
+/// typed address definition, serialized as the canonical `[op, args]` array
+#[derive(Clone, Debug, PartialEq)]
+pub enum AddressDefinition {
+    Sig {
+        pubkey: String,
+        algo: Option<String>,
+    },
+    And(Vec<AddressDefinition>),
+    Or(Vec<AddressDefinition>),
+    RofSet {
+        required: usize,
+        set: Vec<AddressDefinition>,
+    },
+    WeightedAnd {
+        required: u64,
+        set: Vec<(AddressDefinition, u64)>,
+    },
+    Address(String),
+    DefinitionTemplate {
+        unit: String,
+        params: Map<String, Value>,
+    },
+    CosignedBy(String),
+    InDataFeed {
+        oracles: Vec<String>,
+        feed_name: String,
+        relation: String,
+        value: Value,
+        min_mci: Option<u32>,
+    },
+    InMerkle {
+        oracles: Vec<String>,
+        feed_name: String,
+        element: String,
+        min_mci: Option<u32>,
+    },
+    Mci(String, u64),
+    Timestamp(String, u64),
+    Has(Filter),
+    HasOne(Filter),
+    Sum {
+        filter: Filter,
+        equals: Option<i64>,
+        at_least: Option<i64>,
+        at_most: Option<i64>,
+    },
+    SeenAddress(String),
+    Seen(Filter),
+}
+
+impl AddressDefinition {
+    pub fn sig<T: Into<String>>(pubkey: T) -> Self {
+        AddressDefinition::Sig {
+            pubkey: pubkey.into(),
+            algo: None,
+        }
+    }
+
+    pub fn from_value(value: &Value) -> Result<Self> {
+        use self::AddressDefinition::*;
+
+        fn from_values(values: &[Value]) -> Result<Vec<AddressDefinition>> {
+            values.iter().map(AddressDefinition::from_value).collect()
+        }
+
+        fn to_strings(values: &[&str]) -> Vec<String> {
+            values.iter().map(|s| s.to_string()).collect()
+        }
+
+        fn get_optional_min_mci(args: &Value, index: usize) -> Option<u32> {
+            args.get(index).and_then(Value::as_u64).map(|v| v as u32)
+        }
+
+        let definition = Definition::from_value(value)?;
+        let args = definition.args;
+        let ret = match definition.op {
+            "sig" => {
+                let sig_value = SigValue::deserialize(args).context("can't convert to SigValue")?;
+                Sig {
+                    pubkey: sig_value.pubkey.to_owned(),
+                    algo: sig_value.algo.map(str::to_owned),
+                }
+            }
+            "and" => And(from_values(get_and_or_args(args)?)?),
+            "or" => Or(from_values(get_and_or_args(args)?)?),
+            "r of set" => {
+                let args = get_r_of_set_args(args)?;
+                RofSet {
+                    required: args.required,
+                    set: from_values(&args.set)?,
+                }
+            }
+            "weighted and" => {
+                let args = get_weighted_and_args(args)?;
+                WeightedAnd {
+                    required: args.required,
+                    set: args
+                        .set
+                        .iter()
+                        .map(|v| Ok((AddressDefinition::from_value(&v.value)?, v.weight)))
+                        .collect::<Result<_>>()?,
+                }
+            }
+            "address" => Address(get_address_arg(args)?.to_owned()),
+            "definition template" => {
+                let (unit, params) = get_definition_template_args(args)?;
+                DefinitionTemplate {
+                    unit: unit.to_owned(),
+                    params: params.clone(),
+                }
+            }
+            "cosigned by" => CosignedBy(get_address_arg(args)?.to_owned()),
+            "in data feed" => {
+                let feed = get_in_data_feed_args(args)?;
+                InDataFeed {
+                    oracles: to_strings(&feed.addresses),
+                    feed_name: feed.feed_name.to_owned(),
+                    relation: feed.relation.to_owned(),
+                    value: feed.value.clone(),
+                    min_mci: get_optional_min_mci(args, 4),
+                }
+            }
+            "in merkle" => {
+                let merkle = get_in_merkle_args(args)?;
+                InMerkle {
+                    oracles: to_strings(&merkle.addresses),
+                    feed_name: merkle.feed_name.to_owned(),
+                    element: merkle.element.to_owned(),
+                    min_mci: get_optional_min_mci(args, 3),
+                }
+            }
+            "mci" => {
+                let (relation, value) = get_comparison_args(args)?;
+                Mci(relation.to_owned(), value)
+            }
+            "timestamp" => {
+                let (relation, value) = get_comparison_args(args)?;
+                Timestamp(relation.to_owned(), value)
+            }
+            "has" => Has(get_filter_args(args)?),
+            "has one" => HasOne(get_filter_args(args)?),
+            "sum" => {
+                let (filter, args) = get_sum_args(args)?;
+                Sum {
+                    filter,
+                    equals: args.equals,
+                    at_least: args.at_least,
+                    at_most: args.at_most,
+                }
+            }
+            "seen address" => SeenAddress(get_address_arg(args)?.to_owned()),
+            "seen" => Seen(get_filter_args(args)?),
+            op => bail!("unknown op: {}", op),
+        };
+        Ok(ret)
+    }
+
+    pub fn to_value(&self) -> Value {
+        use self::AddressDefinition::*;
+
+        fn to_values(definitions: &[AddressDefinition]) -> Vec<Value> {
+            definitions
+                .iter()
+                .map(AddressDefinition::to_value)
+                .collect()
+        }
+
+        fn push_min_mci(mut args: Vec<Value>, min_mci: Option<u32>) -> Value {
+            if let Some(min_mci) = min_mci {
+                args.push(json!(min_mci));
+            }
+            Value::Array(args)
+        }
+
+        match self {
+            Sig { pubkey, algo } => match algo {
+                Some(algo) => json!(["sig", {"algo": algo, "pubkey": pubkey}]),
+                None => json!(["sig", { "pubkey": pubkey }]),
+            },
+            And(set) => json!(["and", to_values(set)]),
+            Or(set) => json!(["or", to_values(set)]),
+            RofSet { required, set } => {
+                json!(["r of set", {"required": required, "set": to_values(set)}])
+            }
+            WeightedAnd { required, set } => {
+                let set = set
+                    .iter()
+                    .map(|(value, weight)| json!({"value": value.to_value(), "weight": weight}))
+                    .collect::<Vec<_>>();
+                json!(["weighted and", {"required": required, "set": set}])
+            }
+            Address(address) => json!(["address", address]),
+            DefinitionTemplate { unit, params } => json!(["definition template", [unit, params]]),
+            CosignedBy(address) => json!(["cosigned by", address]),
+            InDataFeed {
+                oracles,
+                feed_name,
+                relation,
+                value,
+                min_mci,
+            } => {
+                let args = vec![
+                    json!(oracles),
+                    json!(feed_name),
+                    json!(relation),
+                    value.clone(),
+                ];
+                json!(["in data feed", push_min_mci(args, *min_mci)])
+            }
+            InMerkle {
+                oracles,
+                feed_name,
+                element,
+                min_mci,
+            } => {
+                let args = vec![json!(oracles), json!(feed_name), json!(element)];
+                json!(["in merkle", push_min_mci(args, *min_mci)])
+            }
+            Mci(relation, value) => json!(["mci", [relation, value]]),
+            Timestamp(relation, value) => json!(["timestamp", [relation, value]]),
+            Has(filter) => json!(["has", filter]),
+            HasOne(filter) => json!(["has one", filter]),
+            Sum {
+                filter,
+                equals,
+                at_least,
+                at_most,
+            } => {
+                let mut args = Map::new();
+                args.insert("filter".to_owned(), json!(filter));
+                if let Some(equals) = equals {
+                    args.insert("equals".to_owned(), json!(equals));
+                }
+                if let Some(at_least) = at_least {
+                    args.insert("at_least".to_owned(), json!(at_least));
+                }
+                if let Some(at_most) = at_most {
+                    args.insert("at_most".to_owned(), json!(at_most));
+                }
+                json!(["sum", args])
+            }
+            SeenAddress(address) => json!(["seen address", address]),
+            Seen(filter) => json!(["seen", filter]),
+        }
+    }
+
+    /// the address defined by this definition
+    pub fn to_address(&self) -> Result<String> {
+        object_hash::get_chash(&self.to_value())
     }
+
+    /// validate the definition as of the last stable mci
+    pub fn validate(&self, db: &Connection, is_asset: bool) -> Result<()> {
+        let mut validate_state = ValidationState::new();
+        validate_state.last_ball_mci = storage::read_last_stable_mc_index(db)?;
+        validate_definition(
+            db,
+            &self.to_value(),
+            &Unit::default(),
+            &validate_state,
+            is_asset,
+        )
+    }
+}
+
+impl Serialize for AddressDefinition {
+    fn serialize<S: Serializer>(&self, serializer: S) -> ::std::result::Result<S::Ok, S::Error> {
+        self.to_value().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for AddressDefinition {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> ::std::result::Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        AddressDefinition::from_value(&value).map_err(de::Error::custom)
+    }
+}
+
+#[test]
+fn test_validate_multisig_definition() {
+    use db;
+
+    let db = db::DB_POOL.get_connection();
+    let unit = Unit::default();
+    let mut validate_state = ValidationState::new();
+    validate_state.has_no_references = true;
+    let validate_definition = |definition: &Value, is_asset: bool| {
+        validate_definition(&db, definition, &unit, &validate_state, is_asset)
+    };
+
+    let sig = |pubkey: &str| json!(["sig", {"pubkey": pubkey}]);
+    let pubkey_a = "A0mQdZvy+bGpIu/yBSNt7eB4mTZUQiM173bIQTOQRz3U";
+    let pubkey_b = "Ag5/ArgpVfD7S9PmH7QVuXHo6iSrZCyYcHRdNxUaxc/c";
+    let pubkey_c = "A4mUvDj3yURoDNkb9H4gNw1Ge+WXDBJRKMu9U2VKxVEj";
+
+    let two_of_three = json!(["r of set", {
+        "required": 2,
+        "set": [sig(pubkey_a), sig(pubkey_b), sig(pubkey_c)]
+    }]);
+    assert!(validate_definition(&two_of_three, false).is_ok());
+    assert!(!has_references(&two_of_three).unwrap());
+
+    let weighted = json!(["weighted and", {
+        "required": 3,
+        "set": [{"value": sig(pubkey_a), "weight": 2}, {"value": sig(pubkey_b), "weight": 1}]
+    }]);
+    assert!(validate_definition(&weighted, false).is_ok());
+
+    let and_or = json!([
+        "and",
+        [sig(pubkey_a), ["or", [sig(pubkey_b), sig(pubkey_c)]]]
+    ]);
+    assert!(validate_definition(&and_or, false).is_ok());
+
+    let too_many_required =
+        json!(["r of set", {"required": 3, "set": [sig(pubkey_a), sig(pubkey_b)]}]);
+    assert!(validate_definition(&too_many_required, false).is_err());
+    assert!(validate_definition(&json!(["and", [sig(pubkey_a)]]), false).is_err());
+    assert!(validate_definition(&json!(["unknown", []]), false).is_err());
+
+    let cosigned = json!([
+        "and",
+        [
+            sig(pubkey_a),
+            ["cosigned by", "YFAR4AK2RSRTAWZ3ILRFZOMN7M7QJTJ2"]
+        ]
+    ]);
+    assert!(validate_definition(&cosigned, false).is_ok());
+    // the references are rejected for the rule, not for a malformed expression
+    let is_references_err = |definition: &Value| match validate_definition(definition, false) {
+        Err(e) => e.to_string() == "no references allowed in address definition",
+        Ok(_) => false,
+    };
+    let address = json!(["address", "YFAR4AK2RSRTAWZ3ILRFZOMN7M7QJTJ2"]);
+    assert!(is_references_err(&address));
+    assert!(has_references(&address).unwrap());
+
+    let mci = json!(["and", [sig(pubkey_a), ["mci", [">", 1000]]]]);
+    assert!(is_references_err(&mci));
+    assert!(has_references(&mci).unwrap());
+
+    let has = json!(["and", [sig(pubkey_a), ["has", {"what": "output", "asset": "base"}]]]);
+    assert!(is_references_err(&has));
+    assert!(has_references(&has).unwrap());
+}
+
+#[test]
+fn test_validate_data_feed_definition() {
+    use db;
+
+    let db = db::DB_POOL.get_connection();
+    let unit = Unit::default();
+    let validate_state = ValidationState::new();
+    let oracle = "YFAR4AK2RSRTAWZ3ILRFZOMN7M7QJTJ2";
+    let sig = json!(["sig", {"pubkey": "A0mQdZvy+bGpIu/yBSNt7eB4mTZUQiM173bIQTOQRz3U"}]);
+
+    let in_data_feed = json!([
+        "and",
+        [sig, ["in data feed", [[oracle], "BTC_USD", ">", 7000]]]
+    ]);
+    assert!(validate_definition(&db, &in_data_feed, &unit, &validate_state, false).is_ok());
+    let in_merkle = json!([
+        "and",
+        [sig, ["in merkle", [[oracle], "winners", "ADDRESS"]]]
+    ]);
+    assert!(validate_definition(&db, &in_merkle, &unit, &validate_state, false).is_ok());
+
+    let bad_relation = json!([
+        "and",
+        [sig, ["in data feed", [[oracle], "BTC_USD", "~", 1]]]
+    ]);
+    assert!(validate_definition(&db, &bad_relation, &unit, &validate_state, false).is_err());
+    let bad_oracle = json!([
+        "and",
+        [sig, ["in data feed", [["ORACLE"], "BTC_USD", "=", 1]]]
+    ]);
+    assert!(validate_definition(&db, &bad_oracle, &unit, &validate_state, false).is_err());
+}
+
+#[test]
+fn test_validate_filter() {
+    let output = json!({"what": "output", "address": "this address", "amount_at_least": 1000});
+    assert!(get_filter_args(&output).is_ok());
+    let input = json!({"what": "input", "type": "issue", "own_funds": true});
+    assert!(get_filter_args(&input).is_ok());
+
+    assert!(get_filter_args(&json!({"what": "message"})).is_err());
+    assert!(get_filter_args(&json!({"what": "output", "type": "issue"})).is_err());
+    assert!(get_filter_args(&json!({"what": "output", "amount": 1, "amount_at_most": 2})).is_err());
+    assert!(get_filter_args(&json!({"what": "output", "foo": 1})).is_err());
+
+    assert!(get_sum_args(&json!({"filter": output, "at_least": 10})).is_ok());
+    assert!(get_sum_args(&json!({"filter": output})).is_err());
+    assert!(get_sum_args(&json!({"filter": input, "equals": 10})).is_err());
+}
+
+#[test]
+fn test_compare() {
+    let args = json!([">", 100]);
+    let (relation, value) = get_comparison_args(&args).unwrap();
+    assert!(compare(101, relation, value));
+    assert!(!compare(100, relation, value));
+    assert!(get_comparison_args(&json!(["~", 100])).is_err());
+    assert!(get_comparison_args(&json!([">", -1])).is_err());
+}
+
+#[test]
+fn test_replace_in_template() {
+    let template = json!(["and", [["address", "$addr"], ["cosigned by", "$cosigner"]]]);
+    let params = json!({"addr": "A", "cosigner": "B"});
+    let filled = replace_in_template(&template, params.as_object().unwrap()).unwrap();
+    assert_eq!(
+        filled,
+        json!(["and", [["address", "A"], ["cosigned by", "B"]]])
+    );
+
+    let params = json!({"addr": "A"});
+    assert!(replace_in_template(&template, params.as_object().unwrap()).is_err());
+}
+
+#[test]
+fn test_address_definition_round_trip() {
+    let pubkey_a = "A0mQdZvy+bGpIu/yBSNt7eB4mTZUQiM173bIQTOQRz3U";
+    let pubkey_b = "Ag5/ArgpVfD7S9PmH7QVuXHo6iSrZCyYcHRdNxUaxc/c";
+    let oracle = "YFAR4AK2RSRTAWZ3ILRFZOMN7M7QJTJ2";
+
+    let definition = AddressDefinition::Or(vec![
+        AddressDefinition::RofSet {
+            required: 1,
+            set: vec![
+                AddressDefinition::sig(pubkey_a),
+                AddressDefinition::sig(pubkey_b),
+            ],
+        },
+        AddressDefinition::And(vec![
+            AddressDefinition::sig(pubkey_b),
+            AddressDefinition::InDataFeed {
+                oracles: vec![oracle.to_owned()],
+                feed_name: "BTC_USD".to_owned(),
+                relation: ">".to_owned(),
+                value: json!(7000),
+                min_mci: None,
+            },
+            AddressDefinition::Mci(">".to_owned(), 1000),
+        ]),
+    ]);
+
+    let expected = json!(["or", [
+        ["r of set", {"required": 1, "set": [
+            ["sig", {"pubkey": pubkey_a}],
+            ["sig", {"pubkey": pubkey_b}]
+        ]}],
+        ["and", [
+            ["sig", {"pubkey": pubkey_b}],
+            ["in data feed", [[oracle], "BTC_USD", ">", 7000]],
+            ["mci", [">", 1000]]
+        ]]
+    ]]);
+
+    let value = serde_json::to_value(&definition).unwrap();
+    assert_eq!(value, expected);
+    let parsed: AddressDefinition = serde_json::from_value(value).unwrap();
+    assert_eq!(parsed, definition);
+    assert_eq!(
+        definition.to_address().unwrap(),
+        object_hash::get_chash(&expected).unwrap()
+    );
+
+    assert!(serde_json::from_value::<AddressDefinition>(json!(["sig", "bad"])).is_err());
+    assert!(serde_json::from_value::<AddressDefinition>(json!(["foo", []])).is_err());
+}
+
+#[test]
+fn test_dry_run_authentifiers() {
+    use db;
+
+    let db = db::DB_POOL.get_connection();
+    let pubkey_a = "A0mQdZvy+bGpIu/yBSNt7eB4mTZUQiM173bIQTOQRz3U";
+    let pubkey_b = "Ag5/ArgpVfD7S9PmH7QVuXHo6iSrZCyYcHRdNxUaxc/c";
+    let pubkey_c = "A4mUvDj3yURoDNkb9H4gNw1Ge+WXDBJRKMu9U2VKxVEj";
+    let definition = json!(["r of set", {"required": 2, "set": [
+        ["sig", {"pubkey": pubkey_a}],
+        ["sig", {"pubkey": pubkey_b}],
+        ["sig", {"pubkey": pubkey_c}]
+    ]}]);
+    let unit = Unit::default();
+
+    let signers = vec![pubkey_a.to_owned()];
+    let report = dry_run_authentifiers(&db, "", &definition, &unit, &signers).unwrap();
+    assert!(!report.is_satisfied);
+    assert_eq!(report.required_paths, vec!["r.0", "r.1", "r.2"]);
+    assert_eq!(report.satisfied_paths, vec!["r.0"]);
+    assert_eq!(report.failures.len(), 3);
+    assert_eq!(report.failures[2].path, "r");
+
+    let signers = vec![pubkey_a.to_owned(), pubkey_c.to_owned()];
+    let report = dry_run_authentifiers(&db, "", &definition, &unit, &signers).unwrap();
+    assert!(report.is_satisfied);
+    assert_eq!(report.satisfied_paths, vec!["r.0", "r.2"]);
+}
+
+#[test]
+fn test_verify_authentifiers_offline() {
+    let hash = ::base64::decode("uPQs4TwLtDGRAdH8sbIJ1ZyWpEmwHWRAhXpamODZ7Kk=").unwrap();
+    let pubkey = "A0qTjB3ZjHf2yT1EIvLrkVAWY8MPSueNcB4GTlKGo/o6";
+    let sig =
+        "up+2Fjhnu4OjJeesBPCgoZE+6ReqQDdnqcjhbq2iaulHjlwKYLcwRrD3udSWdHS57ceQeZ+LVPWYBMWBloAgpA==";
+    let mut authentifiers = HashMap::new();
+    authentifiers.insert("r.0".to_owned(), sig.to_owned());
+
+    // the signed branch is enough, the address branch is never needed
+    let definition = json!(["or", [
+        ["sig", {"pubkey": pubkey}],
+        ["address", "YFAR4AK2RSRTAWZ3ILRFZOMN7M7QJTJ2"]
+    ]]);
+    assert!(verify_authentifiers_offline(&definition, &authentifiers, &hash).unwrap());
+
+    // the address branch must be evaluated against the dag
+    let definition = json!(["and", [
+        ["sig", {"pubkey": pubkey}],
+        ["address", "YFAR4AK2RSRTAWZ3ILRFZOMN7M7QJTJ2"]
+    ]]);
+    assert!(!verify_authentifiers_offline(&definition, &authentifiers, &hash).unwrap());
+
+    let definition = json!(["or", [
+        ["sig", {"pubkey": "A4mUvDj3yURoDNkb9H4gNw1Ge+WXDBJRKMu9U2VKxVEj"}],
+        ["sig", {"pubkey": pubkey}]
+    ]]);
+    assert!(verify_authentifiers_offline(&definition, &authentifiers, &hash).is_err());
 }
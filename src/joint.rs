@@ -166,8 +166,8 @@ impl Joint {
     // return a vec of author address
     fn save_authors(&self, tx: &Transaction) -> Result<()> {
         let unit_hash = self.get_unit_hash();
-        let mut definition_chash = None;
         for author in &self.unit.authors {
+            let mut definition_chash = None;
             let definition = &author.definition;
             if !definition.is_null() {
                 definition_chash = Some(get_chash(definition)?);
@@ -226,11 +226,12 @@ impl Joint {
                         None
                     }
                 },
-                "data" | "profile" | "attestation" | "definition_template" => {
-                    let payload = serde_json::to_string(&message.payload)?;
-                    Some(payload)
-                }
-                _ => None,
+                // payment and data feed are stored in their own tables
+                "payment" | "data_feed" => None,
+                _ => match message.payload {
+                    Some(ref payload) => Some(serde_json::to_string(payload)?),
+                    None => None,
+                },
             };
 
             let mut stmt = tx.prepare_cached(
@@ -257,7 +258,7 @@ impl Joint {
                     "data_feed" => match message.payload {
                         Some(Payload::Other(ref v)) => {
                             if let Some(map) = v.as_object() {
-                                for (i, (k, v)) in map.iter().enumerate() {
+                                for (k, v) in map {
                                     let field_name =
                                         if v.is_number() { "int_value" } else { "value" };
                                     let sql = format!(
@@ -276,7 +277,105 @@ impl Joint {
                         }
                         _ => unreachable!("data_feed invalid message"),
                     },
-                    app => unimplemented!("unknow message app: {}", app),
+                    "asset" => match message.payload {
+                        Some(Payload::Other(ref v)) => {
+                            let asset: AssetDefinition = serde_json::from_value(v.clone())?;
+                            self.save_asset_definition(tx, i as u32, &asset)?;
+                        }
+                        _ => bail!("asset payload is not asset definition"),
+                    },
+                    "poll" => match message.payload {
+                        Some(Payload::Other(ref v)) => {
+                            let poll: Poll = serde_json::from_value(v.clone())?;
+                            let mut stmt = tx.prepare_cached(
+                                "INSERT INTO polls (unit, message_index, question) VALUES(?,?,?)",
+                            )?;
+                            stmt.execute(&[unit_hash, &(i as u32), &poll.question])?;
+
+                            for (j, choice) in poll.choices.iter().enumerate() {
+                                let mut stmt = tx.prepare_cached(
+                                    "INSERT INTO poll_choices (unit, choice_index, choice) \
+                                     VALUES(?,?,?)",
+                                )?;
+                                stmt.execute(&[unit_hash, &(j as u32), choice])?;
+                            }
+                        }
+                        _ => bail!("poll payload is not poll"),
+                    },
+                    "vote" => match message.payload {
+                        Some(Payload::Other(ref v)) => {
+                            let vote: Vote = serde_json::from_value(v.clone())?;
+                            let mut stmt = tx.prepare_cached(
+                                "INSERT INTO votes (unit, message_index, poll_unit, choice) \
+                                 VALUES(?,?,?,?)",
+                            )?;
+                            stmt.execute(&[unit_hash, &(i as u32), &vote.unit, &vote.choice])?;
+                        }
+                        _ => bail!("vote payload is not vote"),
+                    },
+                    "address_definition_change" => match message.payload {
+                        Some(Payload::Other(ref v)) => {
+                            let change: AddressDefinitionChange =
+                                serde_json::from_value(v.clone())?;
+                            let address = change
+                                .address
+                                .as_ref()
+                                .unwrap_or(&self.unit.authors[0].address);
+                            let mut stmt = tx.prepare_cached(
+                                "INSERT INTO address_definition_changes \
+                                 (unit, message_index, address, definition_chash) \
+                                 VALUES(?,?,?,?)",
+                            )?;
+                            stmt.execute(&[
+                                unit_hash,
+                                &(i as u32),
+                                address,
+                                &change.definition_chash,
+                            ])?;
+                        }
+                        _ => bail!(
+                            "address_definition_change payload is not address_definition_change"
+                        ),
+                    },
+                    "attestation" => match message.payload {
+                        Some(Payload::Other(ref v)) => {
+                            let attestation: Attestation = serde_json::from_value(v.clone())?;
+                            let mut stmt = tx.prepare_cached(
+                                "INSERT INTO attestations \
+                                 (unit, message_index, attestor_address, address) \
+                                 VALUES(?,?,?,?)",
+                            )?;
+                            stmt.execute(&[
+                                unit_hash,
+                                &(i as u32),
+                                &self.unit.authors[0].address,
+                                &attestation.address,
+                            ])?;
+                        }
+                        _ => bail!("attestation payload is not attestation"),
+                    },
+                    "asset_attestors" => match message.payload {
+                        Some(Payload::Other(ref v)) => {
+                            let asset_attestors: AssetAttestors =
+                                serde_json::from_value(v.clone())?;
+                            let mut stmt = tx.prepare_cached(
+                                "INSERT INTO asset_attestors \
+                                 (unit, message_index, asset, attestor_address) \
+                                 VALUES(?,?,?,?)",
+                            )?;
+                            for attestor in &asset_attestors.attestors {
+                                stmt.execute(&[
+                                    unit_hash,
+                                    &(i as u32),
+                                    &asset_attestors.asset,
+                                    attestor,
+                                ])?;
+                            }
+                        }
+                        _ => bail!("asset_attestors payload is not attestor list"),
+                    },
+                    // the payload of other apps is kept in messages
+                    _ => {}
                 }
             }
 
@@ -300,6 +399,70 @@ impl Joint {
         Ok(())
     }
 
+    fn save_asset_definition(
+        &self,
+        tx: &Transaction,
+        message_index: u32,
+        asset: &AssetDefinition,
+    ) -> Result<()> {
+        let unit_hash = self.get_unit_hash();
+        let issue_condition = match asset.issue_condition {
+            Some(ref condition) => Some(serde_json::to_string(condition)?),
+            None => None,
+        };
+        let transfer_condition = match asset.transfer_condition {
+            Some(ref condition) => Some(serde_json::to_string(condition)?),
+            None => None,
+        };
+
+        let mut stmt = tx.prepare_cached(
+            "INSERT INTO assets (unit, message_index, \
+             cap, is_private, is_transferrable, auto_destroy, fixed_denominations, \
+             issued_by_definer_only, cosigned_by_definer, spender_attested, \
+             issue_condition, transfer_condition) VALUES(?,?,?,?,?,?,?,?,?,?,?,?)",
+        )?;
+        stmt.execute(&[
+            unit_hash,
+            &message_index,
+            &asset.cap,
+            &asset.is_private,
+            &asset.is_transferrable,
+            &asset.auto_destroy,
+            &asset.fixed_denominations,
+            &asset.issued_by_definer_only,
+            &asset.cosigned_by_definer,
+            &asset.spender_attested,
+            &issue_condition,
+            &transfer_condition,
+        ])?;
+
+        if let Some(ref attestors) = asset.attestors {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO asset_attestors (unit, message_index, asset, attestor_address) \
+                 VALUES(?,?,?,?)",
+            )?;
+            for attestor in attestors {
+                stmt.execute(&[unit_hash, &message_index, unit_hash, attestor])?;
+            }
+        }
+
+        if let Some(ref denominations) = asset.denominations {
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO asset_denominations (asset, denomination, count_coins) \
+                 VALUES(?,?,?)",
+            )?;
+            for denomination in denominations {
+                stmt.execute(&[
+                    unit_hash,
+                    &denomination.denomination,
+                    &denomination.count_coins,
+                ])?;
+            }
+        }
+
+        Ok(())
+    }
+
     fn save_header_earnings(&self, tx: &Transaction) -> Result<()> {
         let unit = &self.unit;
         for recipient in &unit.earned_headers_commission_recipients {
@@ -97,18 +97,21 @@ pub mod spec;
 
 pub mod catchup;
 pub mod composer;
-mod definition;
+pub mod definition;
 pub mod joint;
 pub mod joint_storage;
 pub mod light;
 pub mod light_wallet;
 pub mod main_chain;
+pub mod merkle;
 mod obj_ser;
 pub mod object_hash;
 pub mod parent_composer;
+pub mod private_payment;
 pub mod signature;
 pub mod storage;
 pub mod time;
+pub mod uri_payload;
 pub mod validation;
 pub mod wallet;
 pub mod witness_proof;
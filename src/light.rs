@@ -15,6 +15,7 @@ use validation::ValidationState;
 use witness_proof;
 
 const MAX_HISTORY_ITEMS: usize = 1000;
+const MAX_DATA_FEED_ITEMS: usize = 1000;
 
 lazy_static! {
     static ref LIGHT_JOINTS: Mutex<()> = Mutex::new(());
@@ -696,6 +697,65 @@ pub fn prepare_parents_and_last_ball_and_witness_list_unit(
     })
 }
 
+/// without any range only the latest value is returned, the int value range
+/// takes precedence over the mci range
+#[derive(Serialize, Deserialize)]
+pub struct DataFeedRequest {
+    pub oracles: Vec<String>,
+    pub feed_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_mci: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_mci: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_int_value: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_int_value: Option<i64>,
+}
+
+pub fn prepare_data_feeds(request: &DataFeedRequest) -> Result<Vec<storage::DataFeedValue>> {
+    let db = db::DB_POOL.get_connection();
+    if request.oracles.is_empty() || request.oracles.len() > config::MAX_ORACLES_PER_REQUEST {
+        bail!("wrong number of oracles");
+    }
+
+    let last_stable_mci = storage::read_last_stable_mc_index(&db)?;
+    let max_mci = match request.max_mci {
+        Some(mci) => ::std::cmp::min(mci, last_stable_mci),
+        None => last_stable_mci,
+    };
+
+    let feeds = if request.min_int_value.is_some() || request.max_int_value.is_some() {
+        storage::read_data_feeds_by_int_value(
+            &db,
+            &request.oracles,
+            &request.feed_name,
+            request.min_int_value,
+            request.max_int_value,
+            max_mci,
+            MAX_DATA_FEED_ITEMS + 1,
+        )?
+    } else if let Some(min_mci) = request.min_mci {
+        storage::read_data_feeds_in_mci_range(
+            &db,
+            &request.oracles,
+            &request.feed_name,
+            min_mci,
+            max_mci,
+            MAX_DATA_FEED_ITEMS + 1,
+        )?
+    } else {
+        storage::read_latest_data_feed(&db, &request.oracles, &request.feed_name, max_mci)?
+            .into_iter()
+            .collect()
+    };
+
+    if feeds.len() > MAX_DATA_FEED_ITEMS {
+        bail!("your data feed query is too large, please narrow the range");
+    }
+    Ok(feeds)
+}
+
 fn build_proof_chain_on_mc(
     db: &Connection,
     later_mci: u32,
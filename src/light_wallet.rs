@@ -5,6 +5,7 @@ use failure::ResultExt;
 use error::Result;
 use light::HistoryRequest;
 use my_witness;
+use private_payment;
 use rusqlite::Connection;
 
 pub fn get_history(db: &Connection) -> Result<HistoryRequest> {
@@ -17,6 +18,8 @@ pub fn get_history(db: &Connection) -> Result<HistoryRequest> {
         read_my_addresses(db).context("prepare_request_for_history read_my_addresses failed")?;
     let mut requested_joints = read_list_of_unstable_units(db)
         .context("prepare_request_for_history read_list_of_unstable_units failed")?;
+    // the units that the received private payments depend on
+    requested_joints.extend(private_payment::read_missing_units(db)?);
     if requested_joints.is_empty() {
         // here we can't give an empty vec, just make up one
         requested_joints.push("v|NuDxzT7VFa/AqfBsAZ8suG4uj3u+l0kXOLE+nP+dU=".to_string());
@@ -53,7 +56,7 @@ pub fn get_history(db: &Connection) -> Result<HistoryRequest> {
     Ok(req_history)
 }
 
-fn read_my_addresses(db: &Connection) -> Result<Vec<String>> {
+pub fn read_my_addresses(db: &Connection) -> Result<Vec<String>> {
     let mut stmt = db.prepare_cached(
         "SELECT address FROM my_addresses \
          UNION \
@@ -627,6 +627,7 @@ pub fn mark_mc_index_stable(db: &Connection, mci: u32) -> Result<()> {
 
     //Update retrievable
     let _min_retrievable_mci = storage::update_min_retrievable_mci_after_stabilizing_mci(db, mci)?;
+    storage::prune_units_after_stabilizing_mci(db, mci)?;
 
     //Calculate commissions
     headers_commission::calc_headers_commissions(db)?;
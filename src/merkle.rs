@@ -0,0 +1,120 @@
+use base64;
+use error::Result;
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MerkleProof {
+    pub index: usize,
+    pub siblings: Vec<String>,
+    pub root: String,
+}
+
+fn hash(s: &str) -> String {
+    base64::encode(&Sha256::digest(s.as_bytes()))
+}
+
+pub fn get_merkle_root<T: AsRef<str>>(elements: &[T]) -> String {
+    let mut hashes = elements
+        .iter()
+        .map(|e| hash(e.as_ref()))
+        .collect::<Vec<_>>();
+    while hashes.len() > 1 {
+        hashes = hashes
+            .chunks(2)
+            .map(|pair| {
+                // for odd number of hashes the last one is paired with itself
+                let right = pair.get(1).unwrap_or(&pair[0]);
+                hash(&format!("{}{}", pair[0], right))
+            })
+            .collect();
+    }
+    hashes.pop().unwrap_or_default()
+}
+
+pub fn get_merkle_proof<T: AsRef<str>>(
+    elements: &[T],
+    element_index: usize,
+) -> Result<MerkleProof> {
+    ensure!(element_index < elements.len(), "invalid element index");
+
+    let mut hashes = elements
+        .iter()
+        .map(|e| hash(e.as_ref()))
+        .collect::<Vec<_>>();
+    let mut index = element_index;
+    let mut siblings = Vec::new();
+    while hashes.len() > 1 {
+        let sibling_index = if index % 2 == 0 { index + 1 } else { index - 1 };
+        siblings.push(hashes.get(sibling_index).unwrap_or(&hashes[index]).clone());
+        hashes = hashes
+            .chunks(2)
+            .map(|pair| {
+                let right = pair.get(1).unwrap_or(&pair[0]);
+                hash(&format!("{}{}", pair[0], right))
+            })
+            .collect();
+        index /= 2;
+    }
+
+    Ok(MerkleProof {
+        index: element_index,
+        siblings,
+        root: hashes.pop().unwrap_or_default(),
+    })
+}
+
+/// serialize the proof as "index-sibling1-sibling2-...-root"
+pub fn serialize_merkle_proof(proof: &MerkleProof) -> String {
+    let mut serialized_proof = proof.index.to_string();
+    for sibling in &proof.siblings {
+        serialized_proof.push('-');
+        serialized_proof.push_str(sibling);
+    }
+    serialized_proof.push('-');
+    serialized_proof.push_str(&proof.root);
+    serialized_proof
+}
+
+pub fn deserialize_merkle_proof(serialized_proof: &str) -> Result<MerkleProof> {
+    let mut parts = serialized_proof.split('-').collect::<Vec<_>>();
+    ensure!(parts.len() >= 2, "invalid merkle proof");
+    let root = parts.pop().unwrap().to_owned();
+    let index = parts.remove(0).parse::<usize>()?;
+    let siblings = parts.into_iter().map(|s| s.to_owned()).collect();
+    Ok(MerkleProof {
+        index,
+        siblings,
+        root,
+    })
+}
+
+pub fn verify_merkle_proof(element: &str, proof: &MerkleProof) -> bool {
+    let mut index = proof.index;
+    let mut the_other_sibling = hash(element);
+    for sibling in &proof.siblings {
+        // this also works for duplicated trailing nodes
+        the_other_sibling = if index % 2 == 0 {
+            hash(&format!("{}{}", the_other_sibling, sibling))
+        } else {
+            hash(&format!("{}{}", sibling, the_other_sibling))
+        };
+        index /= 2;
+    }
+    the_other_sibling == proof.root
+}
+
+#[test]
+fn test_merkle_proof() {
+    let elements = ["a", "b", "c", "d", "e"];
+    let root = get_merkle_root(&elements);
+
+    for (i, element) in elements.iter().enumerate() {
+        let proof = get_merkle_proof(&elements, i).unwrap();
+        assert_eq!(proof.root, root);
+
+        let serialized_proof = serialize_merkle_proof(&proof);
+        let proof = deserialize_merkle_proof(&serialized_proof).unwrap();
+        assert!(verify_merkle_proof(element, &proof));
+        assert!(!verify_merkle_proof("f", &proof));
+    }
+}
@@ -18,15 +18,17 @@ use may::coroutine;
 use may::net::TcpStream;
 use may::sync::{Mutex, RwLock};
 use object_hash;
+use private_payment;
 use rusqlite::Connection;
 use serde_json::{self, Value};
 use signature;
+use spec::PrivateElement;
 use storage;
 use tungstenite::client::client;
 use tungstenite::handshake::client::Request;
 use tungstenite::protocol::Role;
 use url::Url;
-use utils::{AtomicLock, MapLock};
+use utils::{AtomicLock, MapLock, CPU_POOL};
 use validation;
 
 #[derive(Serialize, Deserialize)]
@@ -496,6 +498,9 @@ impl Server<HubData> for HubData {
             "post_joint" => ws.on_post_joint(params)?,
             "light/get_history" => ws.on_get_history(params)?,
             "light/get_link_proofs" => ws.on_get_link_proofs(params)?,
+            "light/get_data_feed" => ws.on_get_data_feed(params)?,
+            "light/get_private_payments" => ws.on_get_private_payments(params)?,
+            "private_payment" => ws.on_private_payment(params)?,
             "light/get_parents_and_last_ball_and_witness_list_unit" => {
                 ws.on_get_parents_and_last_ball_and_witness_list_unit(params)?
             }
@@ -631,6 +636,13 @@ impl HubConn {
     fn on_get_joint(&self, param: Value) -> Result<Value> {
         let unit = serde_json::from_value(param)?;
         let db = db::DB_POOL.get_connection();
+        // reply an error rather than joint_not_found, the unit is known here
+        if storage::is_pruned_unit(&db, &unit)? {
+            bail!(
+                "unit {} is pruned on this hub, ask a full node instead",
+                unit
+            );
+        }
         match storage::read_joint(&db, &unit) {
             Ok(joint) => Ok(json!({ "joint": joint })),
             Err(e) => {
@@ -688,6 +700,15 @@ impl HubConn {
         Ok(())
     }
 
+    fn on_private_payment(&self, param: Value) -> Result<Value> {
+        let chain: Vec<PrivateElement> =
+            serde_json::from_value(param).context("invalid private payment chain")?;
+
+        let db = db::DB_POOL.get_connection();
+        private_payment::save_private_chain_for_payees(&db, &chain)?;
+        Ok(Value::from("accepted"))
+    }
+
     fn on_new_address_to_watch(&self, param: Value) -> Result<()> {
         if !self.is_inbound() {
             return self.send_error(Value::from("light clients have to be inbound"));
@@ -807,6 +828,36 @@ impl HubConn {
         Ok(serde_json::to_value(light::prepare_link_proofs(&units)?)?)
     }
 
+    fn on_get_data_feed(&self, param: Value) -> Result<Value> {
+        if !self.is_inbound() {
+            bail!("light clients have to be inbound");
+        }
+        let request: light::DataFeedRequest =
+            serde_json::from_value(param).context("invalid data feed request")?;
+        Ok(serde_json::to_value(light::prepare_data_feeds(&request)?)?)
+    }
+
+    fn on_get_private_payments(&self, param: Value) -> Result<Value> {
+        if !self.is_inbound() {
+            bail!("light clients have to be inbound");
+        }
+        let proofs: Vec<private_payment::PayeeProof> =
+            serde_json::from_value(param).context("not a list of payee proofs")?;
+        let challenge = match *self.get_challenge() {
+            Some(ref challenge) => challenge.clone(),
+            None => bail!("no challenge sent to the peer"),
+        };
+
+        let db = db::DB_POOL.get_connection();
+        let mut addresses = Vec::with_capacity(proofs.len());
+        for proof in proofs {
+            private_payment::check_payee_proof(&db, &challenge, &proof)?;
+            addresses.push(proof.address);
+        }
+        let chains = private_payment::take_private_chains_for_payees(&db, &addresses)?;
+        Ok(serde_json::to_value(chains)?)
+    }
+
     fn on_get_parents_and_last_ball_and_witness_list_unit(&self, param: Value) -> Result<Value> {
         if !self.is_inbound() {
             bail!("light clients have to be inbound");
@@ -1005,23 +1056,12 @@ impl HubConn {
                     find_and_handle_joints_that_are_ready(db, Some(unit))?;
                 }
             },
-            Err(err) => match err {
-                ValidationError::OtherError { err } => {
+            Err(e) => match e {
+                ValidationError::OtherError { ref err } => {
                     error!("validation other err={}, unit={}", err, unit);
                 }
-
-                ValidationError::UnitError { err } => {
-                    warn!("{} validation failed: {}", unit, err);
-                    self.send_error_result(unit, &err)?;
-                    self.purge_joint_and_dependencies_and_notify_peers(db, &joint, &err)?;
-                    if !err.contains("authentifier verification failed")
-                        && !err.contains("bad merkle proof at path")
-                    {
-                        self.write_event(db, "invalid")?;
-                    }
-                }
-                ValidationError::JointError { err } => {
-                    self.send_error_result(unit, &err)?;
+                ValidationError::JointError { ref err } => {
+                    self.send_validation_error_result(unit, &e)?;
                     self.write_event(db, "invalid")?;
                     let mut stmt = db.prepare_cached(
                         "INSERT INTO known_bad_joints (joint, json, error) VALUES (?,?,?)",
@@ -1029,7 +1069,7 @@ impl HubConn {
                     stmt.execute(&[
                         &object_hash::get_base64_hash(&joint)?,
                         &serde_json::to_string(&joint)?,
-                        &err,
+                        err,
                     ])?;
                 }
                 ValidationError::NeedHashTree => {
@@ -1054,6 +1094,17 @@ impl HubConn {
                     drop(g);
                     self.request_new_missing_joints(&db, &missing_units)?;
                 }
+                // the unit errors
+                _ => {
+                    let err = e.detail();
+                    warn!("{} validation failed: {}", unit, e);
+                    self.send_validation_error_result(unit, &e)?;
+                    self.purge_joint_and_dependencies_and_notify_peers(db, &joint, &err)?;
+                    match e {
+                        ValidationError::BadSignature { .. } => {}
+                        _ => self.write_event(db, "invalid")?,
+                    }
+                }
             },
         }
 
@@ -1065,6 +1116,7 @@ impl HubConn {
         db: &mut Connection,
         joint: Joint,
         create_ts: usize,
+        precheck: ::std::result::Result<(), validation::ValidationError>,
         unhandled_joints: &mut VecDeque<ReadyJoint>,
     ) -> Result<()> {
         use joint_storage::CheckNewResult;
@@ -1090,7 +1142,7 @@ impl HubConn {
             CheckNewResult::KnownBad => return Ok(()),
             CheckNewResult::KnownUnverified => {}
         }
-        match validation::validate(db, &joint) {
+        match precheck.and_then(|_| validation::validate_prechecked(db, &joint)) {
             Ok(ok) => match ok {
                 ValidationOk::Unsigned(_) => {
                     if joint.unsigned != Some(true) {
@@ -1126,22 +1178,12 @@ impl HubConn {
                     }
                 }
             },
-            Err(err) => match err {
-                ValidationError::OtherError { err } => {
+            Err(e) => match e {
+                ValidationError::OtherError { ref err } => {
                     error!("validation other err={}, unit={}", err, unit);
                 }
-                ValidationError::UnitError { err } => {
-                    warn!("{} validation failed: {}", unit, err);
-                    self.send_error_result(unit, &err)?;
-                    self.purge_joint_and_dependencies_and_notify_peers(db, &joint, &err)?;
-                    if !err.contains("authentifier verification failed")
-                        && !err.contains("bad merkle proof at path")
-                    {
-                        self.write_event(db, "invalid")?;
-                    }
-                }
-                ValidationError::JointError { err } => {
-                    self.send_error_result(unit, &err)?;
+                ValidationError::JointError { ref err } => {
+                    self.send_validation_error_result(unit, &e)?;
                     self.write_event(db, "invalid")?;
                     let mut stmt = db.prepare_cached(
                         "INSERT INTO known_bad_joints (joint, json, error) VALUES (?,?,?)",
@@ -1149,7 +1191,7 @@ impl HubConn {
                     stmt.execute(&[
                         &object_hash::get_base64_hash(&joint)?,
                         &serde_json::to_string(&joint)?,
-                        &err,
+                        err,
                     ])?;
                 }
                 ValidationError::NeedHashTree => {
@@ -1183,6 +1225,17 @@ impl HubConn {
                     drop(g);
                     self.request_new_missing_joints(&db, &missing_units)?;
                 }
+                // the unit errors
+                _ => {
+                    let err = e.detail();
+                    warn!("{} validation failed: {}", unit, e);
+                    self.send_validation_error_result(unit, &e)?;
+                    self.purge_joint_and_dependencies_and_notify_peers(db, &joint, &err)?;
+                    match e {
+                        ValidationError::BadSignature { .. } => {}
+                        _ => self.write_event(db, "invalid")?,
+                    }
+                }
             },
         }
 
@@ -1245,41 +1298,47 @@ impl HubConn {
                     notify_watchers(db, &joint, self)?;
                 }
             },
-            Err(err) => match err {
-                ValidationError::OtherError { err } => {
-                    bail!("validation other err={}, unit={}", err, unit);
-                }
-
-                ValidationError::UnitError { err } => {
-                    self.purge_joint_and_dependencies_and_notify_peers(db, &joint, &err)?;
-                    if !err.contains("authentifier verification failed")
-                        && !err.contains("bad merkle proof at path")
-                    {
+            // the validation error is returned as is, so that the posting
+            // wallet gets its code in the response
+            Err(e) => {
+                match e {
+                    ValidationError::OtherError { ref err } => {
+                        error!("validation other err={}, unit={}", err, unit);
+                    }
+                    ValidationError::JointError { ref err } => {
                         self.write_event(db, "invalid")?;
+                        let mut stmt = db.prepare_cached(
+                            "INSERT INTO known_bad_joints (joint, json, error) VALUES (?,?,?)",
+                        )?;
+                        stmt.execute(&[
+                            &object_hash::get_base64_hash(&joint)?,
+                            &serde_json::to_string(&joint)?,
+                            err,
+                        ])?;
                     }
-                    bail!("{} validation failed: {}", unit, err);
-                }
-                ValidationError::JointError { err } => {
-                    self.write_event(db, "invalid")?;
-                    let mut stmt = db.prepare_cached(
-                        "INSERT INTO known_bad_joints (joint, json, error) VALUES (?,?,?)",
-                    )?;
-                    stmt.execute(&[
-                        &object_hash::get_base64_hash(&joint)?,
-                        &serde_json::to_string(&joint)?,
-                        &err,
-                    ])?;
-                    bail!("{}", err);
-                }
-                ValidationError::NeedHashTree => {
-                    info!("need hash tree for unit {}", unit);
-                    if joint.unsigned == Some(true) {
-                        bail!("need hash tree unsigned");
+                    ValidationError::NeedHashTree => {
+                        info!("need hash tree for unit {}", unit);
+                        if joint.unsigned == Some(true) {
+                            bail!("need hash tree unsigned");
+                        }
+                    }
+                    ValidationError::NeedParentUnits(_) => {}
+                    // the unit errors
+                    _ => {
+                        warn!("{} validation failed: {}", unit, e);
+                        self.purge_joint_and_dependencies_and_notify_peers(
+                            db,
+                            &joint,
+                            &e.detail(),
+                        )?;
+                        match e {
+                            ValidationError::BadSignature { .. } => {}
+                            _ => self.write_event(db, "invalid")?,
+                        }
                     }
-                    bail!("need hash tree");
                 }
-                ValidationError::NeedParentUnits(_) => bail!("unknown parents"),
-            },
+                return Err(e.into());
+            }
         }
 
         Ok(())
@@ -1352,6 +1411,19 @@ impl HubConn {
         Ok(())
     }
 
+    fn send_validation_error_result(
+        &self,
+        unit: &str,
+        error: &validation::ValidationError,
+    ) -> Result<()> {
+        self.send_result(json!({
+            "unit": unit,
+            "result": "error",
+            "error": error.detail(),
+            "error_code": error.code(),
+        }))
+    }
+
     fn purge_joint_and_dependencies_and_notify_peers(
         &self,
         db: &mut Connection,
@@ -1771,22 +1843,64 @@ pub fn find_and_handle_joints_that_are_ready(
         unhandled_joints.push_back(joint);
     }
 
-    while let Some(joint) = unhandled_joints.pop_front() {
-        let ReadyJoint {
-            joint,
-            create_ts,
-            peer,
-        } = joint;
-
-        let ws = match WSS.get_connection_by_name(&peer) {
-            Some(c) => c,
-            None => match WSS.get_next_peer() {
+    // the ready joints don't depend on each other, so the cpu bound checks run
+    // on the thread pool in parallel, the address lock keeps the joints of the
+    // same author in order and the db writes are serialized in Joint::save.
+    // a worker holds one connection for the precheck and validating and one
+    // more for saving, the workers use at most half of the pool
+    let max_workers = ::std::cmp::max(
+        1,
+        ::std::cmp::min(::num_cpus::get(), db::DB_POOL.size() / 4),
+    );
+    while !unhandled_joints.is_empty() {
+        let count = ::std::cmp::min(max_workers, unhandled_joints.len());
+        let mut workers = Vec::with_capacity(count);
+        for joint in unhandled_joints.drain(..count) {
+            let ReadyJoint {
+                joint,
+                create_ts,
+                peer,
+            } = joint;
+
+            let ws = match WSS.get_connection_by_name(&peer) {
                 Some(c) => c,
-                None => bail!("no connection for find_and_handle_joints_that_are_ready"),
-            },
-        };
-        // this is not safe to run in multi-thread, not in parallel
-        ws.handle_saved_joint(db, joint, create_ts, &mut unhandled_joints)?;
+                None => match WSS.get_next_peer() {
+                    Some(c) => c,
+                    None => bail!("no connection for find_and_handle_joints_that_are_ready"),
+                },
+            };
+            workers.push(go!(move || -> Result<VecDeque<ReadyJoint>> {
+                // the job on the pool must not wait for a connection, the
+                // pool threads are shared with the signature batches of the
+                // coroutines that hold the connections
+                let mut db = db::DB_POOL.get_connection();
+                let definitions = validation::read_author_definitions(&db, &joint);
+                let (joint, precheck) = CPU_POOL
+                    .spawn(move || {
+                        let precheck = validation::precheck_with_definitions(&joint, &definitions);
+                        (joint, precheck)
+                    })
+                    .join()?;
+                let mut ready_joints = VecDeque::new();
+                ws.handle_saved_joint(&mut db, joint, create_ts, precheck, &mut ready_joints)?;
+                Ok(ready_joints)
+            }));
+        }
+
+        let mut first_err = None;
+        for worker in workers {
+            match worker.join() {
+                Ok(Ok(mut ready_joints)) => unhandled_joints.append(&mut ready_joints),
+                Ok(Err(e)) => {
+                    error!("handle saved joint failed, err={}", e);
+                    first_err = first_err.or(Some(e));
+                }
+                Err(_) => bail!("handle saved joint panicked"),
+            }
+        }
+        if let Some(e) = first_err {
+            return Err(e);
+        }
     }
 
     // TODO:
@@ -14,6 +14,7 @@ use serde_json::{self, Value};
 use tungstenite::protocol::Role;
 use tungstenite::server::accept;
 use tungstenite::{Message, WebSocket};
+use validation::ValidationError;
 
 macro_rules! t_c {
     ($e:expr) => {
@@ -239,8 +240,15 @@ impl<T> WsConnection<T> {
                                 }
                                 Err(e) => {
                                     error!("{:?}", e);
-                                    let error = json!(e.to_string());
-                                    t!(ws.send_error_response(&tag, error));
+                                    // validation errors carry a code for the peer
+                                    let rsp = match e.downcast_ref::<ValidationError>() {
+                                        Some(e) => json!({
+                                            "error": e.detail(),
+                                            "error_code": e.code(),
+                                        }),
+                                        None => json!({ "error": e.to_string() }),
+                                    };
+                                    t!(ws.send_response(&tag, rsp));
                                 }
                             }
                         });
@@ -292,6 +300,10 @@ impl<T> WsConnection<T> {
         };
 
         let rsp: Response = serde_json::from_value(blocker.wait_rsp(timeout)?[1].take())?;
+        if let Some(code) = rsp.response["error_code"].as_str() {
+            let detail = rsp.response["error"].as_str().unwrap_or("").to_owned();
+            return Err(ValidationError::from_code(code, detail).into());
+        }
         if !rsp.response["error"].is_null() {
             bail!("{} err: {}", command, rsp.response["error"]);
         }
@@ -4,24 +4,39 @@ use std::time::Duration;
 
 use super::network::{Sender, Server, WsConnection};
 use config;
+use crossbeam::atomic::ArcCell;
 use error::Result;
 use failure::ResultExt;
 use joint::Joint;
 use light;
-use light::LastStableBallAndParentUnitsAndWitnessListUnit;
+use light::{DataFeedRequest, LastStableBallAndParentUnitsAndWitnessListUnit};
 use light_wallet;
 use may::coroutine;
 use may::net::TcpStream;
 use my_witness;
+use private_payment;
 use rusqlite::Connection;
 use serde_json::{self, Value};
+use signature::Signer;
+use spec::PrivateElement;
+use storage::DataFeedValue;
 use tungstenite::client::client;
 use tungstenite::handshake::client::Request;
 use tungstenite::protocol::Role;
 use url::Url;
 
-#[derive(Default)]
-pub struct WalletData {}
+pub struct WalletData {
+    // the challenge of the hub, signed to prove that we own our addresses
+    challenge: ArcCell<Option<String>>,
+}
+
+impl Default for WalletData {
+    fn default() -> Self {
+        WalletData {
+            challenge: ArcCell::new(Arc::new(None)),
+        }
+    }
+}
 
 pub type WalletConn = WsConnection<WalletData>;
 
@@ -95,6 +110,54 @@ impl WalletConn {
         Ok(())
     }
 
+    // the chain is built by private_payment::build_private_chain, the hub
+    // keeps it until the payees fetch it
+    pub fn send_private_payment(&self, chain: &[PrivateElement]) -> Result<()> {
+        self.send_request("private_payment", &serde_json::to_value(chain)?)
+            .context("failed to send private payment")?;
+        Ok(())
+    }
+
+    // the hub sends its challenge right after the connection is set up
+    fn wait_for_challenge(&self) -> Result<String> {
+        for _ in 0..50 {
+            if let Some(ref challenge) = *self.get_data().challenge.get() {
+                return Ok(challenge.clone());
+            }
+            coroutine::sleep(Duration::from_millis(100));
+        }
+        bail!("no challenge from the hub");
+    }
+
+    /// fetch the private payments to the addresses we can sign for and save
+    /// them, those whose units are still unknown are requested along with the
+    /// next history
+    pub fn receive_private_payments(&self, db: &mut Connection, signer: &Signer) -> Result<()> {
+        let challenge = self.wait_for_challenge()?;
+        let mut proofs = Vec::new();
+        for address in light_wallet::read_my_addresses(db)? {
+            match private_payment::create_payee_proof(db, &challenge, &address, signer) {
+                Ok(proof) => proofs.push(proof),
+                Err(e) => warn!("can't prove that we own {}, err={}", address, e),
+            }
+        }
+        if proofs.is_empty() {
+            return Ok(());
+        }
+
+        let resp = self
+            .send_request("light/get_private_payments", &serde_json::to_value(proofs)?)
+            .context("failed to get private payments")?;
+        let chains: Vec<Vec<PrivateElement>> = serde_json::from_value(resp)?;
+
+        for chain in chains {
+            if let Err(e) = private_payment::handle_private_chain(db, &chain, self.get_peer()) {
+                error!("failed to handle private payment, err={}", e);
+            }
+        }
+        Ok(())
+    }
+
     pub fn get_parents_and_last_ball_and_witness_list_unit(
         &self,
     ) -> Result<LastStableBallAndParentUnitsAndWitnessListUnit> {
@@ -106,6 +169,13 @@ impl WalletConn {
         Ok(serde_json::from_value(resp)?)
     }
 
+    pub fn get_data_feed(&self, request: &DataFeedRequest) -> Result<Vec<DataFeedValue>> {
+        let resp = self
+            .send_request("light/get_data_feed", &serde_json::to_value(request)?)
+            .context("failed to get data feed")?;
+        Ok(serde_json::from_value(resp)?)
+    }
+
     pub fn refresh_history(&self, db: &Connection) -> Result<()> {
         let req_get_history =
             light_wallet::get_history(db).context("prepare_request_for_history failed")?;
@@ -153,11 +223,13 @@ impl WalletConn {
     }
 
     fn on_hub_challenge(&self, param: Value) -> Result<()> {
-        // TODO: add special wallet logic here
-        // this is hub, we do nothing here
-        // only wallet would save the challenge and save the challenge
-        // for next login and match
+        // signed when fetching the private payments
         info!("peer is a hub, challenge = {}", param);
+        let challenge = match param.as_str() {
+            Some(challenge) => challenge.to_owned(),
+            None => bail!("challenge is not a string"),
+        };
+        self.get_data().challenge.set(Arc::new(Some(challenge)));
         Ok(())
     }
 
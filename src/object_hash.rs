@@ -81,8 +81,13 @@ fn get_checksum(data: &[u8]) -> BitVec {
 }
 
 pub fn is_chash_valid(encoded: &str) -> bool {
-    let chash = base32::decode(base32::Alphabet::RFC4648 { padding: true }, &encoded)
-        .expect("base32 decode return None");
+    if encoded.len() != 32 {
+        return false;
+    }
+    let chash = match base32::decode(base32::Alphabet::RFC4648 { padding: true }, &encoded) {
+        Some(chash) => chash,
+        None => return false,
+    };
 
     let chash = BitVec::from_bytes(&chash);
     let mut checksum = BitVec::new();
@@ -193,4 +198,6 @@ fn test_chash160_validation() {
 
     assert_eq!(is_chash_valid(valid), true);
     assert_eq!(is_chash_valid(invalid), false);
+    assert_eq!(is_chash_valid("1FAR4AK2RSRTAWZ3ILRFZOMN7M7QJTJ2"), false);
+    assert_eq!(is_chash_valid("YFAR4AK2"), false);
 }
@@ -0,0 +1,733 @@
+//! Private payments only post their payload hash and spend proofs to the DAG,
+//! the payload itself is sent to the payee together with the chain of
+//! private payments it spends from, oldest first.
+
+use std::collections::{HashMap, HashSet};
+
+use composer;
+use config;
+use definition;
+use error::Result;
+use object_hash;
+use rusqlite::{Connection, Transaction};
+use serde_json::{self, Value};
+use signature::Signer;
+use spec::{Input, Output, Payment, PrivateElement};
+use storage;
+use validation;
+
+pub fn get_transfer_spend_proof(
+    asset: &str,
+    input: &Input,
+    address: &str,
+    amount: i64,
+    blinding: &Option<String>,
+) -> Result<String> {
+    #[derive(Serialize)]
+    struct TransferSpendProof<'a> {
+        asset: &'a str,
+        unit: &'a Option<String>,
+        message_index: &'a Option<u32>,
+        output_index: &'a Option<u32>,
+        address: &'a str,
+        amount: i64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        blinding: Option<&'a String>,
+    }
+
+    object_hash::get_base64_hash(&TransferSpendProof {
+        asset,
+        unit: &input.unit,
+        message_index: &input.message_index,
+        output_index: &input.output_index,
+        address,
+        amount,
+        blinding: blinding.as_ref(),
+    })
+}
+
+pub fn get_issue_spend_proof(
+    asset: &str,
+    address: &str,
+    amount: i64,
+    serial_number: u32,
+    denomination: Option<u32>,
+) -> Result<String> {
+    // only the coins of fixed denominations assets have a denomination
+    #[derive(Serialize)]
+    struct IssueSpendProof<'a> {
+        asset: &'a str,
+        address: &'a str,
+        amount: i64,
+        serial_number: u32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        denomination: Option<u32>,
+    }
+
+    object_hash::get_base64_hash(&IssueSpendProof {
+        asset,
+        address,
+        amount,
+        serial_number,
+        denomination,
+    })
+}
+
+/// validate and save a private chain received from peer, the chain is kept
+/// in unhandled_private_payments until all its units are known
+pub fn handle_private_chain(
+    db: &mut Connection,
+    chain: &[PrivateElement],
+    peer: &str,
+) -> Result<()> {
+    check_private_chain(chain)?;
+
+    if !process_private_chain(db, chain)? {
+        let head = &chain[chain.len() - 1];
+        let mut stmt =
+            db.prepare_cached("SELECT COUNT(*) FROM unhandled_private_payments WHERE peer=?")?;
+        let count = stmt.query_row(&[&peer], |row| row.get::<_, u32>(0))?;
+        ensure!(
+            count < config::MAX_UNHANDLED_PRIVATE_PAYMENTS_PER_PEER,
+            "too many unhandled private payments from {}",
+            peer
+        );
+
+        info!(
+            "private payment {}:{} depends on unknown units, save for later",
+            head.unit, head.message_index
+        );
+        let mut stmt = db.prepare_cached(
+            "INSERT OR IGNORE INTO unhandled_private_payments \
+             (unit, message_index, output_index, json, peer) VALUES(?,?,-1,?,?)",
+        )?;
+        stmt.execute(&[
+            &head.unit,
+            &head.message_index,
+            &serde_json::to_string(chain)?,
+            &peer,
+        ])?;
+    }
+
+    Ok(())
+}
+
+/// retry the private chains that arrived before their units, the chains
+/// whose units are still unknown after the timeout are dropped
+pub fn handle_saved_private_payments(db: &mut Connection) -> Result<()> {
+    struct SavedChain {
+        unit: String,
+        message_index: u32,
+        json: String,
+    }
+
+    let mut saved_chains = Vec::new();
+    {
+        let mut stmt = db.prepare_cached(
+            "SELECT unit, message_index, json FROM unhandled_private_payments \
+             ORDER BY creation_date",
+        )?;
+        for row in stmt.query_map(&[], |row| SavedChain {
+            unit: row.get(0),
+            message_index: row.get(1),
+            json: row.get(2),
+        })? {
+            saved_chains.push(row?);
+        }
+    }
+
+    for saved in saved_chains {
+        let chain: Vec<PrivateElement> = serde_json::from_str(&saved.json)?;
+        match process_private_chain(db, &chain) {
+            Ok(false) => continue,
+            Ok(true) => info!(
+                "saved private payment {}:{} handled",
+                saved.unit, saved.message_index
+            ),
+            Err(e) => error!(
+                "saved private payment {}:{} is invalid, err={}",
+                saved.unit, saved.message_index, e
+            ),
+        }
+
+        let mut stmt = db.prepare_cached(
+            "DELETE FROM unhandled_private_payments WHERE unit=? AND message_index=?",
+        )?;
+        stmt.execute(&[&saved.unit, &saved.message_index])?;
+    }
+
+    let mut stmt = db.prepare_cached(
+        "DELETE FROM unhandled_private_payments WHERE creation_date < datetime('now', ?)",
+    )?;
+    let count = stmt.execute(&[&format!(
+        "-{} seconds",
+        config::UNHANDLED_PRIVATE_PAYMENT_TIMEOUT
+    )])?;
+    if count > 0 {
+        info!("dropped {} private payments of unknown units", count);
+    }
+
+    Ok(())
+}
+
+/// the units and asset definitions that the saved private chains still wait
+/// for, a light client requests them along with its history
+pub fn read_missing_units(db: &Connection) -> Result<Vec<String>> {
+    let mut stmt = db.prepare_cached("SELECT json FROM unhandled_private_payments")?;
+    let jsons = stmt
+        .query_map(&[], |row| row.get::<_, String>(0))?
+        .collect::<::std::result::Result<Vec<_>, _>>()?;
+
+    let mut units = HashSet::new();
+    for json in jsons {
+        let chain: Vec<PrivateElement> = serde_json::from_str(&json)?;
+        for element in chain {
+            units.extend(element.payload.asset);
+            units.insert(element.unit);
+        }
+    }
+
+    let mut missing_units = Vec::new();
+    for unit in units {
+        let mut stmt = db.prepare_cached("SELECT 1 FROM units WHERE unit=?")?;
+        if !stmt.exists(&[&unit])? {
+            missing_units.push(unit);
+        }
+    }
+    Ok(missing_units)
+}
+
+/// keep the private chain on the hub until the light clients of its payees
+/// fetch it, the hub can't validate the chain as it doesn't know the payload
+/// of the private payments it spends from
+pub fn save_private_chain_for_payees(db: &Connection, chain: &[PrivateElement]) -> Result<()> {
+    check_private_chain(chain)?;
+
+    let head = &chain[chain.len() - 1];
+    let mut payees = head
+        .payload
+        .outputs
+        .iter()
+        .map(|output| &output.address)
+        .collect::<Vec<_>>();
+    payees.sort();
+    payees.dedup();
+
+    let json = serde_json::to_string(chain)?;
+    for address in payees {
+        ensure!(
+            object_hash::is_chash_valid(address),
+            "invalid payee address {}",
+            address
+        );
+        let mut stmt = db.prepare_cached("SELECT COUNT(*) FROM outbox WHERE `to`=?")?;
+        let count = stmt.query_row(&[address], |row| row.get::<_, u32>(0))?;
+        ensure!(
+            count < config::MAX_PENDING_PRIVATE_PAYMENTS_PER_ADDRESS,
+            "too many pending private payments to {}",
+            address
+        );
+
+        let message_hash = object_hash::get_base64_hash(&json!({"to": address, "chain": chain}))?;
+        let mut stmt = db.prepare_cached(
+            "INSERT OR IGNORE INTO outbox (message_hash, `to`, message) VALUES(?,?,?)",
+        )?;
+        stmt.execute(&[&message_hash, address, &json])?;
+    }
+
+    Ok(())
+}
+
+/// the payee signs the challenge of its hub connection to prove that it owns
+/// the address before the hub hands out the private chains paid to it
+#[derive(Serialize, Deserialize)]
+pub struct PayeeProof {
+    pub address: String,
+    pub definition: Value,
+    pub authentifiers: HashMap<String, String>,
+}
+
+fn get_payee_proof_hash_to_sign(challenge: &str, address: &str) -> Result<Vec<u8>> {
+    use sha2::{Digest, Sha256};
+
+    let source_string = ::obj_ser::to_string(&json!({
+        "challenge": challenge,
+        "address": address
+    }))?;
+    Ok(Sha256::digest(source_string.as_bytes()).to_vec())
+}
+
+/// sign the challenge with the current definition of the address, the same
+/// one that the composer signs the units with
+pub fn create_payee_proof(
+    db: &Connection,
+    challenge: &str,
+    address: &String,
+    signer: &Signer,
+) -> Result<PayeeProof> {
+    let definition_chash = storage::read_definition_chash_by_address(db, address, ::std::u32::MAX)?;
+    let definition = composer::read_definition(db, address, &definition_chash)?;
+    let hash = get_payee_proof_hash_to_sign(challenge, address)?;
+    let mut authentifiers = HashMap::new();
+    authentifiers.insert("r".to_owned(), signer.sign(&hash, address)?);
+    Ok(PayeeProof {
+        address: address.clone(),
+        definition,
+        authentifiers,
+    })
+}
+
+/// the definition must be the current one of the address and only the
+/// definitions that can be verified without the db are accepted
+pub fn check_payee_proof(db: &Connection, challenge: &str, proof: &PayeeProof) -> Result<()> {
+    let definition_chash =
+        storage::read_definition_chash_by_address(db, &proof.address, ::std::u32::MAX)?;
+    ensure!(
+        object_hash::get_chash(&proof.definition)? == definition_chash,
+        "wrong definition of payee {}",
+        proof.address
+    );
+    let hash = get_payee_proof_hash_to_sign(challenge, &proof.address)?;
+    ensure!(
+        definition::verify_authentifiers_offline(&proof.definition, &proof.authentifiers, &hash)?,
+        "the proof of payee {} can't be verified offline",
+        proof.address
+    );
+    Ok(())
+}
+
+/// the private chains kept on the hub for the addresses, the payees are
+/// checked by the caller and the chains are removed once they are delivered
+pub fn take_private_chains_for_payees(
+    db: &Connection,
+    addresses: &[String],
+) -> Result<Vec<Vec<PrivateElement>>> {
+    let mut chains = Vec::new();
+    for address in addresses {
+        let mut stmt = db.prepare_cached(
+            "SELECT message_hash, message FROM outbox WHERE `to`=? ORDER BY creation_date",
+        )?;
+        let rows = stmt
+            .query_map(&[address], |row| {
+                (row.get::<_, String>(0), row.get::<_, String>(1))
+            })?
+            .collect::<::std::result::Result<Vec<_>, _>>()?;
+        for (message_hash, json) in rows {
+            chains.push(serde_json::from_str(&json)?);
+            let mut stmt = db.prepare_cached("DELETE FROM outbox WHERE message_hash=?")?;
+            stmt.execute(&[&message_hash])?;
+        }
+    }
+    Ok(chains)
+}
+
+/// drop the private chains that no payee fetched in time
+pub fn purge_expired_private_chains(db: &Connection) -> Result<()> {
+    let mut stmt =
+        db.prepare_cached("DELETE FROM outbox WHERE creation_date < datetime('now', ?)")?;
+    let count = stmt.execute(&[&format!(
+        "-{} seconds",
+        config::PENDING_PRIVATE_PAYMENT_TIMEOUT
+    )])?;
+    if count > 0 {
+        info!("purged {} expired private payments", count);
+    }
+    Ok(())
+}
+
+/// collect the private payments that the element spends from, the element
+/// itself is the last one in the returned chain
+pub fn build_private_chain(
+    db: &Connection,
+    element: PrivateElement,
+) -> Result<Vec<PrivateElement>> {
+    let mut chain = Vec::new();
+    let mut visited = HashSet::new();
+    visited.insert((element.unit.clone(), element.message_index));
+
+    // post order traversal, so that the sources always go before their spenders
+    let mut stack = vec![(element, false)];
+    while let Some((element, is_expanded)) = stack.pop() {
+        if is_expanded {
+            chain.push(element);
+            continue;
+        }
+
+        let src_messages = element
+            .payload
+            .inputs
+            .iter()
+            .filter(|input| input.kind.as_ref().map_or(true, |kind| kind == "transfer"))
+            .map(|input| (input.unit.clone().unwrap(), input.message_index.unwrap()))
+            .collect::<Vec<_>>();
+        stack.push((element, true));
+
+        for (unit, message_index) in src_messages {
+            if visited.insert((unit.clone(), message_index)) {
+                stack.push((read_private_element(db, &unit, message_index)?, false));
+            }
+        }
+    }
+
+    Ok(chain)
+}
+
+// the units of the chain end up in the history requests of light clients
+fn check_private_chain(chain: &[PrivateElement]) -> Result<()> {
+    ensure!(!chain.is_empty(), "empty private chain");
+    for element in chain {
+        ensure!(
+            validation::is_valid_base64(&element.unit, config::HASH_LENGTH),
+            "invalid unit in private chain"
+        );
+        match element.payload.asset {
+            Some(ref asset) if validation::is_valid_base64(asset, config::HASH_LENGTH) => {}
+            _ => bail!("invalid asset in private chain"),
+        }
+    }
+    Ok(())
+}
+
+// return false if some units of the chain are still unknown
+fn process_private_chain(db: &mut Connection, chain: &[PrivateElement]) -> Result<bool> {
+    for element in chain {
+        let mut stmt = db.prepare_cached("SELECT 1 FROM units WHERE unit=?")?;
+        if !stmt.exists(&[&element.unit])? {
+            return Ok(false);
+        }
+    }
+
+    let tx = db.transaction()?;
+    for element in chain {
+        let mut stmt =
+            tx.prepare_cached("SELECT 1 FROM outputs WHERE unit=? AND message_index=?")?;
+        if stmt.exists(&[&element.unit, &element.message_index])? {
+            continue;
+        }
+
+        validation::validate_private_payment(&tx, element)?;
+        save_private_payment(&tx, element)?;
+    }
+    tx.commit()?;
+
+    Ok(true)
+}
+
+fn save_private_payment(tx: &Transaction, element: &PrivateElement) -> Result<()> {
+    let payment = &element.payload;
+    let denomination = payment.denomination.unwrap_or(1);
+
+    for (i, input) in payment.inputs.iter().enumerate() {
+        let kind = input.kind.clone().unwrap_or_else(|| "transfer".to_owned());
+        let address = if kind == "issue" {
+            match input.address {
+                Some(ref address) => address.clone(),
+                None => storage::read_unit_authors(tx, &element.unit)?[0].clone(),
+            }
+        } else {
+            let mut stmt = tx.prepare_cached(
+                "SELECT address FROM outputs WHERE unit=? AND message_index=? AND output_index=?",
+            )?;
+            let address: String = stmt.query_row(
+                &[&input.unit, &input.message_index, &input.output_index],
+                |row| row.get(0),
+            )?;
+
+            let mut stmt = tx.prepare_cached(
+                "UPDATE outputs SET is_spent=1 \
+                 WHERE unit=? AND message_index=? AND output_index=?",
+            )?;
+            stmt.execute(&[&input.unit, &input.message_index, &input.output_index])?;
+            address
+        };
+
+        let mut stmt = tx.prepare_cached(
+            "INSERT INTO inputs \
+             (unit, message_index, input_index, type, \
+             src_unit, src_message_index, src_output_index, \
+             denomination, amount, serial_number, \
+             asset, is_unique, address) \
+             VALUES(?,?,?,?,?,?,?,?,?,?,?,1,?)",
+        )?;
+        stmt.execute(&[
+            &element.unit,
+            &element.message_index,
+            &(i as u32),
+            &kind,
+            &input.unit,
+            &input.message_index,
+            &input.output_index,
+            &denomination,
+            &input.amount,
+            &input.serial_number,
+            &payment.asset,
+            &address,
+        ])?;
+    }
+
+    for (i, output) in payment.outputs.iter().enumerate() {
+        let mut stmt = tx.prepare_cached(
+            "INSERT INTO outputs \
+             (unit, message_index, output_index, address, \
+             amount, asset, denomination, blinding, is_serial) \
+             VALUES(?,?,?,?,?,?,?,?,1)",
+        )?;
+        stmt.execute(&[
+            &element.unit,
+            &element.message_index,
+            &(i as u32),
+            &output.address,
+            &output.amount,
+            &payment.asset,
+            &denomination,
+            &output.blinding,
+        ])?;
+    }
+
+    Ok(())
+}
+
+// restore the private payload from the saved inputs and outputs
+fn read_private_element(
+    db: &Connection,
+    unit: &String,
+    message_index: u32,
+) -> Result<PrivateElement> {
+    struct InputRow {
+        kind: String,
+        src_unit: Option<String>,
+        src_message_index: Option<u32>,
+        src_output_index: Option<u32>,
+        amount: Option<i64>,
+        serial_number: Option<u32>,
+        address: String,
+        asset: Option<String>,
+        denomination: u32,
+    }
+
+    let mut stmt = db.prepare_cached(
+        "SELECT type, src_unit, src_message_index, src_output_index, \
+         amount, serial_number, address, asset, denomination \
+         FROM inputs WHERE unit=? AND message_index=? ORDER BY input_index",
+    )?;
+    let input_rows = stmt
+        .query_map(&[unit, &message_index], |row| InputRow {
+            kind: row.get(0),
+            src_unit: row.get(1),
+            src_message_index: row.get(2),
+            src_output_index: row.get(3),
+            amount: row.get(4),
+            serial_number: row.get(5),
+            address: row.get(6),
+            asset: row.get(7),
+            denomination: row.get(8),
+        })?
+        .collect::<::std::result::Result<Vec<_>, _>>()?;
+    ensure!(
+        !input_rows.is_empty(),
+        "private payment {}:{} not found",
+        unit,
+        message_index
+    );
+
+    let asset = match input_rows[0].asset {
+        Some(ref asset) => asset.clone(),
+        None => bail!("private payment {}:{} has no asset", unit, message_index),
+    };
+    let denomination = input_rows[0].denomination;
+    let is_multi_authored = storage::read_unit_authors(db, unit)?.len() > 1;
+
+    let inputs = input_rows
+        .into_iter()
+        .map(|row| {
+            let address = some_if!(is_multi_authored, row.address);
+            if row.kind == "issue" {
+                Input {
+                    kind: Some(row.kind),
+                    amount: row.amount,
+                    serial_number: row.serial_number,
+                    address,
+                    ..Default::default()
+                }
+            } else {
+                Input {
+                    unit: row.src_unit,
+                    message_index: row.src_message_index,
+                    output_index: row.src_output_index,
+                    address,
+                    ..Default::default()
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let mut stmt = db.prepare_cached(
+        "SELECT address, amount, blinding FROM outputs \
+         WHERE unit=? AND message_index=? ORDER BY output_index",
+    )?;
+    let outputs = stmt
+        .query_map(&[unit, &message_index], |row| Output {
+            address: row.get(0),
+            amount: row.get(1),
+            blinding: row.get(2),
+        })?
+        .collect::<::std::result::Result<Vec<_>, _>>()?;
+
+    let asset_info = match storage::read_asset_info(db, &asset)? {
+        Some(info) => info,
+        None => bail!("asset {} not found", asset),
+    };
+
+    let payload = Payment {
+        address: None,
+        asset: Some(asset),
+        definition_chash: None,
+        denomination: some_if!(asset_info.fixed_denominations, denomination),
+        inputs,
+        outputs,
+    };
+
+    let mut stmt =
+        db.prepare_cached("SELECT payload_hash FROM messages WHERE unit=? AND message_index=?")?;
+    let payload_hash: String = stmt.query_row(&[unit, &message_index], |row| row.get(0))?;
+    ensure!(
+        object_hash::get_base64_hash(&payload)? == payload_hash,
+        "restored private payment {}:{} does not match its payload hash",
+        unit,
+        message_index
+    );
+
+    Ok(PrivateElement {
+        unit: unit.clone(),
+        message_index,
+        payload,
+    })
+}
+
+#[test]
+fn test_issue_spend_proof() {
+    let asset = "JY4RvlUGv0qWItikizmNOIjIYZeEciODOog8AzLju50=";
+    let address = "YFAR4AK2RSRTAWZ3ILRFZOMN7M7QJTJ2";
+
+    // divisible assets have no denomination in their spend proofs
+    let spend_proof = get_issue_spend_proof(asset, address, 1000, 1, None).unwrap();
+    let expected = object_hash::get_base64_hash(&json!({
+        "asset": asset,
+        "address": address,
+        "amount": 1000,
+        "serial_number": 1
+    }))
+    .unwrap();
+    assert_eq!(spend_proof, expected);
+
+    let spend_proof = get_issue_spend_proof(asset, address, 1000, 1, Some(1)).unwrap();
+    assert_ne!(spend_proof, expected);
+}
+
+#[test]
+fn test_unhandled_private_payments_per_peer() {
+    use db;
+
+    let mut db = db::DB_POOL.get_connection();
+    let peer = "test_unhandled_private_payments_per_peer";
+    let chain = |unit: String| {
+        vec![PrivateElement {
+            unit,
+            message_index: 0,
+            payload: Payment {
+                address: None,
+                asset: Some("JY4RvlUGv0qWItikizmNOIjIYZeEciODOog8AzLju50=".to_owned()),
+                definition_chash: None,
+                denomination: None,
+                inputs: Vec::new(),
+                outputs: Vec::new(),
+            },
+        }]
+    };
+
+    assert!(handle_private_chain(&mut db, &chain("' OR 1=1".to_owned()), peer).is_err());
+
+    // chains of unknown units are saved until the peer reaches its limit
+    let mut results = Vec::new();
+    for i in 0..=config::MAX_UNHANDLED_PRIVATE_PAYMENTS_PER_PEER {
+        let unit = object_hash::get_base64_hash(&format!("unit {}", i)).unwrap();
+        results.push(handle_private_chain(&mut db, &chain(unit), peer).is_ok());
+    }
+    db.execute(
+        "DELETE FROM unhandled_private_payments WHERE peer=?",
+        &[&peer],
+    )
+    .unwrap();
+
+    assert!(results[..results.len() - 1].iter().all(|ok| *ok));
+    assert!(!results[results.len() - 1]);
+}
+
+#[test]
+fn test_payee_proof() {
+    use base64;
+    use secp256k1::key::{PublicKey, SecretKey};
+    use secp256k1::Secp256k1;
+    use signature;
+
+    struct TestSigner(Vec<u8>);
+    impl Signer for TestSigner {
+        fn sign(&self, hash: &[u8], _address: &str) -> Result<String> {
+            signature::sign(hash, &self.0)
+        }
+    }
+
+    let prvk = base64::decode("jQGnkLnZlX2DjBUd8JKgHgw23zSdRL/Azx3foi/WqvE=").unwrap();
+    let secp = Secp256k1::new();
+    let secret_key = SecretKey::from_slice(&secp, &prvk).unwrap();
+    let pubkey = PublicKey::from_secret_key(&secp, &secret_key).unwrap();
+    let definition = json!(["sig", {"pubkey": base64::encode(&pubkey.serialize()[..])}]);
+    let address = object_hash::get_chash(&definition).unwrap();
+
+    let db = Connection::open_in_memory().unwrap();
+    db.execute_batch(
+        "CREATE TABLE units (unit CHAR(44), main_chain_index INT, level INT, \
+            is_stable TINYINT, sequence TEXT); \
+         CREATE TABLE address_definition_changes (unit CHAR(44), address CHAR(32), \
+            definition_chash CHAR(32)); \
+         CREATE TABLE my_addresses (address CHAR(32), definition TEXT); \
+         CREATE TABLE shared_addresses (shared_address CHAR(32), definition TEXT); \
+         CREATE TABLE definitions (definition_chash CHAR(32), definition TEXT); \
+         CREATE TABLE outbox (message_hash CHAR(44) PRIMARY KEY, `to` CHAR(33), \
+            message TEXT, creation_date TIMESTAMP DEFAULT CURRENT_TIMESTAMP);",
+    )
+    .unwrap();
+    db.execute(
+        "INSERT INTO my_addresses VALUES (?, ?)",
+        &[&address, &definition.to_string()],
+    )
+    .unwrap();
+
+    let proof = create_payee_proof(&db, "challenge", &address, &TestSigner(prvk)).unwrap();
+    check_payee_proof(&db, "challenge", &proof).unwrap();
+    // the proof is only good for the challenge of the connection
+    assert!(check_payee_proof(&db, "another challenge", &proof).is_err());
+    // and for the address it is made for
+    let other = PayeeProof {
+        address: "YFAR4AK2RSRTAWZ3ILRFZOMN7M7QJTJ2".to_owned(),
+        ..proof
+    };
+    assert!(check_payee_proof(&db, "challenge", &other).is_err());
+
+    // the chains are delivered only once
+    db.execute(
+        "INSERT INTO outbox (message_hash, `to`, message) VALUES ('hash', ?, '[]')",
+        &[&address],
+    )
+    .unwrap();
+    let addresses = vec![address];
+    assert_eq!(
+        take_private_chains_for_payees(&db, &addresses)
+            .unwrap()
+            .len(),
+        1
+    );
+    assert!(take_private_chains_for_payees(&db, &addresses)
+        .unwrap()
+        .is_empty());
+}
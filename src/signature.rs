@@ -1,11 +1,22 @@
 use base64;
+use config;
 use error::Result;
 use failure::ResultExt;
+use num_cpus;
 use secp256k1::{key, Message, Secp256k1, Signature};
+use utils::{FifoCache, CPU_POOL};
+
+// fewer signatures are verified in place, the pool only pays off for more
+const MIN_SIGNATURES_IN_PARALLEL: usize = 8;
 
 lazy_static! {
     // initialize consume too much memory, init it in thread context
     static ref SECP256K1: Secp256k1 = ::std::thread::spawn(|| Secp256k1::new()).join().unwrap();
+    // the signature is part of the key, the unit hash doesn't cover the
+    // authentifiers so the same unit may come with a different signature.
+    // the failures are kept too, verifying them again gives the same result
+    static ref VERIFIED_SIGNATURES: FifoCache<(Vec<u8>, String, String), bool> =
+        FifoCache::with_capacity(config::MAX_SIGNATURES_IN_CACHE);
 }
 
 pub trait Signer {
@@ -27,6 +38,69 @@ pub fn sign(hash: &[u8], priv_key: &[u8]) -> Result<String> {
 
 /// verify the bas64 string signiture with the hash and pub key (a bas64 string)
 pub fn verify(hash: &[u8], b64_sig: &str, b64_pub_key: &str) -> Result<()> {
+    let key = (hash.to_vec(), b64_pub_key.to_owned(), b64_sig.to_owned());
+    match VERIFIED_SIGNATURES.get(&key) {
+        Some(true) => return Ok(()),
+        Some(false) => bail!("signature verification failed"),
+        None => {}
+    }
+    let ret = verify_uncached(hash, b64_sig, b64_pub_key);
+    VERIFIED_SIGNATURES.insert(key, ret.is_ok());
+    ret
+}
+
+/// verify a batch of (hash, signature, pub key), every result is cached and
+/// the first failure is returned. a large batch is spread over the cpu pool,
+/// so this must not be called from a job of the pool
+pub fn verify_batch(checks: &[(&[u8], &str, &str)]) -> Result<()> {
+    let mut pending = Vec::new();
+    for &(hash, b64_sig, b64_pub_key) in checks {
+        let key = (hash.to_vec(), b64_pub_key.to_owned(), b64_sig.to_owned());
+        match VERIFIED_SIGNATURES.get(&key) {
+            Some(true) => {}
+            Some(false) => bail!("signature verification failed"),
+            None => {
+                if !pending.contains(&key) {
+                    pending.push(key);
+                }
+            }
+        }
+    }
+
+    if pending.len() < MIN_SIGNATURES_IN_PARALLEL {
+        return verify_all(pending);
+    }
+
+    let chunk_size = (pending.len() + num_cpus::get() - 1) / num_cpus::get();
+    let jobs = pending
+        .chunks(chunk_size)
+        .map(|chunk| {
+            let chunk = chunk.to_vec();
+            CPU_POOL.spawn(move || verify_all(chunk))
+        })
+        .collect::<Vec<_>>();
+
+    let mut first_err = None;
+    for job in jobs {
+        if let Err(e) = job.join().and_then(|ret| ret) {
+            first_err = first_err.or(Some(e));
+        }
+    }
+    first_err.map_or(Ok(()), Err)
+}
+
+// verify every signature so that all the results are cached
+fn verify_all(keys: Vec<(Vec<u8>, String, String)>) -> Result<()> {
+    let mut first_err = None;
+    for (hash, b64_pub_key, b64_sig) in keys {
+        if let Err(e) = verify(&hash, &b64_sig, &b64_pub_key) {
+            first_err = first_err.or(Some(e));
+        }
+    }
+    first_err.map_or(Ok(()), Err)
+}
+
+fn verify_uncached(hash: &[u8], b64_sig: &str, b64_pub_key: &str) -> Result<()> {
     let msg = Message::from_slice(hash)?;
     let sig = &base64::decode(b64_sig)?;
     let pub_key = key::PublicKey::from_slice(&SECP256K1, &base64::decode(b64_pub_key)?)?;
@@ -61,3 +135,44 @@ fn test_signature() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_verify_batch() -> Result<()> {
+    let hash = base64::decode("uPQs4TwLtDGRAdH8sbIJ1ZyWpEmwHWRAhXpamODZ7Kk=")?;
+    let pub_key = "A0qTjB3ZjHf2yT1EIvLrkVAWY8MPSueNcB4GTlKGo/o6";
+    let sig =
+        "up+2Fjhnu4OjJeesBPCgoZE+6ReqQDdnqcjhbq2iaulHjlwKYLcwRrD3udSWdHS57ceQeZ+LVPWYBMWBloAgpA==";
+    let bad_sig =
+        "YCdh5Q6jOiKQy2R9mQwKJ6tBnq31VFZX2dkb7Ypr+/5z6jj4GLEFT9RtryC4+mSILtKKLeN9YnBmYI4Xa+4tDw==";
+
+    verify_batch(&[(&hash[..], sig, pub_key), (&hash[..], sig, pub_key)])?;
+    // a cached good signature must not let another signature pass
+    verify(&hash[..], sig, pub_key)?;
+    assert!(verify(&hash[..], bad_sig, pub_key).is_err());
+    assert!(verify_batch(&[(&hash[..], sig, pub_key), (&hash[..], bad_sig, pub_key)]).is_err());
+    // the cached failure is still a failure
+    assert!(verify(&hash[..], bad_sig, pub_key).is_err());
+
+    // a large batch runs on the pool, a bad signature fails the whole batch
+    // and the others are still verified
+    let hashes = (0..MIN_SIGNATURES_IN_PARALLEL as u8 * 2)
+        .map(|i| {
+            let mut hash = hash.clone();
+            hash[0] = i;
+            hash
+        })
+        .collect::<Vec<_>>();
+    let mut checks = hashes
+        .iter()
+        .map(|hash| (&hash[..], bad_sig, pub_key))
+        .collect::<Vec<_>>();
+    checks.push((&hash[..], sig, pub_key));
+    assert!(verify_batch(&checks).is_err());
+    for hash in &hashes {
+        let key = (hash.clone(), pub_key.to_owned(), bad_sig.to_owned());
+        assert_eq!(VERIFIED_SIGNATURES.get(&key), Some(false));
+    }
+    verify(&hash[..], sig, pub_key)?;
+
+    Ok(())
+}
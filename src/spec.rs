@@ -68,6 +68,9 @@ pub struct Message {
 pub struct Output {
     pub address: String,
     pub amount: i64,
+    // only private outputs are blinded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blinding: Option<String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -92,6 +95,84 @@ pub struct Payment {
     pub outputs: Vec<Output>,
 }
 
+// the payload of a private payment message, which is sent to the payee
+// instead of being posted to the DAG
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PrivateElement {
+    pub unit: String,
+    pub message_index: u32,
+    pub payload: Payment,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AssetDenomination {
+    pub denomination: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count_coins: Option<i64>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AssetDefinition {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cap: Option<i64>,
+    pub is_private: bool,
+    pub is_transferrable: bool,
+    pub auto_destroy: bool,
+    pub fixed_denominations: bool,
+    pub issued_by_definer_only: bool,
+    pub cosigned_by_definer: bool,
+    pub spender_attested: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issue_condition: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transfer_condition: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attestors: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub denominations: Option<Vec<AssetDenomination>>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AddressDefinitionChange {
+    // only multi-authored units need to tell which address is changed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    pub definition_chash: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AssetAttestors {
+    pub asset: String,
+    pub attestors: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Attestation {
+    // the attested address
+    pub address: String,
+    pub profile: ::serde_json::Map<String, Value>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Poll {
+    pub question: String,
+    pub choices: Vec<String>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Vote {
+    // the unit of the poll
+    pub unit: String,
+    pub choice: String,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct HeaderCommissionShare {
     pub address: String,
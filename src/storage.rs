@@ -26,6 +26,9 @@ lazy_static! {
             .unwrap_or(None)
             .unwrap_or(0)
     });
+    // the last mci whose units are checked for pruning, the skipped units
+    // don't show up in pruned_units
+    static ref LAST_PRUNED_MCI: RwLock<Option<u32>> = RwLock::new(None);
     static ref CACHED_UNIT: FifoCache<String, StaticUnitProperty> =
         FifoCache::with_capacity(config::MAX_ITEMS_IN_CACHE);
     static ref KNOWN_UNIT: FifoCache<String, ()> =
@@ -34,7 +37,7 @@ lazy_static! {
         FifoCache::with_capacity(config::MAX_ITEMS_IN_CACHE);
     static ref CACHED_UNIT_WITNESSES: FifoCache<String, Vec<String>> =
         FifoCache::with_capacity(config::MAX_ITEMS_IN_CACHE);
-    static ref _CACHED_ASSET_INFOS: FifoCache<String, Option<String>> =
+    static ref CACHED_ASSET_INFOS: FifoCache<String, AssetInfo> =
         FifoCache::with_capacity(config::MAX_ITEMS_IN_CACHE);
 }
 
@@ -231,6 +234,346 @@ pub fn read_last_stable_mc_unit_props(db: &Connection) -> Result<Option<LastStab
     Ok(Some(props.swap_remove(0)))
 }
 
+#[derive(Debug, Clone)]
+pub struct AssetInfo {
+    pub asset: String,
+    pub cap: Option<i64>,
+    pub is_private: bool,
+    pub is_transferrable: bool,
+    pub auto_destroy: bool,
+    pub fixed_denominations: bool,
+    pub issued_by_definer_only: bool,
+    pub cosigned_by_definer: bool,
+    pub spender_attested: bool,
+    pub issue_condition: Option<Value>,
+    pub transfer_condition: Option<Value>,
+    pub main_chain_index: Option<u32>,
+    pub sequence: String,
+    pub is_stable: bool,
+    pub definer_address: String,
+}
+
+// return None if the asset is not defined yet
+pub fn read_asset_info(db: &Connection, asset: &String) -> Result<Option<AssetInfo>> {
+    if let Some(info) = CACHED_ASSET_INFOS.get(asset) {
+        return Ok(Some(info));
+    }
+
+    let mut stmt = db.prepare_cached(
+        "SELECT cap, is_private, is_transferrable, auto_destroy, fixed_denominations, \
+         issued_by_definer_only, cosigned_by_definer, spender_attested, \
+         issue_condition, transfer_condition, main_chain_index, sequence, is_stable, address \
+         FROM assets JOIN units USING(unit) JOIN unit_authors USING(unit) WHERE unit=?",
+    )?;
+    let rows = stmt
+        .query_map(&[asset], |row| {
+            let info = AssetInfo {
+                asset: asset.clone(),
+                cap: row.get(0),
+                is_private: row.get(1),
+                is_transferrable: row.get(2),
+                auto_destroy: row.get(3),
+                fixed_denominations: row.get(4),
+                issued_by_definer_only: row.get(5),
+                cosigned_by_definer: row.get(6),
+                spender_attested: row.get(7),
+                issue_condition: None,
+                transfer_condition: None,
+                main_chain_index: row.get(10),
+                sequence: row.get(11),
+                is_stable: row.get(12),
+                definer_address: row.get(13),
+            };
+            let issue_condition: Option<String> = row.get(8);
+            let transfer_condition: Option<String> = row.get(9);
+            (info, issue_condition, transfer_condition)
+        })?.collect::<::std::result::Result<Vec<_>, _>>()?;
+
+    ensure!(rows.len() <= 1, "more than one asset {}", asset);
+    let (mut info, issue_condition, transfer_condition) = match rows.into_iter().next() {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    if let Some(condition) = issue_condition {
+        info.issue_condition = Some(serde_json::from_str(&condition)?);
+    }
+    if let Some(condition) = transfer_condition {
+        info.transfer_condition = Some(serde_json::from_str(&condition)?);
+    }
+
+    // only cache the stable definitions
+    if info.is_stable {
+        CACHED_ASSET_INFOS.insert(asset.clone(), info.clone());
+    }
+    Ok(Some(info))
+}
+
+pub fn read_poll(db: &Connection, poll_unit: &String) -> Result<Option<Poll>> {
+    let mut stmt = db.prepare_cached("SELECT question FROM polls WHERE unit=?")?;
+    let questions = stmt
+        .query_map(&[poll_unit], |row| row.get(0))?
+        .collect::<::std::result::Result<Vec<String>, _>>()?;
+    let question = match questions.into_iter().next() {
+        Some(question) => question,
+        None => return Ok(None),
+    };
+
+    let mut stmt =
+        db.prepare_cached("SELECT choice FROM poll_choices WHERE unit=? ORDER BY choice_index")?;
+    let choices = stmt
+        .query_map(&[poll_unit], |row| row.get(0))?
+        .collect::<::std::result::Result<Vec<String>, _>>()?;
+
+    Ok(Some(Poll { question, choices }))
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PollChoiceResult {
+    pub choice: String,
+    pub count_votes: u32,
+}
+
+/// tally the votes of a poll that are stable at the mci, every choice is listed in order
+pub fn read_poll_results(
+    db: &Connection,
+    poll_unit: &String,
+    mci: u32,
+) -> Result<Vec<PollChoiceResult>> {
+    ensure!(
+        mci <= read_last_stable_mc_index(db)?,
+        "mci {} is not stable yet",
+        mci
+    );
+
+    let mut stmt =
+        db.prepare_cached("SELECT choice FROM poll_choices WHERE unit=? ORDER BY choice_index")?;
+    let choices = stmt
+        .query_map(&[poll_unit], |row| row.get(0))?
+        .collect::<::std::result::Result<Vec<String>, _>>()?;
+    ensure!(!choices.is_empty(), "poll {} not found", poll_unit);
+
+    // an address can change its vote, only its latest stable one counts
+    let mut stmt = db.prepare_cached(
+        "SELECT address, choice FROM votes \
+         CROSS JOIN units USING(unit) CROSS JOIN unit_authors USING(unit) \
+         WHERE poll_unit=? AND is_stable=1 AND sequence='good' AND main_chain_index<=? \
+         ORDER BY main_chain_index, level, unit, message_index",
+    )?;
+    let votes = stmt
+        .query_map(&[poll_unit, &mci], |row| {
+            (row.get::<_, String>(0), row.get::<_, String>(1))
+        })?
+        .collect::<::std::result::Result<Vec<_>, _>>()?;
+    let mut latest_votes = HashMap::new();
+    for (address, choice) in votes {
+        latest_votes.insert(address, choice);
+    }
+
+    let results = choices
+        .into_iter()
+        .map(|choice| PollChoiceResult {
+            count_votes: latest_votes.values().filter(|c| **c == choice).count() as u32,
+            choice,
+        })
+        .collect();
+    Ok(results)
+}
+
+/// the latest stable profile posted by the address
+pub fn read_profile(db: &Connection, address: &String) -> Result<Option<Value>> {
+    let mut stmt = db.prepare_cached(
+        "SELECT payload FROM messages \
+         CROSS JOIN unit_authors USING(unit) CROSS JOIN units USING(unit) \
+         WHERE app='profile' AND address=? AND is_stable=1 AND sequence='good' \
+         ORDER BY main_chain_index DESC, level DESC LIMIT 1",
+    )?;
+    let rows = stmt
+        .query_map(&[address], |row| row.get(0))?
+        .collect::<::std::result::Result<Vec<Option<String>>, _>>()?;
+    // the payload is not kept when it is posted by uri or pruned
+    match rows.into_iter().next() {
+        Some(Some(payload)) => Ok(Some(serde_json::from_str(&payload)?)),
+        _ => Ok(None),
+    }
+}
+
+/// the payloads of all the data messages in the unit
+pub fn read_data_messages(db: &Connection, unit: &String) -> Result<Vec<Value>> {
+    let mut stmt = db.prepare_cached(
+        "SELECT payload FROM messages WHERE unit=? AND app='data' ORDER BY message_index",
+    )?;
+    let rows = stmt
+        .query_map(&[unit], |row| row.get(0))?
+        .collect::<::std::result::Result<Vec<Option<String>>, _>>()?;
+    let mut payloads = Vec::new();
+    // skip the payloads posted by uri or pruned
+    for payload in rows.into_iter().flatten() {
+        payloads.push(serde_json::from_str(&payload)?);
+    }
+    Ok(payloads)
+}
+
+/// the definition template posted in the unit
+pub fn read_definition_template(db: &Connection, unit: &String) -> Result<Option<Value>> {
+    let mut stmt = db.prepare_cached(
+        "SELECT payload FROM messages WHERE unit=? AND app='definition_template'",
+    )?;
+    let rows = stmt
+        .query_map(&[unit], |row| row.get(0))?
+        .collect::<::std::result::Result<Vec<Option<String>>, _>>()?;
+    // the payload is not kept when it is posted by uri or pruned
+    match rows.into_iter().next() {
+        Some(Some(payload)) => Ok(Some(serde_json::from_str(&payload)?)),
+        _ => Ok(None),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DataFeedValue {
+    pub unit: String,
+    pub main_chain_index: u32,
+    pub address: String,
+    pub feed_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub int_value: Option<i64>,
+}
+
+// stable feeds of the name posted by any of the oracles, the params are bound
+// after the feed name
+fn read_data_feeds(
+    db: &Connection,
+    oracles: &[String],
+    feed_name: &str,
+    condition: &str,
+    params: &[&rusqlite::types::ToSql],
+    order_and_limit: &str,
+) -> Result<Vec<DataFeedValue>> {
+    ensure!(!oracles.is_empty(), "no oracles");
+    for oracle in oracles {
+        ensure!(
+            ::object_hash::is_chash_valid(oracle),
+            "invalid oracle address {}",
+            oracle
+        );
+    }
+    let oracle_list = oracles
+        .iter()
+        .map(|s| format!("'{}'", s))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    let sql = format!(
+        "SELECT unit, main_chain_index, address, feed_name, value, int_value \
+         FROM data_feeds CROSS JOIN units USING(unit) CROSS JOIN unit_authors USING(unit) \
+         WHERE address IN({}) AND feed_name=? AND {} AND sequence='good' AND is_stable=1 \
+         {}",
+        oracle_list, condition, order_and_limit
+    );
+    let mut all_params: Vec<&rusqlite::types::ToSql> = vec![&feed_name];
+    all_params.extend_from_slice(params);
+
+    let mut stmt = db.prepare(&sql)?;
+    let feeds = stmt
+        .query_map(&all_params, |row| DataFeedValue {
+            unit: row.get(0),
+            main_chain_index: row.get(1),
+            address: row.get(2),
+            feed_name: row.get(3),
+            value: row.get(4),
+            int_value: row.get(5),
+        })?
+        .collect::<::std::result::Result<Vec<_>, _>>()?;
+    Ok(feeds)
+}
+
+/// the latest value of the feed posted by any of the oracles as of the mci
+pub fn read_latest_data_feed(
+    db: &Connection,
+    oracles: &[String],
+    feed_name: &str,
+    max_mci: u32,
+) -> Result<Option<DataFeedValue>> {
+    let feeds = read_data_feeds(
+        db,
+        oracles,
+        feed_name,
+        "main_chain_index<=?",
+        &[&max_mci],
+        "ORDER BY main_chain_index DESC, level DESC LIMIT 1",
+    )?;
+    Ok(feeds.into_iter().next())
+}
+
+/// the values of the feed posted by the oracles in the mci range, the oldest `limit` ones
+pub fn read_data_feeds_in_mci_range(
+    db: &Connection,
+    oracles: &[String],
+    feed_name: &str,
+    min_mci: u32,
+    max_mci: u32,
+    limit: usize,
+) -> Result<Vec<DataFeedValue>> {
+    read_data_feeds(
+        db,
+        oracles,
+        feed_name,
+        "main_chain_index>=? AND main_chain_index<=?",
+        &[&min_mci, &max_mci],
+        &format!("ORDER BY main_chain_index, level LIMIT {}", limit),
+    )
+}
+
+/// the integer values of the feed within the bounds as of the mci, the oldest `limit` ones
+pub fn read_data_feeds_by_int_value(
+    db: &Connection,
+    oracles: &[String],
+    feed_name: &str,
+    min_int_value: Option<i64>,
+    max_int_value: Option<i64>,
+    max_mci: u32,
+    limit: usize,
+) -> Result<Vec<DataFeedValue>> {
+    read_data_feeds(
+        db,
+        oracles,
+        feed_name,
+        "int_value>=? AND int_value<=? AND main_chain_index<=?",
+        &[
+            &min_int_value.unwrap_or(::std::i64::MIN),
+            &max_int_value.unwrap_or(::std::i64::MAX),
+            &max_mci,
+        ],
+        &format!("ORDER BY main_chain_index, level LIMIT {}", limit),
+    )
+}
+
+/// the latest attestor list of the asset as of the mci
+pub fn read_asset_attestors(db: &Connection, asset: &String, mci: u32) -> Result<Vec<String>> {
+    let mut stmt = db.prepare_cached(
+        "SELECT attestor_address FROM asset_attestors CROSS JOIN units USING(unit) \
+         WHERE asset=? AND sequence='good' AND main_chain_index=( \
+            SELECT MAX(main_chain_index) FROM asset_attestors CROSS JOIN units USING(unit) \
+            WHERE asset=? AND sequence='good' AND main_chain_index<=?) \
+         ORDER BY attestor_address",
+    )?;
+    let attestors = stmt
+        .query_map(&[asset, asset, &mci], |row| row.get(0))?
+        .collect::<::std::result::Result<Vec<String>, _>>()?;
+    Ok(attestors)
+}
+
+/// check if the address is attested by the attestor in a unit that is stable as of the mci
+pub fn is_attested(db: &Connection, address: &str, attestor: &str, mci: u32) -> Result<bool> {
+    let mut stmt = db.prepare_cached(
+        "SELECT 1 FROM attestations CROSS JOIN units USING(unit) \
+         WHERE address=? AND attestor_address=? AND main_chain_index<=? \
+         AND is_stable=1 AND sequence='good' LIMIT 1",
+    )?;
+    Ok(stmt.exists(&[&address, &attestor, &mci])?)
+}
+
 pub fn read_last_stable_mc_index(db: &Connection) -> Result<u32> {
     let ret = read_last_stable_mc_unit_props(db)?;
     match ret {
@@ -281,6 +624,11 @@ pub fn read_joint(db: &Connection, unit: &String) -> Result<Joint> {
 }
 
 pub fn read_joint_directly(db: &Connection, unit_hash: &String) -> Result<Joint> {
+    ensure!(
+        !is_pruned_unit(db, unit_hash)?,
+        "unit {} is pruned, only its ball is kept",
+        unit_hash
+    );
     let min_retrievable_mci = *MIN_RETRIEVABLE_MCI.read().unwrap();
 
     let mut stmt = db.prepare_cached(
@@ -658,6 +1006,7 @@ pub fn read_joint_directly(db: &Connection, unit_hash: &String) -> Result<Joint>
                             outputs.push(Output {
                                 amount: output.amount,
                                 address: output.address.clone(),
+                                blinding: None,
                             });
                         }
 
@@ -670,7 +1019,11 @@ pub fn read_joint_directly(db: &Connection, unit_hash: &String) -> Result<Joint>
                             outputs,
                         });
                     }
-                    app => unimplemented!("app = {}", app),
+                    // other apps keep their payload in messages as json
+                    app => match msg.payload {
+                        Some(ref s) => payload = serde_json::from_str(s)?,
+                        None => bail!("no payload stored for app {}, unit {}", app, unit_hash),
+                    },
                 }
             }
 
@@ -698,7 +1051,8 @@ pub fn read_joint_directly(db: &Connection, unit_hash: &String) -> Result<Joint>
 
             messages.push(Message {
                 app: msg.app,
-                payload: Some(payload),
+                // payloads that are not inline are not stored along with the unit
+                payload: some_if!(msg.payload_location == "inline", payload),
                 payload_hash: msg.payload_hash,
                 payload_location: msg.payload_location,
                 payload_uri: msg.payload_uri,
@@ -745,6 +1099,115 @@ pub fn read_joint_directly(db: &Connection, unit_hash: &String) -> Result<Joint>
     Ok(joint)
 }
 
+/// a line of the joints archive, the balls of the parents and skiplist units
+/// let the importer build the hash tree as in catchup
+#[derive(Serialize, Deserialize)]
+pub struct ExportedJoint {
+    pub joint: Joint,
+    #[serde(default)]
+    pub is_nonserial: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    pub parent_balls: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    #[serde(default)]
+    pub skiplist_balls: Vec<String>,
+}
+
+/// write the stable joints in the mci range as json lines, parents first
+pub fn export_joints<W: ::std::io::Write>(
+    db: &Connection,
+    from_mci: u32,
+    to_mci: u32,
+    writer: &mut W,
+) -> Result<usize> {
+    ensure!(
+        from_mci <= to_mci,
+        "from mci {} is after {}",
+        from_mci,
+        to_mci
+    );
+    let last_stable_mci = read_last_stable_mc_index(db)?;
+    ensure!(
+        to_mci <= last_stable_mci,
+        "mci {} is not stable yet, last stable mci is {}",
+        to_mci,
+        last_stable_mci
+    );
+    // an archive without the pruned units can't be imported
+    let mut stmt = db.prepare_cached(
+        "SELECT MAX(main_chain_index) FROM pruned_units \
+         WHERE main_chain_index>=? AND main_chain_index<=?",
+    )?;
+    let pruned_mci = stmt.query_row(&[&from_mci, &to_mci], |row| row.get::<_, Option<u32>>(0))?;
+    if let Some(mci) = pruned_mci {
+        bail!(
+            "units up to mci {} are pruned on this hub, export them from a full hub",
+            mci
+        );
+    }
+
+    let mut stmt = db.prepare_cached(
+        "SELECT unit, content_hash FROM units \
+         WHERE main_chain_index>=? AND main_chain_index<=? AND is_stable=1 \
+         ORDER BY main_chain_index, `level`, unit",
+    )?;
+    let rows = stmt
+        .query_map(&[&from_mci, &to_mci], |row| {
+            (row.get::<_, String>(0), row.get::<_, Option<String>>(1))
+        })?
+        .collect::<::std::result::Result<Vec<_>, _>>()?;
+
+    for &(ref unit, ref content_hash) in &rows {
+        let mut joint = read_joint_with_ball(db, unit)?;
+        ensure!(joint.ball.is_some(), "no ball for unit {}", unit);
+
+        // retrievable joints are read without the skiplist
+        let mut stmt = db.prepare_cached(
+            "SELECT skiplist_unit, ball FROM skiplist_units \
+             LEFT JOIN balls ON skiplist_unit=balls.unit \
+             WHERE skiplist_units.unit=? ORDER BY skiplist_unit",
+        )?;
+        let skiplist = stmt
+            .query_map(&[unit], |row| {
+                (row.get::<_, String>(0), row.get::<_, Option<String>>(1))
+            })?
+            .collect::<::std::result::Result<Vec<_>, _>>()?;
+        joint.skiplist_units = skiplist.iter().map(|s| s.0.clone()).collect();
+        let mut skiplist_balls = Vec::with_capacity(skiplist.len());
+        for (skiplist_unit, ball) in skiplist {
+            match ball {
+                Some(ball) => skiplist_balls.push(ball),
+                None => bail!("no ball for skiplist unit {}", skiplist_unit),
+            }
+        }
+        skiplist_balls.sort();
+
+        let mut stmt = db.prepare_cached(
+            "SELECT ball FROM parenthoods LEFT JOIN balls \
+             ON parent_unit=balls.unit WHERE child_unit=? ORDER BY ball",
+        )?;
+        let parent_balls = stmt
+            .query_map(&[unit], |row| row.get::<_, Option<String>>(0))?
+            .collect::<::std::result::Result<Option<Vec<_>>, _>>()?;
+        let parent_balls = match parent_balls {
+            Some(balls) => balls,
+            None => bail!("some parents of unit {} have no balls", unit),
+        };
+
+        let exported = ExportedJoint {
+            joint,
+            is_nonserial: content_hash.is_some(),
+            parent_balls,
+            skiplist_balls,
+        };
+        writeln!(writer, "{}", serde_json::to_string(&exported)?)?;
+    }
+    writer.flush()?;
+
+    Ok(rows.len())
+}
+
 pub fn update_min_retrievable_mci_after_stabilizing_mci(
     db: &Connection,
     last_stable_mci: u32,
@@ -806,6 +1269,141 @@ pub fn update_min_retrievable_mci_after_stabilizing_mci(
     Ok(min_retrievable_mci)
 }
 
+pub fn is_pruned_unit(db: &Connection, unit: &String) -> Result<bool> {
+    let mut stmt = db.prepare_cached("SELECT 1 FROM pruned_units WHERE unit=?")?;
+    Ok(stmt.exists(&[unit])?)
+}
+
+// the units that can be stripped: the definitions are kept in full for the
+// witness proofs, the history of the watched addresses is served to light
+// clients, and an output is only removed once a stable unit spends it
+const PRUNABLE_UNIT_CONDITION: &str = "units.is_stable=1 AND units.sequence='good' \
+    AND NOT EXISTS (SELECT 1 FROM unit_authors \
+        WHERE unit_authors.unit=units.unit AND definition_chash IS NOT NULL) \
+    AND NOT EXISTS (SELECT 1 FROM address_definition_changes \
+        WHERE address_definition_changes.unit=units.unit) \
+    AND NOT EXISTS (SELECT 1 FROM unit_authors CROSS JOIN watched_light_addresses USING(address) \
+        WHERE unit_authors.unit=units.unit) \
+    AND NOT EXISTS (SELECT 1 FROM outputs CROSS JOIN watched_light_addresses USING(address) \
+        WHERE outputs.unit=units.unit) \
+    AND NOT EXISTS (SELECT 1 FROM outputs WHERE outputs.unit=units.unit AND NOT EXISTS ( \
+        SELECT 1 FROM inputs CROSS JOIN units AS spenders ON inputs.unit=spenders.unit \
+        WHERE inputs.src_unit=outputs.unit AND inputs.src_message_index=outputs.message_index \
+        AND inputs.src_output_index=outputs.output_index AND inputs.type='transfer' \
+        AND spenders.is_stable=1 AND spenders.sequence='good'))";
+
+/// in pruning mode strip the stable good units older than the retention mci,
+/// the payloads, authentifiers and spent outputs are removed while the units,
+/// inputs, balls and skiplists are kept for validation and catchup
+pub fn prune_units_after_stabilizing_mci(db: &Connection, last_stable_mci: u32) -> Result<()> {
+    let retention = match config::get_pruning_retention_mci() {
+        Some(retention) => retention,
+        None => return Ok(()),
+    };
+    // the retrievable units are still served in full
+    let min_retrievable_mci = get_min_retrievable_mci();
+    if last_stable_mci <= retention || min_retrievable_mci == 0 {
+        return Ok(());
+    }
+    let max_mci = ::std::cmp::min(last_stable_mci - retention, min_retrievable_mci - 1);
+
+    let last_pruned_mci = match *LAST_PRUNED_MCI.read().unwrap() {
+        Some(mci) => Some(mci),
+        None => {
+            let mut stmt = db.prepare_cached("SELECT MAX(main_chain_index) FROM pruned_units")?;
+            stmt.query_row(&[], |row| row.get::<_, Option<u32>>(0))?
+        }
+    };
+
+    let mut units = Vec::new();
+    // the units skipped for their outputs may be pruned once the spenders
+    // stabilized in this mci
+    if let Some(last_pruned_mci) = last_pruned_mci {
+        units.append(&mut read_prunable_units_spent_at_mci(
+            db,
+            last_stable_mci,
+            last_pruned_mci,
+        )?);
+    }
+
+    let from_mci = last_pruned_mci.map(|mci| mci + 1).unwrap_or(0);
+    let mut pruned_mci = last_pruned_mci;
+    if from_mci <= max_mci {
+        // catch up step by step when pruning is turned on for an old database
+        let to_mci = ::std::cmp::min(max_mci, from_mci + config::MAX_MCIS_TO_PRUNE_AT_ONCE - 1);
+        info!("prune units from mci {} to {}", from_mci, to_mci);
+        units.append(&mut read_prunable_units(db, from_mci, to_mci)?);
+        pruned_mci = Some(to_mci);
+    }
+
+    for (unit, mci) in units {
+        prune_unit(db, &unit, mci)?;
+    }
+    *LAST_PRUNED_MCI.write().unwrap() = pruned_mci;
+    Ok(())
+}
+
+fn read_prunable_units(db: &Connection, from_mci: u32, to_mci: u32) -> Result<Vec<(String, u32)>> {
+    let sql = format!(
+        "SELECT unit, main_chain_index FROM units \
+         WHERE main_chain_index>=? AND main_chain_index<=? AND {}",
+        PRUNABLE_UNIT_CONDITION
+    );
+    let mut stmt = db.prepare_cached(&sql)?;
+    let units = stmt
+        .query_map(&[&from_mci, &to_mci], |row| (row.get(0), row.get(1)))?
+        .collect::<::std::result::Result<Vec<_>, _>>()?;
+    Ok(units)
+}
+
+// the units up to the max mci that are not pruned yet and have outputs spent
+// by the units of the mci
+fn read_prunable_units_spent_at_mci(
+    db: &Connection,
+    mci: u32,
+    max_mci: u32,
+) -> Result<Vec<(String, u32)>> {
+    let sql = format!(
+        "SELECT DISTINCT units.unit, units.main_chain_index \
+         FROM units AS spenders CROSS JOIN inputs ON inputs.unit=spenders.unit \
+         CROSS JOIN units ON units.unit=inputs.src_unit \
+         WHERE spenders.main_chain_index=? AND inputs.type='transfer' \
+         AND units.main_chain_index<=? \
+         AND NOT EXISTS (SELECT 1 FROM pruned_units WHERE pruned_units.unit=units.unit) \
+         AND {}",
+        PRUNABLE_UNIT_CONDITION
+    );
+    let mut stmt = db.prepare_cached(&sql)?;
+    let units = stmt
+        .query_map(&[&mci, &max_mci], |row| (row.get(0), row.get(1)))?
+        .collect::<::std::result::Result<Vec<_>, _>>()?;
+    Ok(units)
+}
+
+pub fn prune_unit(db: &Connection, unit: &String, mci: u32) -> Result<()> {
+    let mut stmt = db.prepare_cached(
+        "INSERT OR IGNORE INTO pruned_units (unit, main_chain_index) VALUES (?, ?)",
+    )?;
+    stmt.execute(&[unit, &mci])?;
+
+    // all the outputs are spent by stable units, the inputs are kept as the
+    // double spends are only detected by them
+    let mut stmt = db.prepare_cached("DELETE FROM outputs WHERE unit=?")?;
+    stmt.execute(&[unit])?;
+
+    let mut stmt = db.prepare_cached("DELETE FROM authentifiers WHERE unit=?")?;
+    stmt.execute(&[unit])?;
+
+    // the templates are evaluated in definitions and the profiles and data
+    // are queried by the light clients
+    let mut stmt = db.prepare_cached(
+        "UPDATE messages SET payload=NULL, payload_uri=NULL \
+         WHERE unit=? AND app NOT IN('definition_template', 'profile', 'data')",
+    )?;
+    stmt.execute(&[unit])?;
+    Ok(())
+}
+
 pub enum ArchiveJointReason {
     Uncovered,
     Voided,
@@ -1166,12 +1764,13 @@ pub fn read_definition(db: &Connection, definition_chash: &String) -> Result<Val
         .context(format!("failed to read definition: {}", definition_chash))?)
 }
 
-pub fn read_definition_by_address(
+/// the definition chash of the address after the last stable definition change,
+/// which is the address itself if the definition was never changed
+pub fn read_definition_chash_by_address(
     db: &Connection,
     address: &String,
-    max_mci: Option<u32>,
-) -> Result<::std::result::Result<Value, String>> {
-    let max_mci = max_mci.unwrap_or(::std::u32::MAX);
+    max_mci: u32,
+) -> Result<String> {
     let mut stmt = db.prepare_cached(
         "SELECT definition_chash FROM address_definition_changes CROSS JOIN units USING(unit) \
          WHERE address=? AND is_stable=1 AND sequence='good' AND main_chain_index<=? \
@@ -1180,9 +1779,18 @@ pub fn read_definition_by_address(
     let rows = stmt
         .query_map(&[address, &max_mci], |row| row.get(0))?
         .collect::<::std::result::Result<Vec<String>, _>>()?;
-    let definition_chash = if rows.is_empty() { address } else { &rows[0] };
-    let ret = read_definition_at_mci(db, definition_chash, max_mci)?;
-    Ok(ret.ok_or_else(|| definition_chash.clone()))
+    Ok(rows.into_iter().next().unwrap_or_else(|| address.clone()))
+}
+
+pub fn read_definition_by_address(
+    db: &Connection,
+    address: &String,
+    max_mci: Option<u32>,
+) -> Result<::std::result::Result<Value, String>> {
+    let max_mci = max_mci.unwrap_or(::std::u32::MAX);
+    let definition_chash = read_definition_chash_by_address(db, address, max_mci)?;
+    let ret = read_definition_at_mci(db, &definition_chash, max_mci)?;
+    Ok(ret.ok_or(definition_chash))
 }
 
 fn read_definition_at_mci(
@@ -1410,3 +2018,135 @@ pub fn find_witness_list_unit(
 
     Ok(rows.into_iter().nth(0))
 }
+
+#[test]
+fn test_read_data_feeds_limit() {
+    let db = Connection::open_in_memory().unwrap();
+    db.execute_batch(
+        "CREATE TABLE units (unit CHAR(44) PRIMARY KEY, main_chain_index INT, level INT, \
+            sequence TEXT, is_stable TINYINT); \
+         CREATE TABLE unit_authors (unit CHAR(44), address CHAR(32)); \
+         CREATE TABLE data_feeds (unit CHAR(44), message_index TINYINT, feed_name VARCHAR(64), \
+            value VARCHAR(64), int_value BIGINT);",
+    )
+    .unwrap();
+
+    let oracle = String::from("O7X4UIM5VG3PGBZJAOJ7IX3TWIBUS3MR");
+    for mci in 0..10u32 {
+        let unit = format!("unit{}", mci);
+        db.execute(
+            "INSERT INTO units VALUES (?, ?, ?, 'good', 1)",
+            &[&unit, &mci, &mci],
+        )
+        .unwrap();
+        db.execute("INSERT INTO unit_authors VALUES (?, ?)", &[&unit, &oracle])
+            .unwrap();
+        db.execute(
+            "INSERT INTO data_feeds VALUES (?, 0, 'height', NULL, ?)",
+            &[&unit, &mci],
+        )
+        .unwrap();
+    }
+
+    let oracles = [oracle];
+    let feeds = read_data_feeds_in_mci_range(&db, &oracles, "height", 2, 9, 3).unwrap();
+    let mcis = feeds.iter().map(|f| f.main_chain_index).collect::<Vec<_>>();
+    assert_eq!(mcis, vec![2, 3, 4]);
+    let feeds = read_data_feeds_in_mci_range(&db, &oracles, "height", 2, 9, 100).unwrap();
+    assert_eq!(feeds.len(), 8);
+
+    let feeds = read_data_feeds_by_int_value(&db, &oracles, "height", Some(5), None, 9, 2).unwrap();
+    let values = feeds.iter().map(|f| f.int_value).collect::<Vec<_>>();
+    assert_eq!(values, vec![Some(5), Some(6)]);
+    let feeds =
+        read_data_feeds_by_int_value(&db, &oracles, "height", None, Some(3), 9, 100).unwrap();
+    assert_eq!(feeds.len(), 4);
+}
+
+#[test]
+fn test_prunable_units() {
+    let db = Connection::open_in_memory().unwrap();
+    db.execute_batch(
+        "CREATE TABLE units (unit CHAR(44) PRIMARY KEY, main_chain_index INT, \
+            is_stable TINYINT, sequence TEXT); \
+         CREATE TABLE unit_authors (unit CHAR(44), address CHAR(32), definition_chash CHAR(32)); \
+         CREATE TABLE address_definition_changes (unit CHAR(44), address CHAR(32)); \
+         CREATE TABLE watched_light_addresses (peer VARCHAR(100), address CHAR(32)); \
+         CREATE TABLE outputs (unit CHAR(44), message_index TINYINT, output_index TINYINT, \
+            address CHAR(32), is_spent TINYINT); \
+         CREATE TABLE inputs (unit CHAR(44), type TEXT, src_unit CHAR(44), \
+            src_message_index TINYINT, src_output_index TINYINT); \
+         CREATE TABLE messages (unit CHAR(44), app VARCHAR(30), payload TEXT, payload_uri TEXT); \
+         CREATE TABLE authentifiers (unit CHAR(44), authentifier TEXT); \
+         CREATE TABLE pruned_units (unit CHAR(44) PRIMARY KEY, main_chain_index INT); \
+         INSERT INTO units VALUES \
+            ('spent', 1, 1, 'good'), ('unspent', 1, 1, 'good'), ('defined', 1, 1, 'good'), \
+            ('watched', 2, 1, 'good'), ('spent_by_unstable', 2, 1, 'good'), \
+            ('profile', 2, 1, 'good'), ('spender', 3, 1, 'good'), ('unstable', 4, 0, 'good'); \
+         INSERT INTO unit_authors VALUES ('defined', 'DEFINED', 'DEFINED'), \
+            ('watched', 'WATCHED', NULL); \
+         INSERT INTO watched_light_addresses VALUES ('peer', 'WATCHED'); \
+         INSERT INTO outputs VALUES ('spent', 0, 0, 'PAYEE', 1), ('unspent', 0, 0, 'PAYEE', 0), \
+            ('spent_by_unstable', 0, 0, 'PAYEE', 1); \
+         INSERT INTO inputs VALUES ('spender', 'transfer', 'spent', 0, 0), \
+            ('unstable', 'transfer', 'spent_by_unstable', 0, 0); \
+         INSERT INTO messages VALUES ('profile', 'profile', '{}', NULL), \
+            ('profile', 'text', '\"hello\"', NULL); \
+         INSERT INTO authentifiers VALUES ('profile', 'sig');",
+    )
+    .unwrap();
+
+    let mut units = read_prunable_units(&db, 0, 2).unwrap();
+    units.sort();
+    assert_eq!(
+        units,
+        vec![("profile".to_owned(), 2), ("spent".to_owned(), 1)]
+    );
+
+    // the payloads queried by light clients are kept
+    prune_unit(&db, &"profile".to_owned(), 2).unwrap();
+    assert!(is_pruned_unit(&db, &"profile".to_owned()).unwrap());
+    let payloads = db
+        .prepare("SELECT app, payload FROM messages ORDER BY app")
+        .unwrap()
+        .query_map(&[], |row| (row.get(0), row.get(1)))
+        .unwrap()
+        .collect::<::std::result::Result<Vec<(String, Option<String>)>, _>>()
+        .unwrap();
+    assert_eq!(
+        payloads,
+        vec![
+            ("profile".to_owned(), Some("{}".to_owned())),
+            ("text".to_owned(), None)
+        ]
+    );
+
+    // the unit is revisited when its spender becomes stable
+    assert!(read_prunable_units_spent_at_mci(&db, 4, 2)
+        .unwrap()
+        .is_empty());
+    db.execute_batch("UPDATE units SET is_stable=1 WHERE unit='unstable'")
+        .unwrap();
+    assert_eq!(
+        read_prunable_units_spent_at_mci(&db, 4, 2).unwrap(),
+        vec![("spent_by_unstable".to_owned(), 2)]
+    );
+}
+
+#[test]
+fn test_export_pruned_joints() {
+    let db = Connection::open_in_memory().unwrap();
+    db.execute_batch(
+        "CREATE TABLE units (unit CHAR(44) PRIMARY KEY, main_chain_index INT, \
+            is_on_main_chain TINYINT, is_stable TINYINT); \
+         CREATE TABLE balls (ball CHAR(44) PRIMARY KEY, unit CHAR(44)); \
+         CREATE TABLE pruned_units (unit CHAR(44) PRIMARY KEY, main_chain_index INT); \
+         INSERT INTO units VALUES ('pruned', 1, 1, 1), ('last', 2, 1, 1); \
+         INSERT INTO balls VALUES ('ball', 'last'); \
+         INSERT INTO pruned_units VALUES ('pruned', 1);",
+    )
+    .unwrap();
+
+    let err = export_joints(&db, 0, 2, &mut Vec::new()).unwrap_err();
+    assert!(err.to_string().contains("pruned"));
+}
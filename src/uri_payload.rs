@@ -0,0 +1,249 @@
+//! Payloads with location "uri" are kept off the DAG, the unit only carries the
+//! payload hash and the uri. Resolvers fetch the payload from the uri and
+//! every fetched payload is checked against the hashes in the message.
+
+use std::fs;
+use std::path::PathBuf;
+
+use config;
+use error::Result;
+use may::sync::RwLock;
+use object_hash;
+use rusqlite::Connection;
+use serde_json::{self, Value};
+use spec::{Message, Payload};
+
+const LOCAL_URI_PREFIX: &str = "local://";
+
+pub trait ContentResolver: Send + Sync {
+    /// return the raw json payload, or none if the uri is not handled here
+    fn fetch(&self, uri: &str) -> Result<Option<String>>;
+}
+
+/// content addressed store on the local file system, the uri is derived from
+/// the payload hash so that any node holding a copy of the file can resolve it
+pub struct LocalStore {
+    root: PathBuf,
+}
+
+impl LocalStore {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        LocalStore { root: root.into() }
+    }
+
+    /// save the payload and return the uri to put in the message
+    pub fn put(&self, payload: &Payload) -> Result<String> {
+        let payload_hash = object_hash::get_base64_hash(payload)?;
+        let name = file_name(&payload_hash);
+        fs::create_dir_all(&self.root)?;
+        fs::write(self.root.join(&name), serde_json::to_string(payload)?)?;
+        Ok(format!("{}{}", LOCAL_URI_PREFIX, name))
+    }
+}
+
+impl ContentResolver for LocalStore {
+    fn fetch(&self, uri: &str) -> Result<Option<String>> {
+        if !uri.starts_with(LOCAL_URI_PREFIX) {
+            return Ok(None);
+        }
+        let name = &uri[LOCAL_URI_PREFIX.len()..];
+        // never leave the store directory
+        let is_safe_name = name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        ensure!(
+            !name.is_empty() && is_safe_name,
+            "invalid local uri {}",
+            uri
+        );
+
+        let path = self.root.join(name);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(fs::read_to_string(path)?))
+    }
+}
+
+// base64 hashes may contain '/' and '+' which are not safe in a file name
+fn file_name(payload_hash: &str) -> String {
+    payload_hash
+        .trim_end_matches('=')
+        .replace('/', "_")
+        .replace('+', "-")
+}
+
+lazy_static! {
+    static ref RESOLVERS: RwLock<Vec<Box<ContentResolver>>> = RwLock::new(vec![Box::new(
+        LocalStore::new(config::get_content_store_path())
+    )]);
+}
+
+/// registered resolvers are tried before the ones registered earlier
+pub fn register_resolver(resolver: Box<ContentResolver>) {
+    RESOLVERS.write().unwrap().insert(0, resolver);
+}
+
+/// save the payload in the default local store
+pub fn save_to_local_store(payload: &Payload) -> Result<String> {
+    LocalStore::new(config::get_content_store_path()).put(payload)
+}
+
+/// fetch the payload of a message with location "uri" and check it
+pub fn resolve_payload(message: &Message) -> Result<Payload> {
+    resolve_payload_with(&RESOLVERS.read().unwrap(), message)
+}
+
+// a resolver that fails or returns a wrong payload does not stop the others,
+// the payload may still be fetched from another source
+fn resolve_payload_with(resolvers: &[Box<ContentResolver>], message: &Message) -> Result<Payload> {
+    ensure!(
+        message.payload_location == "uri",
+        "payload location is {}",
+        message.payload_location
+    );
+    let uri = match message.payload_uri {
+        Some(ref uri) => uri,
+        None => bail!("no payload uri"),
+    };
+    ensure!(
+        message.payload_uri_hash.as_ref() == Some(&object_hash::get_base64_hash(uri)?),
+        "wrong payload uri hash"
+    );
+
+    for resolver in resolvers {
+        match resolver
+            .fetch(uri)
+            .and_then(|content| check_payload(message, uri, content))
+        {
+            Ok(Some(payload)) => return Ok(payload),
+            Ok(None) => {}
+            Err(e) => warn!("failed to resolve {}, err={}", uri, e),
+        }
+    }
+    bail!("no resolver can fetch {}", uri)
+}
+
+fn check_payload(message: &Message, uri: &str, content: Option<String>) -> Result<Option<Payload>> {
+    let content = match content {
+        Some(content) => content,
+        None => return Ok(None),
+    };
+
+    // check the raw value, the typed payload may not serialize back the same
+    let payload: Value = serde_json::from_str(&content)?;
+    ensure!(
+        object_hash::get_base64_hash(&payload)? == message.payload_hash,
+        "payload fetched from {} does not match the payload hash",
+        uri
+    );
+    Ok(Some(serde_json::from_value(payload)?))
+}
+
+/// fetch the uri payload of a saved message
+pub fn read_uri_payload(db: &Connection, unit: &String, message_index: u32) -> Result<Payload> {
+    let mut stmt = db.prepare_cached(
+        "SELECT app, payload_hash, payload_location, payload_uri, payload_uri_hash \
+         FROM messages WHERE unit=? AND message_index=?",
+    )?;
+    let messages = stmt
+        .query_map(&[unit, &message_index], |row| Message {
+            app: row.get(0),
+            payload_hash: row.get(1),
+            payload_location: row.get(2),
+            payload_uri: row.get(3),
+            payload_uri_hash: row.get(4),
+            ..Default::default()
+        })?
+        .collect::<::std::result::Result<Vec<_>, _>>()?;
+    match messages.into_iter().next() {
+        Some(message) => resolve_payload(&message),
+        None => bail!("message {}:{} not found", unit, message_index),
+    }
+}
+
+#[cfg(test)]
+struct FixedResolver(Result<Option<String>>);
+
+#[cfg(test)]
+impl ContentResolver for FixedResolver {
+    fn fetch(&self, _uri: &str) -> Result<Option<String>> {
+        match self.0 {
+            Ok(ref content) => Ok(content.clone()),
+            Err(ref e) => bail!("{}", e),
+        }
+    }
+}
+
+#[test]
+fn test_local_store() {
+    let root = ::std::env::temp_dir().join("trustnote_test_local_store");
+    let store = LocalStore::new(root.clone());
+    let payload = Payload::Text(String::from("hello"));
+    let uri = store.put(&payload).unwrap();
+    assert!(uri.starts_with(LOCAL_URI_PREFIX));
+    assert_eq!(
+        store.fetch(&uri).unwrap(),
+        Some(serde_json::to_string(&payload).unwrap())
+    );
+
+    // other schemes and missing files are left to the other resolvers
+    assert!(store
+        .fetch("https://example.com/payload")
+        .unwrap()
+        .is_none());
+    assert!(store.fetch("local://missing").unwrap().is_none());
+
+    // the name can not leave the store directory
+    fs::write(root.join("..").join("trustnote_test_secret"), "\"secret\"").unwrap();
+    assert!(store.fetch("local://../trustnote_test_secret").is_err());
+    assert!(store.fetch("local://sub/file").is_err());
+    assert!(store.fetch("local:///etc/passwd").is_err());
+    assert!(store.fetch("local://").is_err());
+}
+
+#[test]
+fn test_resolve_payload() {
+    use composer;
+
+    let payload = Payload::Text(String::from("hello"));
+    let content = serde_json::to_string(&payload).unwrap();
+    let uri = String::from("https://example.com/payload");
+    let message = composer::create_uri_message("text", &payload, uri).unwrap();
+    let resolver = |content: Result<Option<&str>>| -> Box<ContentResolver> {
+        Box::new(FixedResolver(content.map(|c| c.map(String::from))))
+    };
+    let resolve = |resolvers: Vec<Box<ContentResolver>>| {
+        resolve_payload_with(&resolvers, &message).map(|p| serde_json::to_string(&p).unwrap())
+    };
+
+    assert_eq!(
+        resolve(vec![resolver(Ok(Some(&content)))]).unwrap(),
+        content
+    );
+
+    // the first resolver that has the payload wins
+    assert_eq!(
+        resolve(vec![resolver(Ok(None)), resolver(Ok(Some(&content)))]).unwrap(),
+        content
+    );
+    assert!(resolve(vec![resolver(Ok(None)), resolver(Ok(None))]).is_err());
+
+    // a failed resolver or a wrong payload does not stop the next one
+    assert_eq!(
+        resolve(vec![
+            resolver(Err(format_err!("unreachable"))),
+            resolver(Ok(Some("\"wrong\""))),
+            resolver(Ok(Some(&content))),
+        ])
+        .unwrap(),
+        content
+    );
+    assert!(resolve(vec![resolver(Ok(Some("\"wrong\"")))]).is_err());
+    assert!(resolve(vec![resolver(Ok(Some("not json")))]).is_err());
+
+    // the uri must match its hash
+    let mut tampered = message.clone();
+    tampered.payload_uri = Some(String::from("https://example.com/other"));
+    assert!(resolve_payload_with(&[resolver(Ok(Some(&content)))], &tampered).is_err());
+}
@@ -24,8 +24,11 @@ impl<K: Eq + Hash, V: Clone> FifoCache<K, V> {
     #[inline]
     pub fn insert(&self, k: K, v: V) -> Option<V> {
         let mut map = self.inner.write().unwrap();
-        while self.capacity - 1 < map.len() {
-            map.pop();
+        // make room for a new key by evicting the oldest entries
+        if !map.contains_key(&k) {
+            while !map.is_empty() && map.len() >= self.capacity {
+                map.shift_remove_index(0);
+            }
         }
         map.insert(k, v)
     }
@@ -35,3 +38,29 @@ impl<K: Eq + Hash, V: Clone> FifoCache<K, V> {
         self.inner.write().unwrap().remove(k)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fifo_cache() {
+        let cache = FifoCache::with_capacity(3);
+        for i in 0..10 {
+            cache.insert(i, i * 2);
+        }
+        // the oldest entries are evicted and the recent ones are kept
+        assert_eq!(cache.get(&6), None);
+        assert_eq!(cache.get(&7), Some(14));
+        assert_eq!(cache.get(&8), Some(16));
+        assert_eq!(cache.get(&9), Some(18));
+
+        // updating a cached key evicts nothing
+        cache.insert(7, 0);
+        assert_eq!(cache.get(&7), Some(0));
+        assert_eq!(cache.get(&8), Some(16));
+        cache.insert(10, 20);
+        assert_eq!(cache.get(&7), None);
+        assert_eq!(cache.get(&10), Some(20));
+    }
+}
@@ -3,7 +3,9 @@ pub mod atomic_lock;
 pub mod event;
 pub mod fifo_cache;
 pub mod map_lock;
+pub mod thread_pool;
 
 pub use self::atomic_lock::{AtomicLock, AtomicLockGuard};
 pub use self::fifo_cache::FifoCache;
 pub use self::map_lock::{MapLock, MapLockGuard};
+pub use self::thread_pool::{JobHandle, ThreadPool, CPU_POOL};
@@ -0,0 +1,123 @@
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc as std_mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use error::Result;
+use may::sync::mpsc;
+use num_cpus;
+
+lazy_static! {
+    /// shared by the cpu bound work like signature verification
+    pub static ref CPU_POOL: ThreadPool = ThreadPool::new(num_cpus::get());
+}
+
+trait FnJob: Send {
+    fn call_box(self: Box<Self>);
+}
+
+impl<F: FnOnce() + Send> FnJob for F {
+    fn call_box(self: Box<Self>) {
+        (*self)()
+    }
+}
+
+/// fixed os threads for the cpu bound work, the hub runs its coroutines on a
+/// single worker so they never run in parallel. a coroutine waiting for the
+/// result of a job yields instead of blocking the worker
+pub struct ThreadPool {
+    jobs: Mutex<std_mpsc::Sender<Box<FnJob>>>,
+}
+
+impl ThreadPool {
+    pub fn new(size: usize) -> ThreadPool {
+        let (tx, rx) = std_mpsc::channel::<Box<FnJob>>();
+        let rx = Arc::new(Mutex::new(rx));
+        for i in 0..size {
+            let rx = rx.clone();
+            thread::Builder::new()
+                .name(format!("thread_pool_{}", i))
+                .spawn(move || loop {
+                    let job = match rx.lock().unwrap().recv() {
+                        Ok(job) => job,
+                        Err(_) => break,
+                    };
+                    // a panicked job only drops its result sender
+                    let _ = panic::catch_unwind(AssertUnwindSafe(|| job.call_box()));
+                })
+                .expect("failed to spawn pool thread");
+        }
+        ThreadPool {
+            jobs: Mutex::new(tx),
+        }
+    }
+
+    pub fn spawn<F, T>(&self, f: F) -> JobHandle<T>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel();
+        let job = move || {
+            let _ = tx.send(f());
+        };
+        // the threads never exit, so the receiver is alive
+        self.jobs.lock().unwrap().send(Box::new(job)).unwrap();
+        JobHandle { rx }
+    }
+}
+
+pub struct JobHandle<T> {
+    rx: mpsc::Receiver<T>,
+}
+
+impl<T> JobHandle<T> {
+    /// wait for the result of the job, fails if the job panicked
+    pub fn join(self) -> Result<T> {
+        self.rx.recv().map_err(|_| format_err!("job panicked"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_thread_pool() {
+        let pool = ThreadPool::new(2);
+        let handles = (0..10)
+            .map(|i| pool.spawn(move || i * 2))
+            .collect::<Vec<_>>();
+        let results = handles
+            .into_iter()
+            .map(|h| h.join().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(results, (0..10).map(|i| i * 2).collect::<Vec<_>>());
+
+        // a panicked job fails its handle and the threads keep running
+        assert!(pool
+            .spawn(|| -> u32 { panic!("job panicked") })
+            .join()
+            .is_err());
+        assert_eq!(pool.spawn(|| 1).join().unwrap(), 1);
+    }
+
+    #[test]
+    fn test_thread_pool_parallel() {
+        // the first job only finishes when the second one runs at the same time
+        let pool = ThreadPool::new(2);
+        let (tx, rx) = std_mpsc::channel();
+        let waiter = pool.spawn(move || rx.recv_timeout(Duration::from_secs(5)).is_ok());
+        let sender = pool.spawn(move || tx.send(()).is_ok());
+        assert!(waiter.join().unwrap());
+        assert!(sender.join().unwrap());
+    }
+
+    #[test]
+    fn test_thread_pool_in_coroutine() {
+        let pool = Arc::new(ThreadPool::new(1));
+        let h = go!(move || pool.spawn(|| 42).join().unwrap());
+        assert_eq!(h.join().unwrap(), 42);
+    }
+}
@@ -7,9 +7,12 @@ use main_chain;
 use mc_outputs;
 use object_hash;
 use paid_witnessing;
+use private_payment;
 use rusqlite::{Connection, Transaction};
-use serde_json::Value;
+use serde_json::{self, Value};
+use signature;
 use spec::*;
+use std::collections::HashMap;
 use storage;
 use utils::{MapLock, MapLockGuard};
 // global address map lock
@@ -18,6 +21,18 @@ lazy_static! {
     static ref ADDRESS_LOCK: MapLock<String> = MapLock::new();
 }
 
+// apps whose payload must be inline
+const INLINE_ONLY_APPS: [&str; 8] = [
+    "address_definition_change",
+    "data_feed",
+    "definition_template",
+    "asset",
+    "asset_attestors",
+    "attestation",
+    "poll",
+    "vote",
+];
+
 macro_rules! bail_with_validation_err {
     ($t:ident, $e:expr) => {
         return Err(ValidationError::$t {
@@ -54,6 +69,7 @@ pub struct ValidationState {
     pub unsigned: bool,
     pub sequence: String,
     pub last_ball_mci: u32,
+    pub last_ball_timestamp: u64,
     pub max_known_mci: u32,
     pub skiplist_balls: Vec<String>,
     pub max_parent_limci: u32,
@@ -66,6 +82,13 @@ pub struct ValidationState {
     pub input_keys: Vec<String>, //It could be spendproof in Spendproof or some input related customized string
     pub has_base_payment: bool,
     pub has_data_feed: bool,
+    pub has_poll: bool,
+    pub has_profile: bool,
+    pub has_definition_template: bool,
+    pub assets_with_attestor_list: Vec<String>,
+    pub addresses_with_definition_change: Vec<String>,
+    // validating a private payload received off the DAG
+    pub is_private: bool,
 }
 
 impl ValidationState {
@@ -75,8 +98,9 @@ impl ValidationState {
             sequence: "good".to_owned(),
             max_known_mci: 0,
             last_ball_mci: 0,
+            last_ball_timestamp: 0,
             max_parent_limci: 0,
-            has_no_references: true,
+            has_no_references: false,
             unit_hash_to_sign: None,
             skiplist_balls: Vec::new(),
             additional_queries: ::db::DbQueries::new(),
@@ -86,6 +110,12 @@ impl ValidationState {
             input_keys: Vec::new(),
             has_base_payment: false,
             has_data_feed: false,
+            has_poll: false,
+            has_profile: false,
+            has_definition_template: false,
+            assets_with_attestor_list: Vec::new(),
+            addresses_with_definition_change: Vec::new(),
+            is_private: false,
         }
     }
 }
@@ -100,11 +130,97 @@ pub enum ValidationError {
     NeedHashTree,
     #[fail(display = "Need Parent Units")]
     NeedParentUnits(Vec<String>),
+    // below are unit errors that peers need to tell apart
+    #[fail(display = "Bad Signature: {}", err)]
+    BadSignature { err: String },
+    #[fail(display = "Double Spend: {}", err)]
+    DoubleSpend { err: String },
+    #[fail(display = "Witness List Mismatch: {}", err)]
+    WitnessListMismatch { err: String },
+    #[fail(display = "Commission Mismatch: {}", err)]
+    CommissionMismatch { err: String },
     // convert other unkonw error to this one
     #[fail(display = "Other unknow error")]
     OtherError { err: String },
 }
 
+impl ValidationError {
+    /// the machine readable code sent back to the peer along with the error
+    pub fn code(&self) -> &'static str {
+        match *self {
+            ValidationError::UnitError { .. } => "invalid_unit",
+            ValidationError::JointError { .. } => "invalid_joint",
+            ValidationError::NeedHashTree => "need_hash_tree",
+            ValidationError::NeedParentUnits(_) => "unknown_parent",
+            ValidationError::BadSignature { .. } => "bad_signature",
+            ValidationError::DoubleSpend { .. } => "double_spend",
+            ValidationError::WitnessListMismatch { .. } => "witness_list_mismatch",
+            ValidationError::CommissionMismatch { .. } => "commission_mismatch",
+            ValidationError::OtherError { .. } => "other",
+        }
+    }
+
+    /// the error message without the kind, for missing parents it's the unit list
+    pub fn detail(&self) -> String {
+        match *self {
+            ValidationError::UnitError { ref err }
+            | ValidationError::JointError { ref err }
+            | ValidationError::BadSignature { ref err }
+            | ValidationError::DoubleSpend { ref err }
+            | ValidationError::WitnessListMismatch { ref err }
+            | ValidationError::CommissionMismatch { ref err }
+            | ValidationError::OtherError { ref err } => err.clone(),
+            ValidationError::NeedHashTree => String::from("need hash tree"),
+            ValidationError::NeedParentUnits(ref units) => units.join(", "),
+        }
+    }
+
+    /// restore the error from the code and detail received from a peer
+    pub fn from_code(code: &str, detail: String) -> Self {
+        match code {
+            "invalid_unit" => ValidationError::UnitError { err: detail },
+            "invalid_joint" => ValidationError::JointError { err: detail },
+            "need_hash_tree" => ValidationError::NeedHashTree,
+            "unknown_parent" => {
+                ValidationError::NeedParentUnits(detail.split(", ").map(|s| s.to_owned()).collect())
+            }
+            "bad_signature" => ValidationError::BadSignature { err: detail },
+            "double_spend" => ValidationError::DoubleSpend { err: detail },
+            "witness_list_mismatch" => ValidationError::WitnessListMismatch { err: detail },
+            "commission_mismatch" => ValidationError::CommissionMismatch { err: detail },
+            _ => ValidationError::OtherError { err: detail },
+        }
+    }
+
+    /// the errors that make the unit itself bad
+    pub fn is_unit_error(&self) -> bool {
+        match *self {
+            ValidationError::UnitError { .. }
+            | ValidationError::BadSignature { .. }
+            | ValidationError::DoubleSpend { .. }
+            | ValidationError::WitnessListMismatch { .. }
+            | ValidationError::CommissionMismatch { .. } => true,
+            _ => false,
+        }
+    }
+
+    // the definition reports failed authentifiers as plain errors, only they
+    // are bad signatures, the malformed definitions make the unit bad
+    fn from_definition_error(error: ::failure::Error) -> Self {
+        if error.downcast_ref::<::rusqlite::Error>().is_some() {
+            return ValidationError::from(error);
+        }
+        let err = error.to_string();
+        if err.starts_with("authentifier verification failed")
+            || err.starts_with("bad signature at path")
+            || err.starts_with("bad merkle proof at path")
+        {
+            return ValidationError::BadSignature { err };
+        }
+        ValidationError::UnitError { err }
+    }
+}
+
 impl From<::failure::Error> for ValidationError {
     fn from(error: ::failure::Error) -> Self {
         ValidationError::OtherError {
@@ -155,18 +271,109 @@ pub fn validate_author_signature_without_ref(
         unit,
         &mut validate_state,
         &author.authentifiers,
-    )?;
+    )
+    .map_err(ValidationError::from_definition_error)?;
     Ok(())
 }
 
+// validate the private payload received off the DAG against its unit
+pub fn validate_private_payment(tx: &Transaction, element: &PrivateElement) -> Result<()> {
+    ensure_with_validation_err!(
+        element.unit.len() == config::HASH_LENGTH,
+        UnitError,
+        "invalid unit"
+    );
+    ensure_with_validation_err!(
+        element.payload.asset.is_some(),
+        UnitError,
+        "private payment must be in an asset"
+    );
+
+    struct MessageProps {
+        payload_hash: String,
+        app: String,
+        payload_location: String,
+        sequence: String,
+        is_stable: bool,
+        last_ball_mci: Option<u32>,
+    }
+
+    let mut stmt = tx.prepare_cached(
+        "SELECT payload_hash, app, payload_location, units.sequence, units.is_stable, \
+         lb_units.main_chain_index AS last_ball_mci \
+         FROM messages JOIN units USING(unit) \
+         LEFT JOIN units AS lb_units ON units.last_ball_unit=lb_units.unit \
+         WHERE messages.unit=? AND message_index=?",
+    )?;
+    let rows = stmt
+        .query_map(&[&element.unit, &element.message_index], |row| {
+            MessageProps {
+                payload_hash: row.get(0),
+                app: row.get(1),
+                payload_location: row.get(2),
+                sequence: row.get(3),
+                is_stable: row.get(4),
+                last_ball_mci: row.get(5),
+            }
+        })?
+        .collect::<::std::result::Result<Vec<_>, _>>()?;
+    let props = match rows.into_iter().next() {
+        Some(props) => props,
+        None => bail_with_validation_err!(UnitError, "message not found"),
+    };
+
+    if props.sequence != "good" && props.is_stable {
+        bail_with_validation_err!(UnitError, "unit is final nonserial");
+    }
+    ensure_with_validation_err!(props.app == "payment", UnitError, "invalid app");
+    ensure_with_validation_err!(
+        props.payload_location == "none",
+        UnitError,
+        "payment is not private"
+    );
+    ensure_with_validation_err!(
+        object_hash::get_base64_hash(&element.payload)? == props.payload_hash,
+        UnitError,
+        "payload hash does not match"
+    );
+
+    let mut unit = Unit {
+        unit: Some(element.unit.clone()),
+        ..Default::default()
+    };
+    for address in storage::read_unit_authors(tx, &element.unit)? {
+        unit.authors.push(Author {
+            address,
+            authentifiers: HashMap::new(),
+            definition: Value::Null,
+        });
+    }
+
+    let mut validate_state = ValidationState::new();
+    validate_state.last_ball_mci = props.last_ball_mci.unwrap_or(0);
+    validate_state.is_private = true;
+    validate_payment(
+        tx,
+        &element.payload,
+        element.message_index as usize,
+        &unit,
+        &mut validate_state,
+    )
+}
+
 pub fn validate(db: &mut Connection, joint: &Joint) -> Result<ValidationOk> {
+    precheck(db, joint)?;
+    validate_prechecked(db, joint)
+}
+
+/// the checks that don't touch the db, they can run concurrently on any joint
+/// before it is validated against the db
+pub fn validate_stateless(joint: &Joint) -> Result<()> {
     let unit = &joint.unit;
     // already checked in earlier network processing
     // ensure_with_validation_err!(unit.unit.is_some(), "no unit");
 
     let unit_hash = joint.get_unit_hash();
-    info!("validating joint identified by unit {}", unit_hash);
-
     if unit_hash.len() != config::HASH_LENGTH {
         bail_with_validation_err!(JointError, "wrong unit length");
     }
@@ -221,7 +428,7 @@ pub fn validate(db: &mut Connection, joint: &Joint) -> Result<ValidationOk> {
         let header_size = unit.get_header_size();
         if unit.headers_commission != Some(header_size) {
             bail_with_validation_err!(
-                UnitError,
+                CommissionMismatch,
                 "wrong headers commission, expected {}",
                 header_size
             );
@@ -230,11 +437,26 @@ pub fn validate(db: &mut Connection, joint: &Joint) -> Result<ValidationOk> {
         let payload_size = unit.get_payload_size();
         if unit.payload_commission != Some(payload_size) {
             bail_with_validation_err!(
-                UnitError,
+                CommissionMismatch,
                 "wrong payload commission, expected {}",
                 payload_size
             );
         }
+
+        for message in &unit.messages {
+            if message.payload_location != "inline" {
+                continue;
+            }
+            ensure_with_validation_err!(message.payload.is_some(), UnitError, "no inline payload");
+            let payload_hash = object_hash::get_base64_hash(&message.payload)?;
+            ensure_with_validation_err!(
+                payload_hash == message.payload_hash,
+                UnitError,
+                "wrong payload hash: expected {}, got {}",
+                payload_hash,
+                message.payload_hash
+            );
+        }
     }
 
     if unit.authors.is_empty() {
@@ -264,9 +486,212 @@ pub fn validate(db: &mut Connection, joint: &Joint) -> Result<ValidationOk> {
     }
 
     if unit.witness_list_unit.is_some() && !unit.witnesses.is_empty() {
-        bail_with_validation_err!(UnitError, "ambiguous witnesses");
+        bail_with_validation_err!(WitnessListMismatch, "ambiguous witnesses");
+    }
+
+    if unit.content_hash.is_none() {
+        validate_headers_commission_recipients(unit)?;
+    }
+
+    Ok(())
+}
+
+/// the cpu bound part of the validation, it runs concurrently on any joint
+/// before `validate_prechecked`: the stateless checks and the signatures in
+/// the author definitions. the verified signatures are cached, so evaluating
+/// the definitions in the transaction doesn't verify them again
+pub fn precheck(db: &Connection, joint: &Joint) -> Result<()> {
+    let definitions = read_author_definitions(db, joint);
+    precheck_with_definitions(joint, &definitions)
+}
+
+/// the inline or stored definition of each author, read ahead so that the
+/// precheck can run where no db connection is available. the stored
+/// definition may change before the joint is validated, a missed one is only
+/// verified later in the transaction
+pub fn read_author_definitions(db: &Connection, joint: &Joint) -> Vec<Option<Value>> {
+    joint
+        .unit
+        .authors
+        .iter()
+        .map(|author| {
+            if !author.definition.is_null() {
+                return Some(author.definition.clone());
+            }
+            match storage::read_definition_by_address(db, &author.address, None) {
+                Ok(Ok(definition)) => Some(definition),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// the precheck with the definitions of `read_author_definitions`, it doesn't
+/// touch the db
+pub fn precheck_with_definitions(joint: &Joint, definitions: &[Option<Value>]) -> Result<()> {
+    validate_stateless(joint)?;
+    let unit = &joint.unit;
+    if unit.content_hash.is_some() || joint.unsigned == Some(true) {
+        return Ok(());
+    }
+
+    let unit_hash_to_sign = unit.get_unit_hash_to_sign();
+    for (author, definition) in unit.authors.iter().zip(definitions) {
+        let definition = match *definition {
+            Some(ref definition) => definition,
+            None => continue,
+        };
+        // a bad signature may be in a branch that is not required, it is
+        // up to the definition evaluation to reject the unit
+        for (sig, pubkey) in definition::get_sig_pairs(definition, &author.authentifiers) {
+            let _ = signature::verify(&unit_hash_to_sign, sig, pubkey);
+        }
+    }
+    Ok(())
+}
+
+/// result of checking a joint without the db
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct OfflineReport {
+    pub unit: String,
+    /// the ball hash can only be checked when the parent balls are given
+    pub is_ball_checked: bool,
+    /// authors whose inline definition and signatures are verified
+    pub verified_authors: Vec<String>,
+    /// authors without inline definition or whose definition needs the dag
+    pub unverified_authors: Vec<String>,
+}
+
+/// check everything that doesn't need the db: hashes, commissions, field
+/// limits, the ball when the parent balls are known, and the signatures of
+/// the authors that carry their definition
+pub fn verify_joint_offline(
+    joint: &Joint,
+    parent_balls: Option<&[String]>,
+    skiplist_balls: &[String],
+) -> Result<OfflineReport> {
+    ensure_with_validation_err!(joint.unit.unit.is_some(), JointError, "no unit");
+    validate_stateless(joint)?;
+    let unit = &joint.unit;
+    let mut report = OfflineReport {
+        unit: joint.get_unit_hash().clone(),
+        ..Default::default()
+    };
+
+    if unit.parent_units.len() > config::MAX_PARENT_PER_UNIT {
+        bail_with_validation_err!(UnitError, "too many parents: {}", unit.parent_units.len());
+    }
+    for pair in unit.parent_units.windows(2) {
+        ensure_with_validation_err!(pair[0] < pair[1], UnitError, "parent units not ordered");
+    }
+
+    if !unit.witnesses.is_empty() && unit.witnesses.len() != config::COUNT_WITNESSES {
+        bail_with_validation_err!(WitnessListMismatch, "wrong number of witnesses");
+    }
+
+    if unit.authors.len() > config::MAX_AUTHORS_PER_UNIT {
+        bail_with_validation_err!(UnitError, "too many authors");
+    }
+    let mut prev_address = String::from("");
+    for author in &unit.authors {
+        if author.address <= prev_address {
+            bail_with_validation_err!(UnitError, "author addresses not sorted");
+        }
+        prev_address = author.address.clone();
+        if author.address.len() != 32 {
+            bail_with_validation_err!(UnitError, "wrong address length");
+        }
+        if author.authentifiers.is_empty() && unit.content_hash.is_none() {
+            bail_with_validation_err!(UnitError, "no authentifiers");
+        }
+        for (_, value) in &author.authentifiers {
+            if value.is_empty() {
+                bail_with_validation_err!(UnitError, "authentifiers must be nonempty strings");
+            }
+            if value.len() > config::MAX_AUTHENTIFIER_LENGTH {
+                bail_with_validation_err!(UnitError, "authentifier too long");
+            }
+        }
+    }
+
+    for message in &unit.messages {
+        ensure_with_validation_err!(
+            is_valid_base64(&message.payload_hash, config::HASH_LENGTH),
+            UnitError,
+            "wrong payload hash"
+        );
+        if message.spend_proofs.len() > config::MAX_SPEND_PROOFS_PER_MESSAGE {
+            bail_with_validation_err!(UnitError, "too many spend proofs");
+        }
+    }
+
+    if let (Some(ball), Some(parent_balls)) = (joint.ball.as_ref(), parent_balls) {
+        ensure_with_validation_err!(
+            parent_balls.len() == unit.parent_units.len()
+                && skiplist_balls.len() == joint.skiplist_units.len(),
+            JointError,
+            "parent or skiplist balls don't match the units"
+        );
+        // the balls are sorted in the hash as they are read from the db
+        let mut parent_balls = parent_balls.to_vec();
+        parent_balls.sort();
+        let mut skiplist_balls = skiplist_balls.to_vec();
+        skiplist_balls.sort();
+        let ball_hash = object_hash::get_ball_hash(
+            &report.unit,
+            &parent_balls,
+            &skiplist_balls,
+            unit.content_hash.is_some(),
+        );
+        if &ball_hash != ball {
+            bail_with_validation_err!(JointError, "ball hash is wrong, expect {}", ball_hash);
+        }
+        report.is_ball_checked = true;
+    }
+
+    // stripped and unsigned units carry no signatures to check
+    if unit.content_hash.is_some() || joint.unsigned == Some(true) {
+        report.unverified_authors = unit.authors.iter().map(|a| a.address.clone()).collect();
+        return Ok(report);
+    }
+
+    let unit_hash_to_sign = unit.get_unit_hash_to_sign();
+    for author in &unit.authors {
+        if author.definition.is_null() {
+            report.unverified_authors.push(author.address.clone());
+            continue;
+        }
+        // after a definition change the definition chash is not the address,
+        // only the dag knows whether the definition is the current one
+        if object_hash::get_chash(&author.definition)? != author.address {
+            report.unverified_authors.push(author.address.clone());
+            continue;
+        }
+        let is_verified = definition::verify_authentifiers_offline(
+            &author.definition,
+            &author.authentifiers,
+            &unit_hash_to_sign,
+        )
+        .map_err(ValidationError::from_definition_error)?;
+        if is_verified {
+            report.verified_authors.push(author.address.clone());
+        } else {
+            report.unverified_authors.push(author.address.clone());
+        }
     }
 
+    Ok(report)
+}
+
+/// validate the joint against the db, it must have passed `validate_stateless`
+///
+/// joints with common authors are validated and saved one by one, the returned
+/// lock must be held until the joint is saved
+pub fn validate_prechecked(db: &mut Connection, joint: &Joint) -> Result<ValidationOk> {
+    let unit = &joint.unit;
+    let unit_hash = joint.get_unit_hash();
+    info!("validating joint identified by unit {}", unit_hash);
+
     let mut validate_state = ValidationState::new();
     if joint.unsigned == Some(true) {
         validate_state.unsigned = true;
@@ -277,10 +702,6 @@ pub fn validate(db: &mut Connection, joint: &Joint) -> Result<ValidationOk> {
 
     let tx = db.transaction()?;
     check_duplicate(&tx, unit_hash)?;
-    if unit.content_hash.is_none() {
-        // this is not using db
-        validate_headers_commission_recipients(unit)?;
-    }
 
     if !unit.parent_units.is_empty() {
         validate_hash_tree(&tx, joint, &mut validate_state)?;
@@ -348,7 +769,7 @@ fn validate_headers_commission_recipients(unit: &Unit) -> Result<()> {
 
     if total_earned_headers_commission_share != 100 {
         bail_with_validation_err!(
-            UnitError,
+            CommissionMismatch,
             "sum of earned_headers_commission_share is not 100"
         );
     }
@@ -587,7 +1008,8 @@ fn validate_parents(
         return Err(ValidationError::NeedParentUnits(missing_parent_units));
     }
 
-    let mut stmt = tx.prepare_cached("SELECT is_stable, is_on_main_chain, main_chain_index, ball, (SELECT MAX(main_chain_index) FROM units) AS max_known_mci \n\
+    let mut stmt = tx.prepare_cached("SELECT is_stable, is_on_main_chain, main_chain_index, ball, (SELECT MAX(main_chain_index) FROM units) AS max_known_mci, \n\
+				CAST(strftime('%s', creation_date) AS INTEGER) AS timestamp \n\
 				FROM units LEFT JOIN balls USING(unit) WHERE unit=?")?;
     let last_ball = &unit.last_ball;
     let last_ball_unit = unit.last_ball_unit.as_ref().expect("no last ball unit");
@@ -597,6 +1019,7 @@ fn validate_parents(
         main_chain_index: u32,
         ball: Option<String>,
         max_known_mci: u32,
+        timestamp: i64,
     }
     let mut rows = stmt
         .query_map(&[last_ball_unit], |row| LastBallUnitProps {
@@ -605,6 +1028,7 @@ fn validate_parents(
             main_chain_index: row.get(2),
             ball: row.get(3),
             max_known_mci: row.get(4),
+            timestamp: row.get(5),
         })?.collect::<::std::result::Result<Vec<LastBallUnitProps>, _>>()?;
 
     if rows.len() != 1 {
@@ -632,6 +1056,7 @@ fn validate_parents(
 
     validate_state.last_ball_mci = last_ball_unit_props.main_chain_index;
     validate_state.max_known_mci = last_ball_unit_props.max_known_mci;
+    validate_state.last_ball_timestamp = last_ball_unit_props.timestamp as u64;
 
     if validate_state.max_parent_limci < validate_state.last_ball_mci {
         return create_err(format!(
@@ -798,7 +1223,7 @@ fn validate_witnesses(
             temp_witnesses,
         );
         if determine_result.is_err() && validate_state.last_ball_mci >= 512000 {
-            bail_with_validation_err!(UnitError, "{}", determine_result.err().unwrap())
+            bail_with_validation_err!(WitnessListMismatch, "{}", determine_result.err().unwrap())
         }
         let str_witness: String = temp_witnesses
             .iter()
@@ -832,7 +1257,7 @@ fn validate_witnesses(
             &validate_state.last_ball_mci,
         ])? {
             bail_with_validation_err!(
-                UnitError,
+                WitnessListMismatch,
                 "some witnesses have references in their addresses"
             )
         }
@@ -854,17 +1279,20 @@ fn validate_witnesses(
                 main_chain_index: rows.get(2),
             })?.collect::<::std::result::Result<Vec<_>, _>>()?;
         if units.is_empty() {
-            bail_with_validation_err!(UnitError, "referenced witness list unit is empty")
+            bail_with_validation_err!(WitnessListMismatch, "referenced witness list unit is empty")
         }
         let witness_list_unit_props = &units[0];
         if witness_list_unit_props.sequence != "good" {
-            bail_with_validation_err!(UnitError, "witness list unit is not serialy")
+            bail_with_validation_err!(WitnessListMismatch, "witness list unit is not serialy")
         }
         if witness_list_unit_props.is_stable != 1 {
-            bail_with_validation_err!(UnitError, "witness list unit is not stable")
+            bail_with_validation_err!(WitnessListMismatch, "witness list unit is not stable")
         }
         if witness_list_unit_props.main_chain_index > Some(validate_state.last_ball_mci) {
-            bail_with_validation_err!(UnitError, "witness list unit must come before last ball")
+            bail_with_validation_err!(
+                WitnessListMismatch,
+                "witness list unit must come before last ball"
+            )
         }
 
         let mut stmt =
@@ -873,10 +1301,17 @@ fn validate_witnesses(
             .query_map(&[witness_list_unit], |row| row.get(0))?
             .collect::<::std::result::Result<Vec<String>, _>>()?;
         if witnesses.is_empty() {
-            bail_with_validation_err!(UnitError, "referenced witness list unit has no witnessesl")
+            bail_with_validation_err!(
+                WitnessListMismatch,
+                "referenced witness list unit has no witnessesl"
+            )
         }
         if witnesses.len() != config::COUNT_WITNESSES {
-            bail_with_validation_err!(UnitError, "wrong number of witnesses: {}", witnesses.len())
+            bail_with_validation_err!(
+                WitnessListMismatch,
+                "wrong number of witnesses: {}",
+                witnesses.len()
+            )
         }
         validate_witness_list_mutations(&witnesses)?;
     } else if unit.witnesses.len() == config::COUNT_WITNESSES {
@@ -884,11 +1319,14 @@ fn validate_witnesses(
         let mut prev_witness = witness_iter.next();
         for curr_witness in witness_iter {
             if !object_hash::is_chash_valid(curr_witness) {
-                bail_with_validation_err!(UnitError, "witness address is invalid")
+                bail_with_validation_err!(WitnessListMismatch, "witness address is invalid")
             }
 
             if Some(curr_witness) <= prev_witness {
-                bail_with_validation_err!(UnitError, "wrong order of witnesses, or duplicates")
+                bail_with_validation_err!(
+                    WitnessListMismatch,
+                    "wrong order of witnesses, or duplicates"
+                )
             }
             prev_witness = Some(curr_witness);
         }
@@ -915,13 +1353,13 @@ fn validate_witnesses(
             stmt.query_row(&[&validate_state.last_ball_mci], |row| row.get::<_, u32>(0))?;
         if count_stable_good_witnesses != config::COUNT_WITNESSES as u32 {
             bail_with_validation_err!(
-                UnitError,
+                WitnessListMismatch,
                 "some witnesses are not stable, not serial, or don't come before last ball"
             )
         }
         validate_witness_list_mutations(&unit.witnesses)?;
     } else {
-        bail_with_validation_err!(UnitError, "no witnesses or not enough witnesses")
+        bail_with_validation_err!(WitnessListMismatch, "no witnesses or not enough witnesses")
     }
 
     Ok(())
@@ -943,15 +1381,61 @@ fn validate_authors(
         prev_address = author.address.clone();
     }
     validate_state.unit_hash_to_sign = Some(unit.get_unit_hash_to_sign());
+
+    // the stored definitions are read once for the batch and the evaluation
+    let mut stored_definitions = Vec::with_capacity(unit.authors.len());
     for author in &unit.authors {
-        validate_author(tx, author, unit, validate_state)?;
+        let stored_definition = if author.definition.is_null() && unit.content_hash.is_none() {
+            Some(storage::read_definition_by_address(
+                tx,
+                &author.address,
+                Some(validate_state.last_ball_mci),
+            )?)
+        } else {
+            None
+        };
+        stored_definitions.push(stored_definition);
+    }
+
+    if unit.authors.len() > 1 && !validate_state.unsigned {
+        verify_signatures_in_batch(unit, &stored_definitions, validate_state);
+    }
+    for (author, stored_definition) in unit.authors.iter().zip(stored_definitions) {
+        validate_author(tx, author, stored_definition, unit, validate_state)?;
     }
     Ok(())
 }
 
+// verify the plain sig branches of all the authors at once. every result is
+// cached, so the definition evaluation gets the failures from the cache and
+// decides whether the bad signature is in a branch that matters
+fn verify_signatures_in_batch(
+    unit: &Unit,
+    stored_definitions: &[Option<::std::result::Result<Value, String>>],
+    validate_state: &ValidationState,
+) {
+    let unit_hash_to_sign = validate_state.unit_hash_to_sign.as_ref().unwrap();
+    let mut checks = Vec::new();
+    for (author, stored_definition) in unit.authors.iter().zip(stored_definitions) {
+        let definition = match *stored_definition {
+            Some(Ok(ref definition)) => definition,
+            Some(Err(_)) => continue,
+            None => &author.definition,
+        };
+        for (sig, pubkey) in definition::get_sig_pairs(definition, &author.authentifiers) {
+            checks.push((unit_hash_to_sign.as_slice(), sig, pubkey));
+        }
+    }
+    if let Err(e) = signature::verify_batch(&checks) {
+        info!("batch signature verification failed, err={}", e);
+    }
+}
+
+// the stored definition is read here if the caller hasn't
 fn validate_author(
     tx: &Transaction,
     author: &Author,
+    stored_definition: Option<::std::result::Result<Value, String>>,
     unit: &Unit,
     validate_state: &mut ValidationState,
 ) -> Result<()> {
@@ -1246,7 +1730,8 @@ fn validate_author(
             unit,
             validate_state,
             &author.authentifiers,
-        )?;
+        )
+        .map_err(ValidationError::from_definition_error)?;
 
         check_serial_address_use(validate_state, nonserial)?;
         Ok(())
@@ -1262,11 +1747,14 @@ fn validate_author(
             return Ok(());
         }
 
-        let tmp_address_definition = storage::read_definition_by_address(
-            tx,
-            &author.address,
-            Some(validate_state.last_ball_mci),
-        )?;
+        let tmp_address_definition = match stored_definition {
+            Some(stored_definition) => stored_definition,
+            None => storage::read_definition_by_address(
+                tx,
+                &author.address,
+                Some(validate_state.last_ball_mci),
+            )?,
+        };
         let definition = match tmp_address_definition {
             Ok(v) => v,
             Err(chash) => {
@@ -1285,7 +1773,7 @@ fn validate_author(
 }
 
 #[inline]
-fn is_valid_base64(b64: &String, len: usize) -> bool {
+pub fn is_valid_base64(b64: &String, len: usize) -> bool {
     use base64;
     if b64.len() != len {
         return false;
@@ -1377,7 +1865,7 @@ fn validate_message(
 
             if validate_state.input_keys.contains(&spend_proof.spend_proof) {
                 bail_with_validation_err!(
-                    UnitError,
+                    DoubleSpend,
                     "spend proof {} already used",
                     spend_proof.spend_proof
                 );
@@ -1448,19 +1936,14 @@ fn validate_message(
         if message.payload_location == "none" && message.spend_proofs.len() == 0 {
             bail_with_validation_err!(UnitError, "private payment must come with spend proof(s)");
         }
+        ensure_with_validation_err!(
+            message.payload_location != "none" || message.payload.is_none(),
+            UnitError,
+            "private payment must not contain payload"
+        );
     }
 
-    let inline_only_apps = vec![
-        "address_definition_change",
-        "data_feed",
-        "definition_template",
-        "asset",
-        "asset_attestors",
-        "attestation",
-        "poll",
-        "vote",
-    ];
-    if inline_only_apps.contains(&message.app.as_str()) && message.payload_location != "inline" {
+    if INLINE_ONLY_APPS.contains(&message.app.as_str()) && message.payload_location != "inline" {
         bail_with_validation_err!(UnitError, "{} must be inline", message.app);
     }
 
@@ -1496,7 +1979,7 @@ fn validate_spend_proofs(
 
     let sql = format!(
         "SELECT address, unit, main_chain_index, sequence \
-         FROM spend_proofs JOIN units USING(unit) WHERE unit != {} AND ({})",
+         FROM spend_proofs JOIN units USING(unit) WHERE unit != '{}' AND ({})",
         unit.unit.as_ref().unwrap(),
         eqs
     );
@@ -1539,7 +2022,7 @@ fn check_for_double_spend(
     for conflicting_record in rows {
         if !author_addresses.contains(&&conflicting_record.address) {
             bail_with_validation_err!(
-                UnitError,
+                DoubleSpend,
                 "conflicting {} spent from another address?",
                 kind
             );
@@ -1561,11 +2044,11 @@ fn check_for_double_spend(
             if conflicting_record.main_chain_index > Some(validate_state.last_ball_mci)
                 || conflicting_record.main_chain_index == None
             {
-                bail_with_validation_err!(UnitError, error);
+                bail_with_validation_err!(DoubleSpend, error);
             }
 
             match conflicting_record.sequence.as_str() {
-                "good" => bail_with_validation_err!(UnitError, error), // in good sequence (final state)
+                "good" => bail_with_validation_err!(DoubleSpend, error), // in good sequence (final state)
                 "final-bad" => continue, // to be voided: can reuse the output
                 _ => bail_with_validation_err!(
                     UnitError,
@@ -1579,7 +2062,7 @@ fn check_for_double_spend(
                 validate_state
                     .addresses_with_forked_path
                     .contains(&conflicting_record.address),
-                UnitError,
+                DoubleSpend,
                 "double spending {} without double spending address?",
                 kind
             );
@@ -1619,24 +2102,19 @@ fn validate_inline_payload(
 ) -> Result<()> {
     let ref payload = message.payload.as_ref();
 
+    // the payload hash is checked in validate_stateless
     ensure_with_validation_err!(payload.is_some(), UnitError, "no inline payload");
 
-    let payload_hash = object_hash::get_base64_hash(payload)?;
-    ensure_with_validation_err!(
-        payload_hash == message.payload_hash,
-        UnitError,
-        "wrong payload hash: expected {}, got {}",
-        payload_hash,
-        message.payload_hash
-    );
-
     match message.app.as_str() {
         "text" => match payload {
             Some(Payload::Text(ref _s)) => {}
             _ => bail_with_validation_err!(UnitError, "payload must be string"),
         },
-        "payment" => if let Some(Payload::Payment(ref payment)) = payload {
-            validate_payment(tx, payment, message_index, unit, validate_state)?;
+        "payment" => match payload {
+            Some(Payload::Payment(ref payment)) => {
+                validate_payment(tx, payment, message_index, unit, validate_state)?
+            }
+            _ => bail_with_validation_err!(UnitError, "payment payload is not payment"),
         },
         "data_feed" => {
             if validate_state.has_data_feed {
@@ -1682,27 +2160,476 @@ fn validate_inline_payload(
                 _ => bail_with_validation_err!(UnitError, "data feed payload is not data_feed"),
             }
         }
-        _ => unimplemented!(),
+        "asset" => match payload {
+            Some(Payload::Other(ref v)) => {
+                let asset = match serde_json::from_value::<AssetDefinition>(v.clone()) {
+                    Ok(asset) => asset,
+                    Err(e) => {
+                        bail_with_validation_err!(UnitError, "invalid asset definition: {}", e)
+                    }
+                };
+                validate_asset_definition(tx, &asset, unit, validate_state)?;
+            }
+            _ => bail_with_validation_err!(UnitError, "asset payload is not asset definition"),
+        },
+        "poll" => {
+            if validate_state.has_poll {
+                bail_with_validation_err!(UnitError, "can be only one poll");
+            }
+            validate_state.has_poll = true;
+            match payload {
+                Some(Payload::Other(ref v)) => match serde_json::from_value::<Poll>(v.clone()) {
+                    Ok(poll) => validate_poll(&poll)?,
+                    Err(e) => bail_with_validation_err!(UnitError, "invalid poll: {}", e),
+                },
+                _ => bail_with_validation_err!(UnitError, "poll payload is not poll"),
+            }
+        }
+        "vote" => match payload {
+            Some(Payload::Other(ref v)) => match serde_json::from_value::<Vote>(v.clone()) {
+                Ok(vote) => validate_vote(tx, &vote, validate_state)?,
+                Err(e) => bail_with_validation_err!(UnitError, "invalid vote: {}", e),
+            },
+            _ => bail_with_validation_err!(UnitError, "vote payload is not vote"),
+        },
+        "attestation" => match payload {
+            Some(Payload::Other(ref v)) => match serde_json::from_value::<Attestation>(v.clone()) {
+                Ok(attestation) => validate_attestation(&attestation, unit)?,
+                Err(e) => bail_with_validation_err!(UnitError, "invalid attestation: {}", e),
+            },
+            _ => bail_with_validation_err!(UnitError, "attestation payload is not attestation"),
+        },
+        "asset_attestors" => match payload {
+            Some(Payload::Other(ref v)) => {
+                match serde_json::from_value::<AssetAttestors>(v.clone()) {
+                    Ok(asset_attestors) => {
+                        validate_asset_attestors(tx, &asset_attestors, unit, validate_state)?
+                    }
+                    Err(e) => bail_with_validation_err!(UnitError, "invalid attestor list: {}", e),
+                }
+            }
+            _ => {
+                bail_with_validation_err!(UnitError, "asset_attestors payload is not attestor list")
+            }
+        },
+        "address_definition_change" => match payload {
+            Some(Payload::Other(ref v)) => {
+                match serde_json::from_value::<AddressDefinitionChange>(v.clone()) {
+                    Ok(change) => {
+                        validate_address_definition_change(&change, unit, validate_state)?
+                    }
+                    Err(e) => bail_with_validation_err!(
+                        UnitError,
+                        "invalid address_definition_change: {}",
+                        e
+                    ),
+                }
+            }
+            _ => bail_with_validation_err!(
+                UnitError,
+                "address_definition_change payload is not address_definition_change"
+            ),
+        },
+        "profile" => {
+            ensure_with_validation_err!(
+                unit.authors.len() == 1,
+                UnitError,
+                "profile must be single-authored"
+            );
+            if validate_state.has_profile {
+                bail_with_validation_err!(UnitError, "can be only one profile");
+            }
+            validate_state.has_profile = true;
+            match payload {
+                Some(Payload::Other(Value::Object(_))) => {}
+                _ => bail_with_validation_err!(UnitError, "profile payload must be object"),
+            }
+        }
+        "data" => match payload {
+            Some(Payload::Other(Value::Object(_))) => {}
+            _ => bail_with_validation_err!(UnitError, "data payload must be object"),
+        },
+        "definition_template" => {
+            if validate_state.has_definition_template {
+                bail_with_validation_err!(UnitError, "can be only one definition template");
+            }
+            validate_state.has_definition_template = true;
+            match payload {
+                Some(Payload::Other(Value::Array(ref template))) if template.len() == 2 => {}
+                _ => bail_with_validation_err!(
+                    UnitError,
+                    "definition template payload must be array of two elements"
+                ),
+            }
+        }
+        app => bail_with_validation_err!(UnitError, "unknown app: {}", app),
     }
 
     Ok(())
 }
 
-fn validate_payment(
-    tx: &Transaction,
-    payment: &Payment,
-    message_index: usize,
-    unit: &Unit,
-    validate_state: &mut ValidationState,
-) -> Result<()> {
-    //Base currency
-    if payment.asset.is_none() {
+fn validate_poll(poll: &Poll) -> Result<()> {
+    ensure_with_validation_err!(!poll.question.is_empty(), UnitError, "no question in poll");
+    ensure_with_validation_err!(!poll.choices.is_empty(), UnitError, "no choices in poll");
+    ensure_with_validation_err!(
+        poll.choices.len() <= config::MAX_CHOICES_PER_POLL,
+        UnitError,
+        "too many choices in poll"
+    );
+    for (i, choice) in poll.choices.iter().enumerate() {
         ensure_with_validation_err!(
-            payment.address.is_none()
-                && payment.definition_chash.is_none()
-                && payment.denomination.is_none(),
+            !choice.is_empty(),
             UnitError,
-            "unknown fields in payment message"
+            "all choices must be nonempty strings"
+        );
+        ensure_with_validation_err!(
+            choice.len() <= config::MAX_CHOICE_LENGTH,
+            UnitError,
+            "too long choice in poll"
+        );
+        ensure_with_validation_err!(
+            !poll.choices[..i].contains(choice),
+            UnitError,
+            "duplicate choice in poll: {}",
+            choice
+        );
+    }
+    Ok(())
+}
+
+fn validate_vote(tx: &Transaction, vote: &Vote, validate_state: &ValidationState) -> Result<()> {
+    ensure_with_validation_err!(
+        vote.unit.len() == config::HASH_LENGTH,
+        UnitError,
+        "invalid poll unit"
+    );
+    ensure_with_validation_err!(!vote.choice.is_empty(), UnitError, "invalid choice");
+    ensure_with_validation_err!(
+        vote.choice.len() <= config::MAX_CHOICE_LENGTH,
+        UnitError,
+        "choice too long"
+    );
+
+    let mut stmt = tx.prepare_cached(
+        "SELECT main_chain_index, sequence FROM polls \
+         JOIN poll_choices USING(unit) JOIN units USING(unit) \
+         WHERE polls.unit=? AND choice=?",
+    )?;
+    let rows = stmt
+        .query_map(&[&vote.unit, &vote.choice], |row| {
+            (row.get::<_, Option<u32>>(0), row.get::<_, String>(1))
+        })?
+        .collect::<::std::result::Result<Vec<_>, _>>()?;
+    let (main_chain_index, sequence) = match rows.into_iter().next() {
+        Some(row) => row,
+        None => bail_with_validation_err!(UnitError, "invalid choice or poll"),
+    };
+    match main_chain_index {
+        Some(mci) if mci <= validate_state.last_ball_mci => {}
+        _ => bail_with_validation_err!(UnitError, "poll unit must be before last ball"),
+    }
+    ensure_with_validation_err!(sequence == "good", UnitError, "poll unit is not serial");
+    Ok(())
+}
+
+fn validate_address_definition_change(
+    change: &AddressDefinitionChange,
+    unit: &Unit,
+    validate_state: &mut ValidationState,
+) -> Result<()> {
+    let address = if unit.authors.len() > 1 {
+        let address = match change.address {
+            Some(ref address) if is_valid_address(address) => address,
+            _ => bail_with_validation_err!(UnitError, "when multi-authored, must indicate address"),
+        };
+        ensure_with_validation_err!(
+            unit.authors.iter().any(|author| &author.address == address),
+            UnitError,
+            "foreign address"
+        );
+        address
+    } else {
+        ensure_with_validation_err!(
+            change.address.is_none(),
+            UnitError,
+            "when single-authored, must not indicate address"
+        );
+        &unit.authors[0].address
+    };
+
+    ensure_with_validation_err!(
+        !validate_state
+            .addresses_with_definition_change
+            .contains(address),
+        UnitError,
+        "can be only one definition change per address"
+    );
+    validate_state
+        .addresses_with_definition_change
+        .push(address.clone());
+
+    ensure_with_validation_err!(
+        is_valid_address(&change.definition_chash),
+        UnitError,
+        "bad new definition_chash"
+    );
+    Ok(())
+}
+
+fn validate_attestation(attestation: &Attestation, unit: &Unit) -> Result<()> {
+    ensure_with_validation_err!(
+        unit.authors.len() == 1,
+        UnitError,
+        "attestation must be single-authored"
+    );
+    // it is ok to attest an address that has never been used, or to attest oneself
+    ensure_with_validation_err!(
+        is_valid_address(&attestation.address),
+        UnitError,
+        "attesting an invalid address"
+    );
+    Ok(())
+}
+
+fn validate_asset_attestors(
+    tx: &Transaction,
+    asset_attestors: &AssetAttestors,
+    unit: &Unit,
+    validate_state: &mut ValidationState,
+) -> Result<()> {
+    ensure_with_validation_err!(
+        unit.authors.len() == 1,
+        UnitError,
+        "attestor list must be single-authored"
+    );
+    let asset = &asset_attestors.asset;
+    ensure_with_validation_err!(
+        asset.len() == config::HASH_LENGTH,
+        UnitError,
+        "invalid asset"
+    );
+    ensure_with_validation_err!(
+        !validate_state.assets_with_attestor_list.contains(asset),
+        UnitError,
+        "can be only one attestor list update per asset"
+    );
+    validate_state.assets_with_attestor_list.push(asset.clone());
+
+    let asset_info = match storage::read_asset_info(tx, asset)? {
+        Some(asset_info) => asset_info,
+        None => bail_with_validation_err!(UnitError, "asset {} not found", asset),
+    };
+    match asset_info.main_chain_index {
+        Some(mci) if mci <= validate_state.last_ball_mci => {}
+        _ => bail_with_validation_err!(UnitError, "asset definition must be before last ball"),
+    }
+    ensure_with_validation_err!(
+        asset_info.spender_attested,
+        UnitError,
+        "this asset does not require attestors"
+    );
+    ensure_with_validation_err!(
+        unit.authors[0].address == asset_info.definer_address,
+        UnitError,
+        "attestor list can be edited only by definer"
+    );
+
+    check_attestor_list(Some(&asset_attestors.attestors))
+}
+
+// every spender of an attested asset must be attested by one of its current attestors
+fn check_spenders_attested(
+    tx: &Transaction,
+    asset: &storage::AssetInfo,
+    spenders: &[String],
+    last_ball_mci: u32,
+) -> Result<()> {
+    let attestors = storage::read_asset_attestors(tx, &asset.asset, last_ball_mci)?;
+    ensure_with_validation_err!(!attestors.is_empty(), UnitError, "no attestors for asset");
+
+    for spender in spenders {
+        let mut is_attested = false;
+        for attestor in &attestors {
+            if storage::is_attested(tx, spender, attestor, last_ball_mci)? {
+                is_attested = true;
+                break;
+            }
+        }
+        ensure_with_validation_err!(
+            is_attested,
+            UnitError,
+            "spender {} is not attested",
+            spender
+        );
+    }
+    Ok(())
+}
+
+fn check_attestor_list(attestors: Option<&Vec<String>>) -> Result<()> {
+    let attestors = match attestors {
+        Some(attestors) if !attestors.is_empty() => attestors,
+        _ => bail_with_validation_err!(UnitError, "attestors not defined"),
+    };
+    ensure_with_validation_err!(
+        attestors.len() <= config::MAX_ATTESTORS_PER_ASSET,
+        UnitError,
+        "too many attestors"
+    );
+    for (i, attestor) in attestors.iter().enumerate() {
+        ensure_with_validation_err!(
+            i == 0 || &attestors[i - 1] < attestor,
+            UnitError,
+            "attestors not sorted"
+        );
+        ensure_with_validation_err!(
+            is_valid_address(attestor),
+            UnitError,
+            "invalid attestor address: {}",
+            attestor
+        );
+    }
+    Ok(())
+}
+
+fn validate_asset_definition(
+    tx: &Transaction,
+    asset: &AssetDefinition,
+    unit: &Unit,
+    validate_state: &mut ValidationState,
+) -> Result<()> {
+    ensure_with_validation_err!(
+        unit.authors.len() == 1,
+        UnitError,
+        "asset definition must be single-authored"
+    );
+
+    if let Some(cap) = asset.cap {
+        ensure_with_validation_err!(cap > 0 && cap <= config::MAX_CAP, UnitError, "invalid cap");
+    }
+
+    if asset.spender_attested {
+        check_attestor_list(asset.attestors.as_ref())?;
+    } else {
+        ensure_with_validation_err!(
+            asset.attestors.is_none(),
+            UnitError,
+            "attestors defined when spender_attested is false"
+        );
+    }
+
+    match asset.denominations {
+        Some(ref denominations) => {
+            ensure_with_validation_err!(
+                asset.fixed_denominations,
+                UnitError,
+                "denominations defined when fixed_denominations is false"
+            );
+            ensure_with_validation_err!(
+                !denominations.is_empty(),
+                UnitError,
+                "denominations not defined"
+            );
+            ensure_with_validation_err!(
+                denominations.len() <= config::MAX_DENOMINATIONS_PER_ASSET_DEFINITION,
+                UnitError,
+                "too many denominations"
+            );
+
+            let mut prev_denomination = 0;
+            let mut total_cap = Some(0);
+            for denomination in denominations {
+                ensure_with_validation_err!(
+                    denomination.denomination > prev_denomination,
+                    UnitError,
+                    "denominations unsorted"
+                );
+                prev_denomination = denomination.denomination;
+                match denomination.count_coins {
+                    Some(count_coins) => {
+                        ensure_with_validation_err!(
+                            count_coins > 0,
+                            UnitError,
+                            "invalid number of coins"
+                        );
+                        if let Some(cap) = total_cap {
+                            let amount = i64::from(denomination.denomination)
+                                .checked_mul(count_coins)
+                                .and_then(|amount| cap.checked_add(amount));
+                            ensure_with_validation_err!(
+                                amount.is_some(),
+                                UnitError,
+                                "sum of denominations overflow"
+                            );
+                            total_cap = amount;
+                        }
+                    }
+                    None => total_cap = None,
+                }
+            }
+
+            // all the denominations have count, then the cap is determined
+            if let (Some(cap), Some(total_cap)) = (asset.cap, total_cap) {
+                ensure_with_validation_err!(
+                    cap == total_cap,
+                    UnitError,
+                    "cap doesn't match sum of denominations"
+                );
+            }
+        }
+        None => ensure_with_validation_err!(
+            !asset.fixed_denominations,
+            UnitError,
+            "denominations not defined"
+        ),
+    }
+
+    if asset.is_private && asset.is_transferrable && !asset.fixed_denominations {
+        bail_with_validation_err!(
+            UnitError,
+            "if private and transferrable, must have fixed denominations"
+        );
+    }
+    if asset.is_private
+        && !asset.fixed_denominations
+        && !(asset.auto_destroy && !asset.is_transferrable)
+    {
+        bail_with_validation_err!(
+            UnitError,
+            "if private and divisible, must also be auto-destroy and non-transferrable"
+        );
+    }
+    if asset.cap.is_some() && !asset.issued_by_definer_only {
+        bail_with_validation_err!(UnitError, "if capped, must be issued by definer only");
+    }
+
+    for condition in &[&asset.issue_condition, &asset.transfer_condition] {
+        if let Some(condition) = condition {
+            if let Err(e) =
+                definition::validate_definition(tx, condition, unit, validate_state, true)
+            {
+                bail_with_validation_err!(UnitError, "invalid asset condition: {}", e);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn validate_payment(
+    tx: &Transaction,
+    payment: &Payment,
+    message_index: usize,
+    unit: &Unit,
+    validate_state: &mut ValidationState,
+) -> Result<()> {
+    //Base currency
+    if payment.asset.is_none() {
+        ensure_with_validation_err!(
+            payment.address.is_none()
+                && payment.definition_chash.is_none()
+                && payment.denomination.is_none(),
+            UnitError,
+            "unknown fields in payment message"
         );
 
         ensure_with_validation_err!(
@@ -1723,19 +2650,108 @@ fn validate_payment(
         );
     }
 
-    //We do not handle assets for now
-    unimplemented!();
+    let asset = payment.asset.as_ref().unwrap();
+    ensure_with_validation_err!(
+        asset.len() == config::HASH_LENGTH,
+        UnitError,
+        "invalid asset"
+    );
+    ensure_with_validation_err!(
+        payment.address.is_none() && payment.definition_chash.is_none(),
+        UnitError,
+        "unknown fields in payment message"
+    );
+
+    let asset_info = match storage::read_asset_info(tx, asset)? {
+        Some(info) => info,
+        None => bail_with_validation_err!(UnitError, "asset {} not found", asset),
+    };
+    if asset_info.main_chain_index.is_none()
+        || asset_info.main_chain_index > Some(validate_state.last_ball_mci)
+    {
+        bail_with_validation_err!(UnitError, "asset definition must be before last ball");
+    }
+    ensure_with_validation_err!(
+        asset_info.sequence == "good",
+        UnitError,
+        "asset definition is not serial"
+    );
+
+    if asset_info.fixed_denominations {
+        ensure_with_validation_err!(payment.denomination > Some(0), UnitError, "no denomination");
+    } else {
+        ensure_with_validation_err!(
+            payment.denomination.is_none(),
+            UnitError,
+            "denomination in arbitrary-amounts asset"
+        );
+    }
+
+    ensure_with_validation_err!(
+        asset_info.is_private == validate_state.is_private,
+        UnitError,
+        "asset privacy mismatch"
+    );
+
+    let is_cosigned_by_definer = unit
+        .authors
+        .iter()
+        .any(|a| a.address == asset_info.definer_address);
+    ensure_with_validation_err!(
+        !asset_info.cosigned_by_definer || is_cosigned_by_definer,
+        UnitError,
+        "must be cosigned by definer"
+    );
+
+    validate_payment_inputs_and_outputs(
+        tx,
+        payment,
+        Some(&asset_info),
+        message_index,
+        unit,
+        validate_state,
+    )
+}
+
+// evaluate the issue or transfer condition of the asset against the unit
+fn validate_asset_condition(
+    tx: &Transaction,
+    asset: &storage::AssetInfo,
+    kind: &str,
+    condition: &Option<Value>,
+    unit: &Unit,
+    validate_state: &mut ValidationState,
+) -> Result<()> {
+    let condition = match condition {
+        Some(condition) => condition,
+        None => return Ok(()),
+    };
+
+    if let Err(e) = definition::validate_authentifiers(
+        tx,
+        "",
+        &Value::from(asset.asset.as_str()),
+        condition,
+        unit,
+        validate_state,
+        &HashMap::new(),
+    ) {
+        bail_with_validation_err!(UnitError, "{}_condition not satisfied: {}", kind, e);
+    }
+    Ok(())
 }
 
 fn validate_payment_inputs_and_outputs(
     tx: &Transaction,
     payment: &Payment,
-    asset: Option<String>,
+    asset: Option<&storage::AssetInfo>,
     message_index: usize,
     unit: &Unit,
     validate_state: &mut ValidationState,
 ) -> Result<()> {
     let denomination = payment.denomination.unwrap_or(1);
+    let asset_name = asset.map(|a| a.asset.as_str()).unwrap_or("base");
+    let is_fixed_denominations = asset.map_or(false, |a| a.fixed_denominations);
 
     let author_addresses = unit.authors.iter().map(|a| &a.address).collect::<Vec<_>>();
 
@@ -1749,6 +2765,11 @@ fn validate_payment_inputs_and_outputs(
         UnitError,
         "too many outputs"
     );
+    ensure_with_validation_err!(
+        !is_fixed_denominations || payment.inputs.len() == 1,
+        UnitError,
+        "fixed denominations payment must have 1 input"
+    );
 
     let mut input_addresses = Vec::new();
     let mut output_addresses = Vec::new();
@@ -1767,6 +2788,17 @@ fn validate_payment_inputs_and_outputs(
 
         // TODO: add asset check, we don't support private asset payment
         let amount = output.amount;
+        ensure_with_validation_err!(
+            output.blinding.is_some() == validate_state.is_private,
+            UnitError,
+            "blinding is required in private outputs only"
+        );
+        ensure_with_validation_err!(
+            !is_fixed_denominations || amount % i64::from(denomination) == 0,
+            UnitError,
+            "output amount must be a multiple of denomination {}",
+            denomination
+        );
         let address = &output.address;
 
         ensure_with_validation_err!(
@@ -1823,20 +2855,20 @@ fn validate_payment_inputs_and_outputs(
                     "amount must be positive"
                 );
 
-                //serial_number is a u32!
-                // ensure_with_validation_err!(
-                //     input.serial_number > Some(0),
-                //     UnitError,
-                //     "serial_number must be positive"
-                // );
-
-                //if (!objAsset || objAsset.cap)
                 ensure_with_validation_err!(
-                    input.serial_number == Some(1),
+                    input.serial_number > Some(0),
                     UnitError,
-                    "for capped asset serial_number must be 1"
+                    "serial_number must be positive"
                 );
 
+                if asset.map_or(true, |a| a.cap.is_some()) {
+                    ensure_with_validation_err!(
+                        input.serial_number == Some(1),
+                        UnitError,
+                        "for capped asset serial_number must be 1"
+                    );
+                }
+
                 ensure_with_validation_err!(
                     !b_issue,
                     UnitError,
@@ -1872,23 +2904,68 @@ fn validate_payment_inputs_and_outputs(
 
                 input_addresses.push(address.clone());
 
-                //Why not checking this first?
-                ensure_with_validation_err!(
-                    unit.is_genesis_unit(),
-                    UnitError,
-                    "only genesis can issue base asset"
-                );
+                match asset {
+                    Some(asset) if asset.fixed_denominations => {
+                        let count_coins = read_count_coins(tx, &asset.asset, denomination)?;
+                        match count_coins {
+                            Some(count_coins) => ensure_with_validation_err!(
+                                count_coins
+                                    .checked_mul(i64::from(denomination))
+                                    .map_or(false, |amount| input.amount == Some(amount)),
+                                UnitError,
+                                "wrong size of issue of denomination {}",
+                                denomination
+                            ),
+                            None => ensure_with_validation_err!(
+                                input.amount.unwrap_or(0) % i64::from(denomination) == 0,
+                                UnitError,
+                                "issue amount must be a multiple of denomination {}",
+                                denomination
+                            ),
+                        }
+                        ensure_with_validation_err!(
+                            !asset.issued_by_definer_only
+                                || address.as_str() == asset.definer_address,
+                            UnitError,
+                            "only definer can issue this asset"
+                        );
+                    }
+                    Some(asset) => {
+                        if let Some(cap) = asset.cap {
+                            ensure_with_validation_err!(
+                                input.amount == Some(cap),
+                                UnitError,
+                                "issue must be equal to cap"
+                            );
+                        }
+                        ensure_with_validation_err!(
+                            !asset.issued_by_definer_only
+                                || address.as_str() == asset.definer_address,
+                            UnitError,
+                            "only definer can issue this asset"
+                        );
+                    }
+                    None => {
+                        //Why not checking this first?
+                        ensure_with_validation_err!(
+                            unit.is_genesis_unit(),
+                            UnitError,
+                            "only genesis can issue base asset"
+                        );
 
-                ensure_with_validation_err!(
-                    input.amount == Some(config::TOTAL_WHITEBYTES),
-                    UnitError,
-                    "issue must be equal to cap"
-                );
+                        ensure_with_validation_err!(
+                            input.amount == Some(config::TOTAL_WHITEBYTES),
+                            UnitError,
+                            "issue must be equal to cap"
+                        );
+                    }
+                }
 
                 total_input += input.amount.unwrap_or(0);
 
                 let input_key = format!(
-                    "base-{}-{}-{}",
+                    "{}-{}-{}-{}",
+                    asset_name,
                     denomination,
                     address,
                     input.serial_number.unwrap_or(0),
@@ -1896,16 +2973,49 @@ fn validate_payment_inputs_and_outputs(
 
                 ensure_with_validation_err!(
                     !validate_state.input_keys.contains(&input_key),
-                    UnitError,
+                    DoubleSpend,
                     "input {} already used",
                     input_key
                 );
                 validate_state.input_keys.push(input_key);
 
-                let double_spend_where = "type='issue'".to_owned();
+                let mut double_spend_where = "type='issue'".to_owned();
+                if let Some(asset) = asset {
+                    if asset.fixed_denominations {
+                        double_spend_where += &format!(" AND denomination={}", denomination);
+                    }
+                    double_spend_where +=
+                        &format!(" AND serial_number={}", input.serial_number.unwrap());
+                    if !asset.issued_by_definer_only {
+                        double_spend_where += &format!(" AND address='{}'", address);
+                    }
+
+                    validate_asset_condition(
+                        tx,
+                        asset,
+                        "issue",
+                        &asset.issue_condition,
+                        unit,
+                        validate_state,
+                    )?;
+
+                    // double spends of private payments are caught by their spend proofs
+                    if validate_state.is_private {
+                        let spend_proof = private_payment::get_issue_spend_proof(
+                            &asset.asset,
+                            address,
+                            input.amount.unwrap_or(0),
+                            input.serial_number.unwrap(),
+                            some_if!(asset.fixed_denominations, denomination),
+                        )?;
+                        check_private_spend_proof(tx, &spend_proof, address, message_index, unit)?;
+                        continue;
+                    }
+                }
                 check_input_double_spend(
                     tx,
                     &double_spend_where,
+                    asset,
                     unit,
                     validate_state,
                     message_index,
@@ -1954,20 +3064,20 @@ fn validate_payment_inputs_and_outputs(
                 let input_output_index = input.output_index.unwrap();
 
                 let input_key = format!(
-                    "base-{}-{}-{}",
-                    input_unit, input_message_index, input_output_index,
+                    "{}-{}-{}-{}",
+                    asset_name, input_unit, input_message_index, input_output_index,
                 );
 
                 ensure_with_validation_err!(
                     !validate_state.input_keys.contains(&input_key),
-                    UnitError,
+                    DoubleSpend,
                     "input {} already used",
                     input_key
                 );
                 validate_state.input_keys.push(input_key);
 
                 let mut stmt = tx.prepare_cached(
-                    "SELECT amount, is_stable, sequence, address, main_chain_index, denomination, asset \
+                    "SELECT amount, is_stable, sequence, address, main_chain_index, denomination, asset, blinding \
                         FROM outputs \
                         JOIN units USING(unit) \
                         WHERE outputs.unit=? AND message_index=? AND output_index=?",
@@ -1981,6 +3091,7 @@ fn validate_payment_inputs_and_outputs(
                     main_chain_index: Option<u32>,
                     denomination: u32,
                     asset: Option<String>,
+                    blinding: Option<String>,
                 }
 
                 let rows = stmt
@@ -1994,6 +3105,7 @@ fn validate_payment_inputs_and_outputs(
                             main_chain_index: row.get(4),
                             denomination: row.get(5),
                             asset: row.get(6),
+                            blinding: row.get(7),
                         },
                     )?.collect::<::std::result::Result<Vec<_>, _>>()?;
 
@@ -2013,7 +3125,6 @@ fn validate_payment_inputs_and_outputs(
                     "src output amount is not a number"
                 );
 
-                //Now the payment.asset is None
                 ensure_with_validation_err!(
                     payment.asset == src_output.asset,
                     UnitError,
@@ -2052,6 +3163,35 @@ fn validate_payment_inputs_and_outputs(
 
                 total_input += src_output.amount.unwrap_or(0);
 
+                if let Some(asset) = asset {
+                    validate_asset_condition(
+                        tx,
+                        asset,
+                        "transfer",
+                        &asset.transfer_condition,
+                        unit,
+                        validate_state,
+                    )?;
+
+                    if validate_state.is_private {
+                        let spend_proof = private_payment::get_transfer_spend_proof(
+                            &asset.asset,
+                            input,
+                            owner_address,
+                            src_output.amount.unwrap_or(0),
+                            &src_output.blinding,
+                        )?;
+                        check_private_spend_proof(
+                            tx,
+                            &spend_proof,
+                            owner_address,
+                            message_index,
+                            unit,
+                        )?;
+                        continue;
+                    }
+                }
+
                 let double_spend_where = format!(
                     "type='{}' AND src_unit='{}' AND src_message_index={} AND src_output_index={}",
                     kind, input_unit, input_message_index, input_output_index
@@ -2059,6 +3199,7 @@ fn validate_payment_inputs_and_outputs(
                 check_input_double_spend(
                     tx,
                     &double_spend_where,
+                    asset,
                     unit,
                     validate_state,
                     message_index,
@@ -2066,6 +3207,12 @@ fn validate_payment_inputs_and_outputs(
                 )?;
             }
             "headers_commission" | "witnessing" => {
+                ensure_with_validation_err!(
+                    asset.is_none(),
+                    UnitError,
+                    "only base asset can have {}",
+                    kind
+                );
                 if kind == "headers_commission" {
                     ensure_with_validation_err!(
                         !b_have_witnessing,
@@ -2138,7 +3285,7 @@ fn validate_payment_inputs_and_outputs(
                 );
                 ensure_with_validation_err!(
                     !validate_state.input_keys.contains(&input_key),
-                    UnitError,
+                    DoubleSpend,
                     "input {} already used",
                     input_key
                 );
@@ -2195,7 +3342,7 @@ fn validate_payment_inputs_and_outputs(
                 total_input += i64::from(commission);
 
                 let double_spend_where = format!(
-                    "type='{}' AND from_main_chain_index={} AND address={} AND asset IS NULL",
+                    "type='{}' AND from_main_chain_index={} AND address='{}'",
                     kind,
                     input.from_main_chain_index.unwrap(),
                     address
@@ -2203,6 +3350,7 @@ fn validate_payment_inputs_and_outputs(
                 check_input_double_spend(
                     tx,
                     &double_spend_where,
+                    asset,
                     unit,
                     validate_state,
                     message_index,
@@ -2215,9 +3363,44 @@ fn validate_payment_inputs_and_outputs(
 
     info!(
         "inputs done {:?} {:?} {:?}",
-        asset, input_addresses, output_addresses
+        asset_name, input_addresses, output_addresses
     );
 
+    if let Some(asset) = asset {
+        if asset.spender_attested {
+            check_spenders_attested(tx, asset, &input_addresses, validate_state.last_ball_mci)?;
+        }
+
+        // only the definer can send a non-transferrable asset to anybody,
+        // everybody else can only send it back to the definer
+        if !asset.is_transferrable && !b_issue && !input_addresses.contains(&asset.definer_address)
+        {
+            ensure_with_validation_err!(
+                output_addresses
+                    .iter()
+                    .all(|a| *a == asset.definer_address || input_addresses.contains(a)),
+                UnitError,
+                "the asset is not transferrable"
+            );
+            ensure_with_validation_err!(
+                output_addresses.contains(&asset.definer_address),
+                UnitError,
+                "the asset is not transferrable and definer not found on either side of the deal"
+            );
+        }
+
+        ensure_with_validation_err!(
+            total_input == total_output,
+            UnitError,
+            "inputs and outputs do not balance: {} != {}",
+            total_input,
+            total_output
+        );
+
+        info!("validatePaymentInputsAndOutputs done");
+        return Ok(());
+    }
+
     ensure_with_validation_err!(
         total_input
             == total_output
@@ -2236,17 +3419,63 @@ fn validate_payment_inputs_and_outputs(
     Ok(())
 }
 
+// the spend proofs of a private payment are posted along with its unit
+fn check_private_spend_proof(
+    tx: &Transaction,
+    spend_proof: &str,
+    address: &str,
+    message_index: usize,
+    unit: &Unit,
+) -> Result<()> {
+    let mut stmt = tx.prepare_cached(
+        "SELECT 1 FROM spend_proofs \
+         WHERE unit=? AND message_index=? AND spend_proof=? AND address=?",
+    )?;
+    ensure_with_validation_err!(
+        stmt.exists(&[
+            unit.unit.as_ref().unwrap(),
+            &(message_index as u32),
+            &spend_proof,
+            &address
+        ])?,
+        UnitError,
+        "spend proof {} not found",
+        spend_proof
+    );
+    Ok(())
+}
+
+// return the number of coins of the capped asset denomination, None for uncapped assets
+fn read_count_coins(tx: &Transaction, asset: &String, denomination: u32) -> Result<Option<i64>> {
+    let mut stmt = tx.prepare_cached(
+        "SELECT count_coins FROM asset_denominations WHERE asset=? AND denomination=?",
+    )?;
+    let rows = stmt
+        .query_map(&[asset, &denomination], |row| row.get(0))?
+        .collect::<::std::result::Result<Vec<Option<i64>>, _>>()?;
+    if rows.is_empty() {
+        bail_with_validation_err!(UnitError, "invalid denomination: {}", denomination);
+    }
+    Ok(rows[0])
+}
+
 fn check_input_double_spend(
     tx: &Transaction,
-    double_spend_where: &String,
+    double_spend_where: &str,
+    asset: Option<&storage::AssetInfo>,
     unit: &Unit,
     validate_state: &mut ValidationState,
     message_index: usize,
     input_index: usize,
 ) -> Result<()> {
+    let double_spend_where = match asset {
+        Some(asset) => format!("{} AND asset='{}'", double_spend_where, asset.asset),
+        None => format!("{} AND asset IS NULL", double_spend_where),
+    };
+
     let sql = format!(
         "SELECT unit, address, message_index, input_index, main_chain_index, sequence, is_stable \
-         from inputs JOIN units USING(unit) WHERE {} AND unit !='{}' AND asset IS NULL",
+         from inputs JOIN units USING(unit) WHERE {} AND unit !='{}'",
         double_spend_where,
         unit.unit.as_ref().unwrap(),
     );
@@ -2276,3 +3505,470 @@ fn check_input_double_spend(
 
     Ok(())
 }
+
+#[test]
+fn test_inline_only_apps() {
+    use db;
+
+    let mut db = db::DB_POOL.get_connection();
+    let tx = db.transaction().unwrap();
+    let unit = Unit::default();
+
+    for app in INLINE_ONLY_APPS.iter() {
+        let payload = Payload::Other(json!({}));
+        let mut message = Message {
+            app: app.to_string(),
+            payload_hash: object_hash::get_base64_hash(&payload).unwrap(),
+            payload_location: "none".to_owned(),
+            ..Default::default()
+        };
+        let mut validate_state = ValidationState::new();
+        match validate_message(&tx, &message, 0, &unit, &mut validate_state) {
+            Err(ValidationError::UnitError { err }) => assert!(err.contains("must be inline")),
+            ret => panic!("{} not inline should be rejected, got {:?}", app, ret),
+        }
+
+        // an empty payload is invalid for all the apps
+        message.payload = Some(payload);
+        message.payload_location = "inline".to_owned();
+        let mut validate_state = ValidationState::new();
+        match validate_inline_payload(&tx, &message, 0, &unit, &mut validate_state) {
+            Err(ValidationError::UnitError { .. }) => {}
+            ret => panic!(
+                "{} with empty payload should be rejected, got {:?}",
+                app, ret
+            ),
+        }
+    }
+}
+
+#[test]
+fn test_unknown_app() {
+    use db;
+
+    let mut db = db::DB_POOL.get_connection();
+    let tx = db.transaction().unwrap();
+    let payload = Payload::Other(json!({"foo": "bar"}));
+    let message = Message {
+        app: "foo".to_owned(),
+        payload_hash: object_hash::get_base64_hash(&payload).unwrap(),
+        payload: Some(payload),
+        payload_location: "inline".to_owned(),
+        ..Default::default()
+    };
+    let mut validate_state = ValidationState::new();
+    match validate_inline_payload(&tx, &message, 0, &Unit::default(), &mut validate_state) {
+        Err(ValidationError::UnitError { err }) => assert_eq!(err, "unknown app: foo"),
+        ret => panic!("unknown app should be rejected, got {:?}", ret),
+    }
+}
+
+#[test]
+fn test_unknown_asset_payment() {
+    use db;
+
+    let mut db = db::DB_POOL.get_connection();
+    let tx = db.transaction().unwrap();
+    let asset = "lQCxxsMslXLzQKybX2KArOGho8XuNf1Lpds2abdf8O4=".to_owned();
+    let payment = Payment {
+        address: None,
+        asset: Some(asset.clone()),
+        definition_chash: None,
+        denomination: None,
+        inputs: Vec::new(),
+        outputs: Vec::new(),
+    };
+    let mut validate_state = ValidationState::new();
+    match validate_payment(&tx, &payment, 0, &Unit::default(), &mut validate_state) {
+        Err(ValidationError::UnitError { err }) => {
+            assert_eq!(err, format!("asset {} not found", asset))
+        }
+        ret => panic!("payment in unknown asset should be rejected, got {:?}", ret),
+    }
+}
+
+#[test]
+fn test_invalid_denomination() {
+    use db;
+
+    let mut db = db::DB_POOL.get_connection();
+    let tx = db.transaction().unwrap();
+    let asset = "lQCxxsMslXLzQKybX2KArOGho8XuNf1Lpds2abdf8O4=".to_owned();
+    match read_count_coins(&tx, &asset, 7) {
+        Err(ValidationError::UnitError { err }) => assert_eq!(err, "invalid denomination: 7"),
+        ret => panic!("unknown denomination should be rejected, got {:?}", ret),
+    }
+}
+
+#[test]
+fn test_private_payment_of_unknown_message() {
+    use db;
+
+    let mut db = db::DB_POOL.get_connection();
+    let tx = db.transaction().unwrap();
+    let element = PrivateElement {
+        unit: "lQCxxsMslXLzQKybX2KArOGho8XuNf1Lpds2abdf8O4=".to_owned(),
+        message_index: 7,
+        payload: Payment {
+            address: None,
+            asset: Some("lQCxxsMslXLzQKybX2KArOGho8XuNf1Lpds2abdf8O4=".to_owned()),
+            definition_chash: None,
+            denomination: None,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+        },
+    };
+    match validate_private_payment(&tx, &element) {
+        Err(ValidationError::UnitError { err }) => assert_eq!(err, "message not found"),
+        ret => panic!(
+            "private payment of unknown message should be rejected, got {:?}",
+            ret
+        ),
+    }
+}
+
+#[test]
+fn test_validate_asset_definition() {
+    use db;
+
+    let mut db = db::DB_POOL.get_connection();
+    let tx = db.transaction().unwrap();
+    let mut unit = Unit::default();
+    unit.authors.push(Author {
+        address: "YFAR4AK2RSRTAWZ3ILRFZOMN7M7QJTJ2".to_owned(),
+        authentifiers: Default::default(),
+        definition: Value::Null,
+    });
+    let validate = |asset: Value| {
+        let asset: AssetDefinition = serde_json::from_value(asset).unwrap();
+        validate_asset_definition(&tx, &asset, &unit, &mut ValidationState::new())
+    };
+
+    let mut asset = json!({
+        "cap": 1000000,
+        "is_private": false,
+        "is_transferrable": true,
+        "auto_destroy": false,
+        "fixed_denominations": false,
+        "issued_by_definer_only": true,
+        "cosigned_by_definer": false,
+        "spender_attested": false
+    });
+    assert!(validate(asset.clone()).is_ok());
+
+    asset["issued_by_definer_only"] = json!(false);
+    assert!(validate(asset.clone()).is_err());
+
+    asset["issued_by_definer_only"] = json!(true);
+    asset["fixed_denominations"] = json!(true);
+    asset["denominations"] = json!([
+        {"denomination": 1, "count_coins": 1000},
+        {"denomination": 1000, "count_coins": 999}
+    ]);
+    assert!(validate(asset.clone()).is_ok());
+
+    asset["denominations"] = json!([{"denomination": 2}, {"denomination": 1}]);
+    assert!(validate(asset.clone()).is_err());
+
+    asset["denominations"] = json!([{"denomination": 1, "count_coins": 10}]);
+    assert!(validate(asset.clone()).is_err());
+
+    asset["denominations"] = json!([
+        {"denomination": 1, "count_coins": 1},
+        {"denomination": 4294967295u32, "count_coins": 9007199254740991i64}
+    ]);
+    assert!(validate(asset.clone()).is_err());
+
+    asset["fixed_denominations"] = json!(false);
+    asset.as_object_mut().unwrap().remove("denominations");
+    asset["attestors"] = json!(["YFAR4AK2RSRTAWZ3ILRFZOMN7M7QJTJ2"]);
+    assert!(validate(asset.clone()).is_err());
+}
+
+#[test]
+fn test_validate_poll() {
+    let mut poll = Poll {
+        question: "which color?".to_owned(),
+        choices: vec!["red".to_owned(), "blue".to_owned()],
+    };
+    assert!(validate_poll(&poll).is_ok());
+
+    poll.choices.push("red".to_owned());
+    match validate_poll(&poll) {
+        Err(ValidationError::UnitError { err }) => {
+            assert_eq!(err, "duplicate choice in poll: red")
+        }
+        ret => panic!("duplicate choices should be rejected, got {:?}", ret),
+    }
+}
+
+#[test]
+fn test_validate_attestation() {
+    let mut unit = Unit::default();
+    unit.authors.push(Author {
+        address: "YFAR4AK2RSRTAWZ3ILRFZOMN7M7QJTJ2".to_owned(),
+        authentifiers: HashMap::new(),
+        definition: Value::Null,
+    });
+    let mut attestation: Attestation = serde_json::from_value(json!({
+        "address": "YFAR4AK2RSRTAWZ3ILRFZOMN7M7QJTJ2",
+        "profile": {"email": "user@example.com"}
+    })).unwrap();
+    assert!(validate_attestation(&attestation, &unit).is_ok());
+
+    attestation.address = "NFAR4AK2RSRTAWZ3ILRFZOMN7M7QJTJ2".to_owned();
+    assert!(validate_attestation(&attestation, &unit).is_err());
+
+    // the profile must be an object
+    assert!(serde_json::from_value::<Attestation>(json!({
+        "address": "YFAR4AK2RSRTAWZ3ILRFZOMN7M7QJTJ2",
+        "profile": "user@example.com"
+    })).is_err());
+}
+
+#[test]
+fn test_validate_address_definition_change() {
+    let mut unit = Unit::default();
+    unit.authors.push(Author {
+        address: "YFAR4AK2RSRTAWZ3ILRFZOMN7M7QJTJ2".to_owned(),
+        authentifiers: HashMap::new(),
+        definition: Value::Null,
+    });
+    let mut change = AddressDefinitionChange {
+        address: Some("YFAR4AK2RSRTAWZ3ILRFZOMN7M7QJTJ2".to_owned()),
+        definition_chash: "JERTY5XNENMHYQW7NVBXUB5CU3IDODA3".to_owned(),
+    };
+    let mut validate_state = ValidationState::new();
+    match validate_address_definition_change(&change, &unit, &mut validate_state) {
+        Err(ValidationError::UnitError { err }) => {
+            assert_eq!(err, "when single-authored, must not indicate address")
+        }
+        ret => panic!(
+            "address in single-authored change should be rejected, got {:?}",
+            ret
+        ),
+    }
+
+    change.address = None;
+    assert!(validate_address_definition_change(&change, &unit, &mut validate_state).is_ok());
+    match validate_address_definition_change(&change, &unit, &mut validate_state) {
+        Err(ValidationError::UnitError { err }) => {
+            assert_eq!(err, "can be only one definition change per address")
+        }
+        ret => panic!("second definition change should be rejected, got {:?}", ret),
+    }
+}
+
+#[test]
+fn test_data_payload_must_be_object() {
+    use db;
+
+    let mut db = db::DB_POOL.get_connection();
+    let tx = db.transaction().unwrap();
+    let payload = json!(["not", "an", "object"]);
+    let message = Message {
+        app: "data".to_owned(),
+        payload_location: "inline".to_owned(),
+        payload_hash: object_hash::get_base64_hash(&payload).unwrap(),
+        payload: Some(Payload::Other(payload)),
+        ..Default::default()
+    };
+    let mut validate_state = ValidationState::new();
+    match validate_inline_payload(&tx, &message, 0, &Unit::default(), &mut validate_state) {
+        Err(ValidationError::UnitError { err }) => assert_eq!(err, "data payload must be object"),
+        ret => panic!(
+            "data payload that is not an object should be rejected, got {:?}",
+            ret
+        ),
+    }
+}
+
+#[test]
+fn test_validation_error_code_round_trip() {
+    let errors = vec![
+        ValidationError::UnitError {
+            err: "unknown app: foo".to_owned(),
+        },
+        ValidationError::BadSignature {
+            err: "authentifier verification failed".to_owned(),
+        },
+        ValidationError::DoubleSpend {
+            err: "input abc already used".to_owned(),
+        },
+        ValidationError::NeedParentUnits(vec!["parent1".to_owned(), "parent2".to_owned()]),
+    ];
+    for error in errors {
+        let restored = ValidationError::from_code(error.code(), error.detail());
+        assert_eq!(restored.code(), error.code());
+        assert_eq!(restored.detail(), error.detail());
+        assert_eq!(restored.is_unit_error(), error.is_unit_error());
+    }
+}
+
+#[test]
+fn test_definition_error() {
+    let is_bad_signature =
+        |err: &str| match ValidationError::from_definition_error(format_err!("{}", err)) {
+            ValidationError::BadSignature { .. } => true,
+            ValidationError::UnitError { .. } => false,
+            e => panic!("unexpected error {:?}", e),
+        };
+    assert!(is_bad_signature("authentifier verification failed"));
+    assert!(is_bad_signature("bad signature at path: \"r.0\""));
+    assert!(!is_bad_signature("some authentifiers are not used"));
+    assert!(!is_bad_signature("incompatible params"));
+    assert!(!is_bad_signature("each branch must have a signature"));
+}
+
+#[test]
+fn test_verify_joint_offline() {
+    let payload = Payload::Text("hello".to_owned());
+    let mut authentifiers = HashMap::new();
+    authentifiers.insert("r".to_owned(), "-".repeat(config::SIG_LENGTH));
+    let mut unit = Unit {
+        authors: vec![Author {
+            address: "YFAR4AK2RSRTAWZ3ILRFZOMN7M7QJTJ2".to_owned(),
+            authentifiers,
+            definition: json!(["sig", {"pubkey": "A0qTjB3ZjHf2yT1EIvLrkVAWY8MPSueNcB4GTlKGo/o6"}]),
+        }],
+        messages: vec![Message {
+            app: "text".to_owned(),
+            payload_location: "inline".to_owned(),
+            payload_hash: object_hash::get_base64_hash(&payload).unwrap(),
+            payload: Some(payload),
+            ..Default::default()
+        }],
+        parent_units: vec!["uPQs4TwLtDGRAdH8sbIJ1ZyWpEmwHWRAhXpamODZ7Kk=".to_owned()],
+        last_ball: Some("KLop9582tzXZJbytWjiWLcnpEdvJI7mUymbnUPXweOM=".to_owned()),
+        last_ball_unit: Some("uPQs4TwLtDGRAdH8sbIJ1ZyWpEmwHWRAhXpamODZ7Kk=".to_owned()),
+        witness_list_unit: Some("uPQs4TwLtDGRAdH8sbIJ1ZyWpEmwHWRAhXpamODZ7Kk=".to_owned()),
+        ..Default::default()
+    };
+    unit.headers_commission = Some(unit.get_header_size());
+    unit.payload_commission = Some(unit.get_payload_size());
+    unit.unit = Some(unit.get_unit_hash());
+    let mut joint = Joint {
+        ball: None,
+        skiplist_units: Vec::new(),
+        unsigned: Some(true),
+        unit,
+    };
+
+    // unsigned joints pass without checking the signatures
+    let report = verify_joint_offline(&joint, None, &[]).unwrap();
+    assert!(!report.is_ball_checked);
+    assert!(report.verified_authors.is_empty());
+    assert_eq!(report.unverified_authors.len(), 1);
+
+    // the inline definition may be a changed one, it is left to the dag
+    joint.unsigned = None;
+    let report = verify_joint_offline(&joint, None, &[]).unwrap();
+    assert!(report.verified_authors.is_empty());
+    assert_eq!(
+        report.unverified_authors,
+        vec!["YFAR4AK2RSRTAWZ3ILRFZOMN7M7QJTJ2".to_owned()]
+    );
+
+    joint.unit.last_ball = Some("jQGnkLnZlX2DjBUd8JKgHgw23zSdRL/Azx3foi/WqvE=".to_owned());
+    match verify_joint_offline(&joint, None, &[]) {
+        Err(ValidationError::JointError { err }) => assert!(err.starts_with("wrong unit hash")),
+        ret => panic!("tampered unit should be rejected, got {:?}", ret),
+    }
+}
+
+#[test]
+fn test_precheck() {
+    let payload = Payload::Text("hello".to_owned());
+    let mut authentifiers = HashMap::new();
+    authentifiers.insert("r".to_owned(), "-".repeat(config::SIG_LENGTH));
+    let mut unit = Unit {
+        authors: vec![Author {
+            address: "YFAR4AK2RSRTAWZ3ILRFZOMN7M7QJTJ2".to_owned(),
+            authentifiers,
+            definition: json!(["sig", {"pubkey": "A0qTjB3ZjHf2yT1EIvLrkVAWY8MPSueNcB4GTlKGo/o6"}]),
+        }],
+        messages: vec![Message {
+            app: "text".to_owned(),
+            payload_location: "inline".to_owned(),
+            payload_hash: object_hash::get_base64_hash(&payload).unwrap(),
+            payload: Some(payload),
+            ..Default::default()
+        }],
+        parent_units: vec!["uPQs4TwLtDGRAdH8sbIJ1ZyWpEmwHWRAhXpamODZ7Kk=".to_owned()],
+        last_ball: Some("KLop9582tzXZJbytWjiWLcnpEdvJI7mUymbnUPXweOM=".to_owned()),
+        last_ball_unit: Some("uPQs4TwLtDGRAdH8sbIJ1ZyWpEmwHWRAhXpamODZ7Kk=".to_owned()),
+        witness_list_unit: Some("uPQs4TwLtDGRAdH8sbIJ1ZyWpEmwHWRAhXpamODZ7Kk=".to_owned()),
+        ..Default::default()
+    };
+    unit.headers_commission = Some(unit.get_header_size());
+    unit.payload_commission = Some(unit.get_payload_size());
+    unit.unit = Some(unit.get_unit_hash());
+    let mut joint = Joint {
+        ball: None,
+        skiplist_units: Vec::new(),
+        unsigned: None,
+        unit,
+    };
+    // the definitions of the authors are not known in an empty db
+    let db = Connection::open_in_memory().unwrap();
+
+    // a bad signature is left to the definition evaluation
+    assert!(precheck(&db, &joint).is_ok());
+    joint.unit.authors[0].definition = Value::Null;
+    assert!(precheck(&db, &joint).is_ok());
+
+    // the inline payloads are hashed before the transaction
+    joint.unit.messages[0].payload = Some(Payload::Text("world".to_owned()));
+    match precheck(&db, &joint) {
+        Err(ValidationError::UnitError { err }) => assert!(err.starts_with("wrong payload hash")),
+        ret => panic!("tampered payload should be rejected, got {:?}", ret),
+    }
+}
+
+#[test]
+fn test_double_spend_of_pruned_input() {
+    let mut db = Connection::open_in_memory().unwrap();
+    db.execute_batch(
+        "CREATE TABLE units (unit CHAR(44) PRIMARY KEY, main_chain_index INT, \
+            is_stable TINYINT, sequence TEXT); \
+         CREATE TABLE inputs (unit CHAR(44), message_index TINYINT, input_index TINYINT, \
+            type TEXT, src_unit CHAR(44), src_message_index TINYINT, src_output_index TINYINT, \
+            asset CHAR(44), address CHAR(32), is_unique TINYINT); \
+         CREATE TABLE outputs (unit CHAR(44)); \
+         CREATE TABLE authentifiers (unit CHAR(44)); \
+         CREATE TABLE messages (unit CHAR(44), app VARCHAR(30), payload TEXT, payload_uri TEXT); \
+         CREATE TABLE pruned_units (unit CHAR(44) PRIMARY KEY, main_chain_index INT); \
+         INSERT INTO units VALUES ('source', 1, 1, 'good'), ('spender', 2, 1, 'good'); \
+         INSERT INTO inputs VALUES ('spender', 0, 0, 'transfer', 'source', 0, 0, NULL, \
+            'PAYER', 1);",
+    )
+    .unwrap();
+    // the source keeps its output as its first unit carries the definition
+    storage::prune_unit(&db, &"spender".to_owned(), 2).unwrap();
+
+    let tx = db.transaction().unwrap();
+    let unit = Unit {
+        unit: Some("double".to_owned()),
+        authors: vec![Author {
+            address: "PAYER".to_owned(),
+            authentifiers: HashMap::new(),
+            definition: Value::Null,
+        }],
+        parent_units: vec!["spender".to_owned()],
+        ..Default::default()
+    };
+    let mut validate_state = ValidationState::new();
+    validate_state.last_ball_mci = 2;
+    match check_input_double_spend(
+        &tx,
+        "type='transfer' AND src_unit='source' AND src_message_index=0 AND src_output_index=0",
+        None,
+        &unit,
+        &mut validate_state,
+        0,
+        0,
+    ) {
+        Err(ValidationError::DoubleSpend { .. }) => {}
+        ret => panic!("spending a pruned input again should fail, got {:?}", ret),
+    }
+}
@@ -1,11 +1,13 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 
-use composer::{self, ComposeInfo};
+use composer::{self, AssetPaymentInfo, ComposeInfo};
+use definition;
 use error::Result;
 use network::wallet::WalletConn;
+use object_hash;
 use rusqlite::Connection;
-use serde_json;
+use serde_json::{self, Value};
 use spec::Output;
 
 pub fn update_wallet_address(
@@ -38,6 +40,20 @@ pub fn update_wallet_address(
     Ok(())
 }
 
+/// save a definition that an address of the wallet changes to, the composer
+/// reveals it in the first unit of the address after the change is stable
+pub fn save_definition(db: &Connection, definition: &Value) -> Result<String> {
+    let definition_chash = object_hash::get_chash(definition)?;
+    let definition_json = serde_json::to_string(definition)?;
+    let has_references = definition::has_references(definition)? as u8;
+    let mut stmt = db.prepare_cached(
+        "INSERT OR IGNORE INTO definitions (definition_chash, definition, has_references) \
+         VALUES (?, ?, ?)",
+    )?;
+    stmt.execute(&[&definition_chash, &definition_json, &has_references])?;
+    Ok(definition_chash)
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TransactionHistory {
     pub amount: i64,
@@ -235,43 +251,69 @@ pub fn read_transaction_history(db: &Connection, address: &str) -> Result<Vec<Tr
 }
 
 // return values: first is unstable balance, second is stable balance.
-pub fn get_balance(db: &Connection, address: &str) -> Result<(i64, i64)> {
+// the base currency is queried when asset is None
+pub fn get_balance(db: &Connection, address: &str, asset: Option<&str>) -> Result<(i64, i64)> {
     let mut stmt = db.prepare_cached(
-        "SELECT asset, is_stable, SUM(amount) AS balance \
+        "SELECT is_stable, SUM(amount) AS balance \
          FROM outputs JOIN units USING(unit) \
-         WHERE is_spent=0 AND address=? AND sequence='good' AND asset IS NULL \
+         WHERE is_spent=0 AND address=? AND sequence='good' AND asset IS ? \
          GROUP BY is_stable",
     )?;
 
     let rows = stmt
-        .query_map(&[&address], |row| row.get(2))?
-        .collect::<::std::result::Result<Vec<i64>, _>>()?;
-
-    match rows.len() {
-        2 => return Ok((rows[0], rows[1])),
-        1 => return Ok((0, rows[0])),
-        _ => return Ok((0, 0)),
+        .query_map(&[&address, &asset], |row| (row.get::<_, bool>(0), row.get(1)))?
+        .collect::<::std::result::Result<Vec<(bool, i64)>, _>>()?;
+
+    let (mut unstable_balance, mut stable_balance) = (0, 0);
+    for (is_stable, balance) in rows {
+        if is_stable {
+            stable_balance = balance;
+        } else {
+            unstable_balance = balance;
+        }
     }
+    Ok((unstable_balance, stable_balance))
 }
 
+// the amounts are in TTT, or in the smallest units of the asset if it's given
 pub fn prepare_payment(
     ws: &Arc<WalletConn>,
     address_amount: &Vec<(&str, f64)>,
     text: Option<&str>,
     wallet_info_address: &str,
+    asset: Option<&str>,
 ) -> Result<ComposeInfo> {
+    let unit_amount = if asset.is_some() { 1.0 } else { 1_000_000.0 };
     let mut outputs = Vec::new();
     for (address, amount) in address_amount.into_iter() {
         outputs.push(Output {
             address: address.to_string(),
-            amount: (amount * 1_000_000.0).round() as i64,
+            amount: (amount * unit_amount).round() as i64,
+            blinding: None,
         });
     }
-    let amounts = outputs.iter().fold(0, |acc, x| acc + x.amount);
-    outputs.push(Output {
+    let change_output = Output {
         address: wallet_info_address.to_string(),
         amount: 0,
-    });
+        blinding: None,
+    };
+
+    // the base payment only pays the commissions of an asset payment
+    let (outputs, asset_payment) = match asset {
+        Some(asset) => {
+            outputs.push(change_output.clone());
+            let asset_payment = AssetPaymentInfo {
+                asset: asset.to_string(),
+                outputs,
+            };
+            (vec![change_output], Some(asset_payment))
+        }
+        None => {
+            outputs.push(change_output);
+            (outputs, None)
+        }
+    };
+    let amounts = outputs.iter().fold(0, |acc, x| acc + x.amount);
 
     let light_props = match ws.get_parents_and_last_ball_and_witness_list_unit() {
         Ok(res) => {
@@ -306,5 +348,6 @@ pub fn prepare_payment(
         witnesses: Vec::new(),
         inputs: Vec::new(),
         send_all: false, // FIXME: now send_all is always false
+        asset_payment,
     })
 }
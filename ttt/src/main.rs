@@ -12,6 +12,7 @@ extern crate fern;
 extern crate may;
 extern crate rusqlite;
 extern crate serde;
+#[macro_use]
 extern crate serde_json;
 extern crate trustnote;
 extern crate trustnote_wallet_base;
@@ -40,6 +41,9 @@ struct WalletInfo {
     _00_address: String,
     _00_address_pubk: ExtendedPubKey,
     _00_address_prvk: ExtendedPrivKey,
+    // the key of the definition in effect, none for the one the address is created with
+    key_index: Option<u32>,
+    signing_prvk: ExtendedPrivKey,
 }
 
 impl WalletInfo {
@@ -63,9 +67,52 @@ impl WalletInfo {
             wallet_0_id,
             _00_address,
             _00_address_pubk,
+            signing_prvk: _00_address_prvk,
             _00_address_prvk,
+            key_index: None,
         })
     }
+
+    // the keys the address changes its definition to are at /m/44'/0'/0'/1/index
+    fn rotated_key(&self, index: u32) -> Result<(ExtendedPubKey, ExtendedPrivKey)> {
+        let pubk = trustnote_wallet_base::wallet_address_pubkey(&self.wallet_pubk, true, index)?;
+        let prvk = trustnote_wallet_base::wallet_address_prvkey(&self.master_prvk, 0, true, index)?;
+        Ok((pubk, prvk))
+    }
+
+    // sign with the key of the definition that is stable now, the definition is
+    // saved again in case the database is created after the change
+    fn select_signing_key(&mut self, db: &Connection) -> Result<()> {
+        let definition_chash =
+            storage::read_definition_chash_by_address(db, &self._00_address, ::std::u32::MAX)?;
+        if definition_chash == self._00_address {
+            self.key_index = None;
+            self.signing_prvk = self._00_address_prvk;
+            return Ok(());
+        }
+
+        for index in 0..MAX_KEY_ROTATIONS {
+            let (pubk, prvk) = self.rotated_key(index)?;
+            let definition = sig_definition(&pubk);
+            if object_hash::get_chash(&definition)? == definition_chash {
+                wallet::save_definition(db, &definition)?;
+                self.key_index = Some(index);
+                self.signing_prvk = prvk;
+                return Ok(());
+            }
+        }
+        bail!(
+            "address {} is changed to unknown definition {}",
+            self._00_address,
+            definition_chash
+        );
+    }
+}
+
+const MAX_KEY_ROTATIONS: u32 = 1000;
+
+fn sig_definition(pubk: &ExtendedPubKey) -> serde_json::Value {
+    json!(["sig", { "pubkey": pubk.to_base64_key() }])
 }
 
 impl Signer for WalletInfo {
@@ -74,7 +121,7 @@ impl Signer for WalletInfo {
             bail!("invalid address for wallet to sign");
         }
 
-        trustnote_wallet_base::sign(hash, &self._00_address_prvk)
+        trustnote_wallet_base::sign(hash, &self.signing_prvk)
     }
 }
 
@@ -133,7 +180,8 @@ fn connect_to_remote(peers: &[String]) -> Result<Arc<WalletConn>> {
 
 fn info(db: &Connection, wallet_info: &WalletInfo) -> Result<()> {
     let address_pubk = wallet_info._00_address_pubk.to_base64_key();
-    let (unstable_balance, stable_balance) = wallet::get_balance(&db, &wallet_info._00_address)?;
+    let (unstable_balance, stable_balance) =
+        wallet::get_balance(&db, &wallet_info._00_address, None)?;
     let total = (unstable_balance + stable_balance) as f64 / 1000_000.0;
     let stable = stable_balance as f64 / 1000_000.0;
     let pending = unstable_balance as f64 / 1000_000.0;
@@ -144,6 +192,9 @@ fn info(db: &Connection, wallet_info: &WalletInfo) -> Result<()> {
     println!("└──wallet_id(0): {}", wallet_info.wallet_0_id);
     println!("   └──address(0/0): {}", wallet_info._00_address);
     println!("      ├── path: /m/44'/0'/0'/0/0");
+    if let Some(index) = wallet_info.key_index {
+        println!("      ├── signing key path: /m/44'/0'/0'/1/{}", index);
+    }
     println!("      ├── pubkey: {}", address_pubk);
     println!("      └── balance: {:.6}", total);
     println!("          ├── stable: {:.6}", stable);
@@ -180,13 +231,19 @@ fn check_witnesses(ws: &WalletConn, db: &db::Database) -> Result<()> {
     Ok(())
 }
 
-fn sync(ws: &WalletConn, db: &db::Database, wallet_info: &WalletInfo) -> Result<()> {
+fn sync(ws: &WalletConn, db: &mut db::Database, wallet_info: &mut WalletInfo) -> Result<()> {
     update_wallet_address(db, wallet_info)?;
     check_witnesses(ws, db)?;
+    // the units of the received private payments come with the history, the
+    // hub only hands them out for the challenge signed with the current key
+    wallet_info.select_signing_key(db)?;
+    ws.receive_private_payments(db, &*wallet_info)?;
     match ws.refresh_history(db) {
         Ok(_) => info!("refresh history done"),
         Err(e) => bail!("refresh history failed, err={:?}", e),
     }
+    private_payment::handle_saved_private_payments(db)?;
+    wallet_info.select_signing_key(db)?;
     Ok(())
 }
 
@@ -235,14 +292,34 @@ fn history_log(
 
 fn send_payment(
     ws: &Arc<WalletConn>,
-    db: &Connection,
+    db: &mut Connection,
     text: Option<&str>,
     address_amount: &Vec<(&str, f64)>,
+    asset: Option<&str>,
     wallet_info: &WalletInfo,
 ) -> Result<()> {
-    let payment = wallet::prepare_payment(ws, address_amount, text, &wallet_info._00_address)?;
-    let joint = composer::compose_joint(db, payment, wallet_info)?;
-    ws.post_joint(&joint)?;
+    let payment =
+        wallet::prepare_payment(ws, address_amount, text, &wallet_info._00_address, asset)?;
+    let (joint, private_elements) =
+        composer::compose_joint_with_private_payloads(db, payment, wallet_info)?;
+    if let Err(e) = ws.post_joint(&joint) {
+        if let Some(e) = e.downcast_ref::<validation::ValidationError>() {
+            bail!(
+                "payment rejected by hub, reason={}: {}",
+                e.code(),
+                e.detail()
+            );
+        }
+        return Err(e);
+    }
+
+    // the payees get the private payloads from the hub, and our own change is
+    // saved once the unit arrives with the next sync
+    for element in private_elements {
+        let chain = private_payment::build_private_chain(db, element)?;
+        ws.send_private_payment(&chain)?;
+        private_payment::handle_private_chain(db, &chain, ws.get_peer())?;
+    }
 
     println!("FROM  : {}", wallet_info._00_address);
     println!("TO    : ");
@@ -258,6 +335,168 @@ fn send_payment(
     Ok(())
 }
 
+// one json object of feeds per line, or the whole file
+fn parse_feeds(text: &str) -> Result<serde_json::Map<String, serde_json::Value>> {
+    let feeds = serde_json::from_str(text).context("feeds must be a json object")?;
+    Ok(feeds)
+}
+
+fn post_data_feed(
+    ws: &Arc<WalletConn>,
+    db: &mut db::Database,
+    feeds: &serde_json::Map<String, serde_json::Value>,
+    wallet_info: &mut WalletInfo,
+) -> Result<()> {
+    // pick up the change of the last feed unit
+    sync(ws, db, wallet_info)?;
+
+    let mut payment =
+        wallet::prepare_payment(ws, &Vec::new(), None, &wallet_info._00_address, None)?;
+    payment
+        .messages
+        .push(composer::create_data_feed_message(feeds)?);
+    let joint = composer::compose_joint(db, payment, &*wallet_info)?;
+    ws.post_joint(&joint)?;
+
+    println!(
+        "[{}] posted data feed unit {}: {}",
+        Local.timestamp_millis(time::now() as i64).naive_local(),
+        joint.unit.unit.unwrap(),
+        serde_json::to_string(feeds)?
+    );
+    Ok(())
+}
+
+// post the feeds read from the source every interval, a failed round is
+// logged and retried in the next round so that the oracle keeps running
+fn run_oracle(
+    ws: &Arc<WalletConn>,
+    db: &mut db::Database,
+    source: &str,
+    interval: u64,
+    wallet_info: &mut WalletInfo,
+) -> Result<()> {
+    use std::io::BufRead;
+
+    let stdin = ::std::io::stdin();
+    let mut lines = stdin.lock().lines();
+
+    loop {
+        let text = if source == "-" {
+            match lines.next() {
+                Some(line) => line?,
+                None => {
+                    info!("oracle source closed, exit");
+                    return Ok(());
+                }
+            }
+        } else {
+            match ::std::fs::read_to_string(source) {
+                Ok(text) => text,
+                Err(e) => {
+                    error!("failed to read feeds from {}, err={}", source, e);
+                    String::new()
+                }
+            }
+        };
+
+        if !text.trim().is_empty() {
+            let ret =
+                parse_feeds(&text).and_then(|feeds| post_data_feed(ws, db, &feeds, wallet_info));
+            if let Err(e) = ret {
+                error!("failed to post data feed, err={}", e);
+            }
+        }
+
+        ::std::thread::sleep(::std::time::Duration::from_secs(interval));
+    }
+}
+
+// change the definition of the address to the next rotated key, the address
+// stays the same and the new key signs once the change is stable
+fn rotate_key(ws: &Arc<WalletConn>, db: &Connection, wallet_info: &WalletInfo) -> Result<()> {
+    let mut stmt = db.prepare_cached(
+        "SELECT 1 FROM address_definition_changes CROSS JOIN units USING(unit) \
+         WHERE address=? AND is_stable=0 AND sequence='good'",
+    )?;
+    if stmt.exists(&[&wallet_info._00_address])? {
+        bail!("the last definition change is not stable yet");
+    }
+
+    let index = wallet_info.key_index.map_or(0, |index| index + 1);
+    if index >= MAX_KEY_ROTATIONS {
+        bail!("too many key rotations");
+    }
+    let (pubk, _) = wallet_info.rotated_key(index)?;
+    let definition_chash = wallet::save_definition(db, &sig_definition(&pubk))?;
+
+    let mut payment =
+        wallet::prepare_payment(ws, &Vec::new(), None, &wallet_info._00_address, None)?;
+    payment
+        .messages
+        .push(composer::create_address_definition_change_message(
+            None,
+            definition_chash.clone(),
+        )?);
+    let joint = composer::compose_joint(db, payment, wallet_info)?;
+    ws.post_joint(&joint)?;
+
+    println!("ADDRESS   : {}", wallet_info._00_address);
+    println!("UNIT      : {}", joint.unit.unit.unwrap());
+    println!("DEFINITION: {}", definition_chash);
+    println!("KEY PATH  : /m/44'/0'/0'/1/{}", index);
+    Ok(())
+}
+
+// the joint json may carry the balls of its parents and skiplist units
+// so that its ball can be checked too
+fn verify_joint(source: &str) -> Result<()> {
+    let text = if source == "-" {
+        use std::io::Read;
+        let mut text = String::new();
+        ::std::io::stdin().read_to_string(&mut text)?;
+        text
+    } else {
+        ::std::fs::read_to_string(source)?
+    };
+    let value: serde_json::Value = serde_json::from_str(&text).context("joint must be json")?;
+    let joint: joint::Joint = serde_json::from_value(value.clone()).context("not a joint")?;
+    let read_balls = |name: &str| -> Result<Option<Vec<String>>> {
+        match value.get(name) {
+            Some(balls) => Ok(Some(serde_json::from_value(balls.clone())?)),
+            None => Ok(None),
+        }
+    };
+    let parent_balls = read_balls("parent_balls")?;
+    let skiplist_balls = read_balls("skiplist_balls")?.unwrap_or_default();
+
+    let report = validation::verify_joint_offline(
+        &joint,
+        parent_balls.as_ref().map(|balls| balls.as_slice()),
+        &skiplist_balls,
+    )
+    .map_err(|e| format_err!("invalid joint, reason={}: {}", e.code(), e.detail()))?;
+
+    println!("UNIT      : {}", report.unit);
+    if joint.ball.is_some() {
+        if report.is_ball_checked {
+            println!("BALL      : ok");
+        } else {
+            println!("BALL      : not checked, parent_balls not given");
+        }
+    }
+    for address in &report.verified_authors {
+        println!("SIGNED    : {}", address);
+    }
+    for address in &report.unverified_authors {
+        println!(
+            "UNCHECKED : {}, definition not inline or depends on the dag",
+            address
+        );
+    }
+    Ok(())
+}
+
 fn main() -> Result<()> {
     let yml = load_yaml!("ttt.yml");
     let m = App::from_yaml(yml).get_matches();
@@ -278,12 +517,17 @@ fn main() -> Result<()> {
         return Ok(());
     }
 
+    // verify command needs neither the wallet nor the hub
+    if let Some(verify) = m.subcommand_matches("verify") {
+        return verify_joint(verify.value_of("JOINT").unwrap());
+    }
+
     let settings = config::get_settings();
-    let wallet_info = WalletInfo::from_mnemonic(&settings.mnemonic)?;
-    let db = db::DB_POOL.get_connection();
+    let mut wallet_info = WalletInfo::from_mnemonic(&settings.mnemonic)?;
+    let mut db = db::DB_POOL.get_connection();
     let ws = connect_to_remote(&settings.hub_url)?;
     // other commad would just sync data first
-    sync(&ws, &db, &wallet_info)?;
+    sync(&ws, &mut db, &mut wallet_info)?;
 
     //Info
     if let Some(_info) = m.subcommand_matches("info") {
@@ -311,6 +555,7 @@ fn main() -> Result<()> {
 
     //Send
     if let Some(send) = m.subcommand_matches("send") {
+        let asset = send.value_of("asset");
         let mut address_amount = Vec::new();
         if let Some(pay) = send.values_of("pay") {
             let v = pay.collect::<Vec<_>>();
@@ -318,7 +563,12 @@ fn main() -> Result<()> {
                 if !::object_hash::is_chash_valid(arg[0]) {
                     bail!("invalid address, please check");
                 }
-                let amount = arg[1].parse::<f64>().context("invalid amount arg")?;
+                // asset amounts have no display unit
+                let amount = if asset.is_some() {
+                    arg[1].parse::<u64>().context("invalid amount arg")? as f64
+                } else {
+                    arg[1].parse::<f64>().context("invalid amount arg")?
+                };
                 if amount > std::u64::MAX as f64 || amount < 0.000001 {
                     bail!("invalid amount, please check");
                 }
@@ -327,12 +577,39 @@ fn main() -> Result<()> {
         }
 
         let text = send.value_of("text");
-        return send_payment(&ws, &db, text, &address_amount, &wallet_info);
+        return send_payment(&ws, &mut db, text, &address_amount, asset, &wallet_info);
+    }
+
+    //Oracle
+    if let Some(oracle) = m.subcommand_matches("oracle") {
+        let source = oracle.value_of("SOURCE").unwrap();
+        let interval = value_t!(oracle.value_of("interval"), u64)?;
+        if interval == 0 {
+            bail!("invalid interval, please check");
+        }
+        return run_oracle(&ws, &mut db, source, interval, &mut wallet_info);
+    }
+
+    if let Some(_rotate) = m.subcommand_matches("rotate") {
+        return rotate_key(&ws, &db, &wallet_info);
     }
 
     if let Some(balance) = m.subcommand_matches("balance") {
+        let asset = balance.value_of("asset");
         let (unstable_balance, stable_balance) =
-            wallet::get_balance(&db, &wallet_info._00_address)?;
+            wallet::get_balance(&db, &wallet_info._00_address, asset)?;
+
+        // asset amounts have no display unit
+        if asset.is_some() {
+            if let Some(_s) = balance.values_of("s") {
+                println!("{}", stable_balance);
+            } else if let Some(_p) = balance.values_of("p") {
+                println!("{}", unstable_balance);
+            } else {
+                println!("{}", stable_balance + unstable_balance);
+            }
+            return Ok(());
+        }
 
         if let Some(_s) = balance.values_of("s") {
             println!("{:.6}", stable_balance as f64 / 1000_000.0);